@@ -0,0 +1,158 @@
+use std::{fs::Metadata, io::SeekFrom, path::Path, time::Duration};
+
+use log::info;
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncSeekExt, BufReader},
+};
+
+use super::{report, sshd::{Attempt, Attempts}, StateFile};
+use crate::payload::Reporter;
+use crate::shutdown::Shutdown;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Where reading stopped, saved as "<inode> <offset>".
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Position {
+    inode: u64,
+    offset: u64,
+}
+
+impl Position {
+    fn parse(state: &str) -> Option<Self> {
+        let (inode, offset) = state.split_once(' ')?;
+        Some(Self {
+            inode: inode.parse().ok()?,
+            offset: offset.parse().ok()?,
+        })
+    }
+
+    fn encode(&self) -> String {
+        format!("{} {}", self.inode, self.offset)
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> u64 {
+    0
+}
+
+/// Follows `path` like `tail -F`. Rotation is noticed by the inode changing
+/// and truncation by the file shrinking below the saved offset.
+pub async fn tail(path: &Path, state: &StateFile, reporter: &Reporter, mut shutdown: Shutdown) -> anyhow::Result<()> {
+    let mut attempts = Attempts::default();
+    let mut file = File::open(path).await?;
+    let metadata = file.metadata().await?;
+
+    let mut position = match state.load().as_deref().and_then(Position::parse) {
+        Some(saved) if saved.inode == inode(&metadata) && saved.offset <= metadata.len() => saved,
+        // rotated while we were down, the new file has not been read yet.
+        Some(_) => Position { inode: inode(&metadata), offset: 0 },
+        // first run, don't replay the whole history.
+        None => Position { inode: inode(&metadata), offset: metadata.len() },
+    };
+    let mut saved = None;
+
+    loop {
+        // anything left in the current file belongs before the rotation.
+        report(reporter, read_lines(&mut file, &mut position, &mut attempts).await?).await;
+        report(reporter, attempts.expired()).await;
+
+        if let Ok(current) = tokio::fs::metadata(path).await {
+            if inode(&current) != position.inode {
+                info!("{} was rotated, reopening.", path.display());
+                file = File::open(path).await?;
+                position = Position { inode: inode(&file.metadata().await?), offset: 0 };
+                continue;
+            }
+            if current.len() < position.offset {
+                info!("{} was truncated, reading from the start.", path.display());
+                position.offset = 0;
+            }
+        }
+
+        if saved != Some(position) {
+            state.save(&position.encode());
+            saved = Some(position);
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            _ = shutdown.recv() => return Ok(()),
+        }
+    }
+}
+
+/// Reads every complete line after `position` and moves it past them.
+/// A half written line is left for the next poll.
+async fn read_lines(file: &mut File, position: &mut Position, attempts: &mut Attempts) -> anyhow::Result<Vec<Attempt>> {
+    file.seek(SeekFrom::Start(position.offset)).await?;
+    let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, file);
+    let mut found = Vec::new();
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line).await?;
+        if line.pop() != Some(b'\n') {
+            return Ok(found);
+        }
+        position.offset += read as u64;
+        found.extend(attempts.line(&String::from_utf8_lossy(&line)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    const FAILED: &str = "Oct 18 10:00:00 host sshd[1]: Failed password for root from 203.0.113.7 port 4242 ssh2\n";
+
+    #[tokio::test]
+    async fn reads_complete_lines_from_the_saved_offset() {
+        let path = std::env::temp_dir().join(format!("authlog-{}", uuid::Uuid::new_v4()));
+        let mut writer = File::create(&path).await.unwrap();
+        let second = FAILED.replace("root", "admin");
+        writer.write_all(FAILED.as_bytes()).await.unwrap();
+        writer.write_all(second.as_bytes()).await.unwrap();
+        let end = (FAILED.len() + second.len()) as u64;
+
+        let mut file = File::open(&path).await.unwrap();
+        let mut attempts = Attempts::default();
+        // the first line was read before a restart.
+        let mut position = Position { inode: 0, offset: FAILED.len() as u64 };
+        let found = read_lines(&mut file, &mut position, &mut attempts).await.unwrap();
+        assert_eq!(found.iter().map(|a| a.username.as_str()).collect::<Vec<_>>(), ["admin"]);
+        assert_eq!(position.offset, end);
+
+        // half a line waits for the rest.
+        let (head, tail) = FAILED.split_at(20);
+        writer.write_all(head.as_bytes()).await.unwrap();
+        assert!(read_lines(&mut file, &mut position, &mut attempts).await.unwrap().is_empty());
+        assert_eq!(position.offset, end);
+
+        writer.write_all(tail.as_bytes()).await.unwrap();
+        let found = read_lines(&mut file, &mut position, &mut attempts).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(position.offset, end + FAILED.len() as u64);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        let position = Position { inode: 1234, offset: 5678 };
+        assert!(Position::parse(&position.encode()) == Some(position));
+        assert!(Position::parse("1234").is_none());
+        assert!(Position::parse("a b").is_none());
+    }
+}
@@ -0,0 +1,209 @@
+//////////////////////////////////////////////////////////////////////
+// https://systemd.io/JOURNAL_EXPORT_FORMATS/#journal-export-format //
+//////////////////////////////////////////////////////////////////////
+
+use std::{process::Stdio, time::Duration};
+
+use log::debug;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader},
+    process::Command,
+    sync::mpsc,
+};
+
+use super::{report, sshd::Attempts, StateFile};
+use crate::payload::Reporter;
+use crate::shutdown::Shutdown;
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest binary field that is read, sshd messages are far shorter. A
+/// corrupt length would otherwise be allocated as is.
+const MAX_FIELD_LENGTH: u64 = 64 * 1024;
+
+/// One journal entry, only the fields the collector needs.
+#[derive(Default)]
+struct Entry {
+    cursor: Option<String>,
+    message: Option<String>,
+}
+
+/// Follows sshd entries through `journalctl -o export`, resuming after
+/// the saved cursor.
+pub async fn follow(state: &StateFile, reporter: &Reporter, mut shutdown: Shutdown) -> anyhow::Result<()> {
+    let mut command = Command::new("journalctl");
+    command
+        .args(["--output=export", "--follow"])
+        // OpenSSH 9.8 logs as sshd-session, matches on one field are ORed.
+        .args(["SYSLOG_IDENTIFIER=sshd", "SYSLOG_IDENTIFIER=sshd-session"])
+        .stdout(Stdio::piped())
+        .kill_on_drop(true);
+    let mut cursor = state.load();
+    match &cursor {
+        Some(saved) => command.arg(format!("--after-cursor={}", saved)),
+        // first run, don't replay the whole history.
+        None => command.arg("--lines=0"),
+    };
+
+    let mut child = command.spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("journalctl has no stdout"))?;
+    // reading happens on its own task so the select below never drops half an entry.
+    let (sender, mut entries) = mpsc::channel(256);
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(stdout);
+        loop {
+            match read_entry(&mut reader).await {
+                Ok(Some(entry)) => {
+                    if sender.send(entry).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => return,
+                Err(e) => {
+                    debug!("Failed to read journal entry: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+    let mut attempts = Attempts::default();
+    let mut saved = cursor.clone();
+    let mut ticker = tokio::time::interval(SAVE_INTERVAL);
+
+    let result = loop {
+        tokio::select! {
+            entry = entries.recv() => {
+                let Some(entry) = entry else {
+                    break Err(anyhow::anyhow!("journalctl exited"));
+                };
+                if let Some(message) = entry.message {
+                    report(reporter, attempts.message(&message)).await;
+                }
+                if entry.cursor.is_some() {
+                    cursor = entry.cursor;
+                }
+            }
+            _ = ticker.tick() => {
+                report(reporter, attempts.expired()).await;
+                if saved != cursor {
+                    if let Some(cursor) = &cursor {
+                        state.save(cursor);
+                    }
+                    saved = cursor.clone();
+                }
+            }
+            _ = shutdown.recv() => break Ok(()),
+        }
+    };
+
+    if let Some(cursor) = &cursor {
+        state.save(cursor);
+    }
+    result
+}
+
+/// Reads fields until the blank line ending an entry. Returns `None` at EOF.
+async fn read_entry<R: AsyncBufRead + Unpin>(reader: &mut R) -> anyhow::Result<Option<Entry>> {
+    let mut entry = Entry::default();
+    let mut line = Vec::new();
+    let mut fields = 0;
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            return Ok(None);
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if line.is_empty() {
+            if fields == 0 {
+                continue;
+            }
+            return Ok(Some(entry));
+        }
+        fields += 1;
+
+        let (name, value) = match line.iter().position(|b| *b == b'=') {
+            Some(at) => (String::from_utf8_lossy(&line[..at]).into_owned(), line[at + 1..].to_vec()),
+            // binary field: name, little endian length, data, newline.
+            None => {
+                let name = String::from_utf8_lossy(&line).into_owned();
+                let length = reader.read_u64_le().await?;
+                if length > MAX_FIELD_LENGTH {
+                    return Err(anyhow::anyhow!("field {} is {} bytes, more than {}", name, length, MAX_FIELD_LENGTH));
+                }
+                let mut value = vec![0u8; length as usize];
+                reader.read_exact(&mut value).await?;
+                reader.read_u8().await?;
+                (name, value)
+            }
+        };
+
+        match name.as_str() {
+            "__CURSOR" => entry.cursor = Some(String::from_utf8_lossy(&value).into_owned()),
+            "MESSAGE" => entry.message = Some(String::from_utf8_lossy(&value).into_owned()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_text_and_binary_fields() {
+        let mut export = Vec::new();
+        export.extend_from_slice(b"__CURSOR=s=1;i=1\nSYSLOG_IDENTIFIER=sshd\nMESSAGE=Failed password for root from 203.0.113.7 port 1 ssh2\n\n");
+        // MESSAGE with a newline in it is sent as a binary field.
+        let message = b"Invalid user a\nb from 203.0.113.7";
+        export.extend_from_slice(b"__CURSOR=s=1;i=2\nMESSAGE\n");
+        export.extend_from_slice(&(message.len() as u64).to_le_bytes());
+        export.extend_from_slice(message);
+        export.extend_from_slice(b"\n\n");
+        let mut reader = &export[..];
+
+        let first = read_entry(&mut reader).await.unwrap().unwrap();
+        assert_eq!(first.cursor.as_deref(), Some("s=1;i=1"));
+        assert_eq!(first.message.as_deref(), Some("Failed password for root from 203.0.113.7 port 1 ssh2"));
+
+        let second = read_entry(&mut reader).await.unwrap().unwrap();
+        assert_eq!(second.cursor.as_deref(), Some("s=1;i=2"));
+        assert_eq!(second.message.as_deref(), Some("Invalid user a\nb from 203.0.113.7"));
+
+        assert!(read_entry(&mut reader).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn entries_without_a_message_keep_their_cursor() {
+        let mut reader = &b"\n__CURSOR=s=1;i=3\n_PID=1\n\n"[..];
+        let entry = read_entry(&mut reader).await.unwrap().unwrap();
+        assert_eq!(entry.cursor.as_deref(), Some("s=1;i=3"));
+        assert!(entry.message.is_none());
+    }
+
+    #[tokio::test]
+    async fn truncated_binary_fields_fail() {
+        let mut export = b"MESSAGE\n".to_vec();
+        export.extend_from_slice(&100u64.to_le_bytes());
+        export.extend_from_slice(b"short");
+        assert!(read_entry(&mut &export[..]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn oversized_binary_fields_fail() {
+        let mut export = b"MESSAGE\n".to_vec();
+        export.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(read_entry(&mut &export[..]).await.is_err());
+
+        let mut export = b"MESSAGE\n".to_vec();
+        export.extend_from_slice(&(MAX_FIELD_LENGTH + 1).to_le_bytes());
+        export.extend_from_slice(&vec![b'a'; MAX_FIELD_LENGTH as usize + 1]);
+        export.extend_from_slice(b"\n\n");
+        assert!(read_entry(&mut &export[..]).await.is_err());
+    }
+}
@@ -0,0 +1,123 @@
+////////////////
+// COLLECTOR //
+//////////////
+
+use std::{env, fs, path::PathBuf};
+
+use async_trait::async_trait;
+use log::{error, info};
+
+use crate::payload::{Payload, Reporter};
+use crate::protocol::{ssh, Protocol};
+use crate::shutdown::Shutdown;
+
+use self::sshd::Attempt;
+
+pub mod file;
+pub mod journald;
+mod sshd;
+
+const DEFAULT_LOG_PATHS: [&str; 2] = ["/var/log/auth.log", "/var/log/secure"];
+const DEFAULT_STATE_PATH: &str = "/var/lib/brute-daemon/authlog.state";
+
+pub enum Source {
+    File(PathBuf),
+    Journald,
+}
+
+/// Reads failed sshd logins from a real OpenSSH server instead of
+/// running a honeypot. Only usernames and addresses are available.
+pub struct AuthLog {
+    source: Source,
+    state: StateFile,
+}
+
+impl AuthLog {
+    /// Reads `AUTH_LOG_SOURCE` (`file` or `journald`), `AUTH_LOG_PATH`
+    /// and `AUTH_LOG_STATE`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let source = match env::var("AUTH_LOG_SOURCE").unwrap_or("file".to_string()).as_str() {
+            "file" => Source::File(match env::var("AUTH_LOG_PATH") {
+                Ok(path) => PathBuf::from(path),
+                Err(_) => DEFAULT_LOG_PATHS
+                    .iter()
+                    .map(PathBuf::from)
+                    .find(|path| path.exists())
+                    .unwrap_or(PathBuf::from(DEFAULT_LOG_PATHS[0])),
+            }),
+            "journald" => Source::Journald,
+            other => return Err(anyhow::anyhow!("unknown AUTH_LOG_SOURCE '{}'", other)),
+        };
+        let state = StateFile::new(env::var("AUTH_LOG_STATE").unwrap_or(DEFAULT_STATE_PATH.to_string()));
+        Ok(Self { source, state })
+    }
+}
+
+#[async_trait]
+impl Protocol for AuthLog {
+    fn name(&self) -> &'static str {
+        "AUTHLOG"
+    }
+
+    async fn bind(&mut self) -> anyhow::Result<()> {
+        if let Some(parent) = self.state.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if let Source::File(path) = &self.source {
+            if !path.exists() {
+                return Err(anyhow::anyhow!("{} does not exist", path.display()));
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(&mut self, reporter: Reporter, shutdown: Shutdown) -> anyhow::Result<()> {
+        match &self.source {
+            Source::File(path) => {
+                info!("Collecting sshd failures from {}", path.display());
+                file::tail(path, &self.state, &reporter, shutdown).await
+            }
+            Source::Journald => {
+                info!("Collecting sshd failures from journald");
+                journald::follow(&self.state, &reporter, shutdown).await
+            }
+        }
+    }
+}
+
+/// The failures come from a real sshd, so they're labeled like the SSH honeypot's.
+async fn report(reporter: &Reporter, attempts: Vec<Attempt>) {
+    for attempt in attempts {
+        let ip = attempt.ip.to_string();
+        if let Err(e) = reporter.report(Payload::new(&attempt.username, "", &ip, ssh::NAME)).await {
+            error!("Failed to report sshd failure from {}: {}", ip, e);
+        }
+    }
+}
+
+/// Remembers how far the log has been read across restarts.
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn load(&self) -> Option<String> {
+        fs::read_to_string(&self.path)
+            .ok()
+            .map(|state| state.trim().to_string())
+            .filter(|state| !state.is_empty())
+    }
+
+    /// Writes through a temporary file so a crash never leaves half a state.
+    pub fn save(&self, state: &str) {
+        let tmp = self.path.with_extension("tmp");
+        let result = fs::write(&tmp, state).and_then(|_| fs::rename(&tmp, &self.path));
+        if let Err(e) = result {
+            error!("Failed to save collector state to {}: {}", self.path.display(), e);
+        }
+    }
+}
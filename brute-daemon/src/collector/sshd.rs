@@ -0,0 +1,264 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/// How long an "Invalid user" line waits for its "Failed password" line
+/// before it is reported on its own (key-only clients never send one).
+const PENDING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A failed login recovered from the log. sshd never logs passwords.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Attempt {
+    pub username: String,
+    pub ip: IpAddr,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Event {
+    Failed { username: String, ip: IpAddr, port: u16 },
+    InvalidUser { username: String, ip: IpAddr, port: u16 },
+}
+
+/// Turns sshd messages into attempts. An invalid user usually logs both
+/// "Invalid user" and "Failed password" for the same connection, so the
+/// first is held back until the second shows up to avoid counting it twice.
+#[derive(Default)]
+pub struct Attempts {
+    pending: HashMap<(IpAddr, u16), (String, Instant)>,
+}
+
+impl Attempts {
+    /// Accepts a full syslog line, lines from other programs are ignored.
+    pub fn line(&mut self, line: &str) -> Vec<Attempt> {
+        match strip_syslog_prefix(line) {
+            Some(message) => self.message(message),
+            None => Vec::new(),
+        }
+    }
+
+    /// Accepts a bare journald MESSAGE, which is already sshd's own.
+    pub fn message(&mut self, message: &str) -> Vec<Attempt> {
+        let (message, repeated) = strip_repeated(message);
+
+        let mut attempts = Vec::new();
+        match parse(message) {
+            Some(Event::Failed { username, ip, port }) => {
+                self.pending.remove(&(ip, port));
+                for _ in 0..repeated {
+                    attempts.push(Attempt { username: username.clone(), ip });
+                }
+            }
+            Some(Event::InvalidUser { username, ip, port }) => {
+                self.pending.insert((ip, port), (username, Instant::now()));
+            }
+            None => {}
+        }
+        attempts
+    }
+
+    /// Invalid users that never got a "Failed" line.
+    pub fn expired(&mut self) -> Vec<Attempt> {
+        self.pending_longer_than(PENDING_TIMEOUT)
+    }
+
+    fn pending_longer_than(&mut self, timeout: Duration) -> Vec<Attempt> {
+        let mut attempts = Vec::new();
+        self.pending.retain(|(ip, _), (username, seen)| {
+            if seen.elapsed() < timeout {
+                return true;
+            }
+            attempts.push(Attempt { username: std::mem::take(username), ip: *ip });
+            false
+        });
+        attempts
+    }
+}
+
+/// "Oct 18 10:00:00 host sshd[123]: msg" -> "msg". Only the tag after the
+/// hostname is checked, lines from other programs are `None`.
+fn strip_syslog_prefix(line: &str) -> Option<&str> {
+    let rest = skip_timestamp(line)?;
+    let (_host, rest) = rest.split_once(' ')?;
+    let (tag, message) = rest.split_once(": ")?;
+    let program = match tag.strip_suffix(']') {
+        Some(tag) => tag.split_once('[')?.0,
+        None => tag,
+    };
+    // OpenSSH 9.8 logs as sshd-session.
+    matches!(program, "sshd" | "sshd-session").then_some(message)
+}
+
+/// Skips "Oct 18 10:00:00 " (days below 10 are padded with a space) or an
+/// RFC 3339 timestamp, which is a single word.
+fn skip_timestamp(line: &str) -> Option<&str> {
+    let (first, rest) = line.split_once(' ')?;
+    if first.len() != 3 || !first.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return Some(rest);
+    }
+    let (_day, rest) = rest.trim_start_matches(' ').split_once(' ')?;
+    let (_time, rest) = rest.split_once(' ')?;
+    Some(rest)
+}
+
+/// rsyslog folds duplicates into "message repeated 3 times: [ msg]".
+fn strip_repeated(message: &str) -> (&str, usize) {
+    let Some(rest) = message.strip_prefix("message repeated ") else {
+        return (message, 1);
+    };
+    let Some((count, rest)) = rest.split_once(" times: [ ") else {
+        return (message, 1);
+    };
+    match (count.parse(), rest.strip_suffix(']')) {
+        (Ok(count), Some(inner)) => (inner, count),
+        _ => (message, 1),
+    }
+}
+
+fn parse(message: &str) -> Option<Event> {
+    if let Some(rest) = message.strip_prefix("Failed ") {
+        let (method, rest) = rest.split_once(" for ")?;
+        if method != "password" && method != "keyboard-interactive/pam" {
+            return None;
+        }
+        let rest = rest.strip_prefix("invalid user ").unwrap_or(rest);
+        let (username, ip, port) = parse_origin(rest)?;
+        return Some(Event::Failed { username, ip, port });
+    }
+    if let Some(rest) = message.strip_prefix("Invalid user ") {
+        let (username, ip, port) = parse_origin(rest)?;
+        return Some(Event::InvalidUser { username, ip, port });
+    }
+    None
+}
+
+/// "root from 1.2.3.4 port 22 ssh2". Usernames may contain spaces so the
+/// last " from " is the separator.
+fn parse_origin(rest: &str) -> Option<(String, IpAddr, u16)> {
+    let (username, origin) = rest.rsplit_once(" from ")?;
+    if username.is_empty() {
+        return None;
+    }
+    let mut parts = origin.split(' ');
+    let ip = parts.next()?.parse().ok()?;
+    // older sshd versions leave out the port.
+    let port = match (parts.next(), parts.next()) {
+        (Some("port"), Some(port)) => port.parse().ok()?,
+        _ => 0,
+    };
+    Some((username.to_string(), ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempt(username: &str, ip: &str) -> Attempt {
+        Attempt { username: username.to_string(), ip: ip.parse().unwrap() }
+    }
+
+    #[test]
+    fn failed_passwords() {
+        let mut attempts = Attempts::default();
+        assert_eq!(
+            attempts.line("Oct 18 10:00:00 host sshd[123]: Failed password for root from 203.0.113.7 port 4242 ssh2"),
+            [attempt("root", "203.0.113.7")]
+        );
+        assert_eq!(
+            attempts.line("Oct 18 10:00:00 host sshd[123]: Failed keyboard-interactive/pam for admin from 2001:db8::7 port 4242 ssh2"),
+            [attempt("admin", "2001:db8::7")]
+        );
+        // journald MESSAGE fields have no syslog prefix.
+        assert_eq!(
+            attempts.message("Failed password for root from 203.0.113.7 port 4242 ssh2"),
+            [attempt("root", "203.0.113.7")]
+        );
+    }
+
+    #[test]
+    fn newer_sshd_logs_as_sshd_session() {
+        let mut attempts = Attempts::default();
+        assert_eq!(
+            attempts.line("2024-10-18T10:00:00+00:00 host sshd-session[99]: Failed password for root from 203.0.113.7 port 4242 ssh2"),
+            [attempt("root", "203.0.113.7")]
+        );
+    }
+
+    #[test]
+    fn only_the_tag_names_the_program() {
+        let mut attempts = Attempts::default();
+        assert_eq!(
+            attempts.message("Failed password for sshd from 203.0.113.7 port 4242 ssh2"),
+            [attempt("sshd", "203.0.113.7")]
+        );
+        attempts.message("Invalid user sshd_admin from 203.0.113.7 port 4243");
+        assert_eq!(attempts.pending_longer_than(Duration::ZERO), [attempt("sshd_admin", "203.0.113.7")]);
+        assert_eq!(
+            attempts.line("Oct  8 10:00:00 sshd-box sshd[1]: Failed password for sshd from 203.0.113.7 port 4242 ssh2"),
+            [attempt("sshd", "203.0.113.7")]
+        );
+        assert!(attempts
+            .line("Oct 18 10:00:00 sshd-box sudo[1]: Failed password for root from 203.0.113.7 port 4242 ssh2")
+            .is_empty());
+    }
+
+    #[test]
+    fn invalid_users_are_counted_once() {
+        let mut attempts = Attempts::default();
+        assert!(attempts.line("Oct 18 10:00:00 host sshd[1]: Invalid user oracle from 203.0.113.7 port 4242").is_empty());
+        assert_eq!(
+            attempts.line("Oct 18 10:00:01 host sshd[1]: Failed password for invalid user oracle from 203.0.113.7 port 4242 ssh2"),
+            [attempt("oracle", "203.0.113.7")]
+        );
+        assert!(attempts.pending_longer_than(Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn invalid_users_without_a_failure_are_reported_later() {
+        let mut attempts = Attempts::default();
+        attempts.line("Oct 18 10:00:00 host sshd[1]: Invalid user oracle from 203.0.113.7 port 4242");
+        assert!(attempts.expired().is_empty());
+        assert_eq!(attempts.pending_longer_than(Duration::ZERO), [attempt("oracle", "203.0.113.7")]);
+        assert!(attempts.pending_longer_than(Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn repeated_messages_count_every_repeat() {
+        let mut attempts = Attempts::default();
+        let found = attempts.line(
+            "Oct 18 10:00:00 host sshd[1]: message repeated 3 times: [ Failed password for root from 203.0.113.7 port 4242 ssh2]",
+        );
+        assert_eq!(found.len(), 3);
+    }
+
+    #[test]
+    fn odd_usernames_and_missing_ports() {
+        let mut attempts = Attempts::default();
+        assert_eq!(
+            attempts.message("Failed password for invalid user a from b from 203.0.113.7 port 4242 ssh2"),
+            [attempt("a from b", "203.0.113.7")]
+        );
+        assert_eq!(
+            attempts.message("Failed password for root from 203.0.113.7"),
+            [attempt("root", "203.0.113.7")]
+        );
+    }
+
+    #[test]
+    fn everything_else_is_ignored() {
+        let mut attempts = Attempts::default();
+        for line in [
+            "Oct 18 10:00:00 host sshd[1]: Accepted password for root from 203.0.113.7 port 4242 ssh2",
+            "Oct 18 10:00:00 host sshd[1]: Failed publickey for root from 203.0.113.7 port 4242 ssh2",
+            "Oct 18 10:00:00 host sshd[1]: Failed password for  from 203.0.113.7 port 4242 ssh2",
+            "Oct 18 10:00:00 host sshd[1]: Failed password for root from not-an-ip port 4242 ssh2",
+            "Oct 18 10:00:00 host sudo[1]: Failed password for root from 203.0.113.7 port 4242 ssh2",
+            "Oct 18 10:00:00 host sshdx[1]: Failed password for root from 203.0.113.7 port 4242 ssh2",
+            "Oct 18 10:00:00 host sshd[1]: message repeated x times: [ Failed password for root from 203.0.113.7 port 4242 ssh2]",
+            "",
+        ] {
+            assert!(attempts.line(line).is_empty(), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use std::{env, fmt::Display, str::FromStr};
+
+/// Parses `var`, falling back to `default` when it is unset.
+pub fn env_or<T: FromStr>(var: &str, default: T) -> anyhow::Result<T>
+where
+    T::Err: Display,
+{
+    match env::var(var) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid {}: {}", var, e)),
+        Err(_) => Ok(default),
+    }
+}
@@ -0,0 +1,87 @@
+use std::{env, net::IpAddr, str::FromStr};
+
+use ipnetwork::IpNetwork;
+
+/// Ranges ignored when `IGNORE_CIDRS` is not set, the ones brute-http
+/// drops by default so they aren't sent just to be rejected.
+pub const DEFAULT_IGNORE_CIDRS: &str = "10.0.0.0/8,172.16.0.0/12,192.168.0.0/16,127.0.0.0/8,::1/128,fc00::/7,fe80::/10";
+
+/// A list of IPv4/IPv6 networks used to match source addresses.
+#[derive(Clone, Debug, Default)]
+pub struct IpFilter {
+    networks: Vec<IpNetwork>,
+}
+
+impl IpFilter {
+    /// Parses a comma separated list of CIDRs. Bare addresses are treated as /32 or /128.
+    pub fn parse(list: &str) -> anyhow::Result<Self> {
+        let networks = list
+            .split(',')
+            .map(str::trim)
+            .filter(|cidr| !cidr.is_empty())
+            .map(|cidr| {
+                IpNetwork::from_str(cidr)
+                    .map_err(|e| anyhow::anyhow!("invalid CIDR '{}': {}", cidr, e))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { networks })
+    }
+
+    /// Reads the list from `var`, falling back to `default` when it is unset.
+    pub fn from_env(var: &str, default: &str) -> anyhow::Result<Self> {
+        Self::parse(&env::var(var).unwrap_or(default.to_string()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4-mapped IPv6 addresses should match IPv4 ranges.
+        let ip = ip.to_canonical();
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_addresses_inside_any_network() {
+        let filter = IpFilter::parse("10.0.0.0/8, 2001:db8::/32").unwrap();
+        assert!(filter.contains("10.1.2.3".parse().unwrap()));
+        assert!(filter.contains("2001:db8::1".parse().unwrap()));
+        assert!(!filter.contains("11.0.0.1".parse().unwrap()));
+        assert!(!filter.contains("2001:db9::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn bare_addresses_match_only_themselves() {
+        let filter = IpFilter::parse("192.0.2.7,::1").unwrap();
+        assert!(filter.contains("192.0.2.7".parse().unwrap()));
+        assert!(!filter.contains("192.0.2.8".parse().unwrap()));
+        assert!(filter.contains("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_match_ipv4_networks() {
+        let filter = IpFilter::parse(DEFAULT_IGNORE_CIDRS).unwrap();
+        assert!(filter.contains("::ffff:127.0.0.1".parse().unwrap()));
+        assert!(filter.contains("::ffff:192.168.1.1".parse().unwrap()));
+        assert!(!filter.contains("::ffff:192.0.2.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn empty_entries_are_skipped() {
+        assert!(IpFilter::parse("").unwrap().is_empty());
+        assert!(IpFilter::parse(" , ,").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_networks_are_rejected() {
+        let error = IpFilter::parse("10.0.0.0/8,10.0.0.0/33").unwrap_err();
+        assert!(error.to_string().contains("10.0.0.0/33"));
+        assert!(IpFilter::parse("not-a-network").is_err());
+    }
+}
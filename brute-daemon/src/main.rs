@@ -1,9 +1,23 @@
-use log::LevelFilter;
-use protocol::ftp::start_ftp_server;
-use protocol::ssh::start_ssh_server;
+use std::{env, time::Duration};
 
+use log::{info, warn, LevelFilter};
+use tokio::time::{timeout_at, Instant};
+use collector::AuthLog;
+use payload::Reporter;
+use protocol::ftp::Ftp;
+use protocol::ssh::Ssh;
+use protocol::ProtocolRegistry;
+use tarpit::Tarpit;
+
+mod collector;
+mod config;
+mod filter;
 mod protocol;
 mod payload;
+mod proxy;
+mod shutdown;
+mod signing;
+mod tarpit;
 
 //////////////////////////
 // SUPPORTED PROTOCOLS //
@@ -22,13 +36,57 @@ async fn main() -> anyhow::Result<()> {
 
     #[cfg(debug_assertions)]
     dotenvy::dotenv().unwrap();
-    
-    let (ssh, ftp) = tokio::join!(
-        start_ssh_server(),
-        start_ftp_server()
-    );
-
-    ssh.unwrap();
-    ftp.unwrap();
+
+    let reporter = Reporter::from_env()?;
+    let (trigger, shutdown) = shutdown::channel();
+    let shutdown_timeout = Duration::from_secs(config::env_or("SHUTDOWN_TIMEOUT", 30)?);
+
+    // honeypot runs the fake listeners, collector reads a real sshd's logs.
+    let mode = env::var("DAEMON_MODE").unwrap_or("honeypot".to_string());
+    let (honeypot, collector) = match mode.as_str() {
+        "honeypot" => (true, false),
+        "collector" => (false, true),
+        "both" => (true, true),
+        other => return Err(anyhow::anyhow!("unknown DAEMON_MODE '{}'", other)),
+    };
+
+    let mut registry = ProtocolRegistry::default();
+    if honeypot {
+        let tarpit = Tarpit::from_env()?;
+        if tarpit.is_enabled() {
+            tokio::spawn(tarpit.clone().report_stats(Duration::from_secs(60)));
+        }
+        registry
+            .register(Ssh::new(tarpit.clone())?)
+            .register(Ftp::new(tarpit)?);
+    }
+    if collector {
+        registry.register(AuthLog::from_env()?);
+    }
+    // lets the PAM module and other local agents report without HTTP.
+    #[cfg(unix)]
+    if config::env_or("LOCAL_SOCKET", false)? {
+        registry.register(protocol::local::Local::from_env()?);
+    }
+
+    let run = registry.run(reporter.clone(), shutdown);
+    tokio::pin!(run);
+    tokio::select! {
+        _ = &mut run => {}
+        _ = shutdown::signal() => {}
+    }
+
+    ///////////////
+    // SHUTDOWN //
+    /////////////
+    info!("Shutting down, waiting up to {:?} for listeners and queued reports.", shutdown_timeout);
+    let _ = trigger.send(true);
+    let deadline = Instant::now() + shutdown_timeout;
+    if timeout_at(deadline, &mut run).await.is_err() {
+        warn!("Listeners did not stop before the shutdown deadline.");
+    }
+    if timeout_at(deadline, reporter.flush()).await.is_err() {
+        warn!("Some queued reports were not delivered before the shutdown deadline.");
+    }
     Ok(())
 }
@@ -1,9 +1,27 @@
-use std::env;
+use std::{
+    env,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
-use reqwest::Client;
-use serde::Serialize;
+use log::{debug, error, info, warn};
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    Notify,
+};
+use tokio::task::JoinHandle;
 
-#[derive(Serialize)]
+use crate::config::env_or;
+use crate::filter::{IpFilter, DEFAULT_IGNORE_CIDRS};
+use crate::signing::Signer;
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Payload {
     username: String,
     password: String,
@@ -11,53 +29,400 @@ pub struct Payload {
     protocol: String,
 }
 
-pub struct PayloadConfig {
-    url: String,
-    bearer_token: String,
+impl Payload {
+    pub fn new(username: &str, password: &str, ip_address: &str, protocol: &str) -> Payload {
+        Payload {
+            username: String::from(username),
+            password: String::from(password),
+            ip_address: String::from(ip_address),
+            protocol: String::from(protocol),
+        }
+    }
 }
 
-impl Payload {
-    pub fn new(
-        username: String,
-        password: String,
-        ip_address: String,
-        protocol: String,
-        url: String,
-        bearer_token: String,
-    ) -> anyhow::Result<(Payload, PayloadConfig)> {
-        //let client = Client::new();
-        let payload = Payload {
-            username: username,
-            password: password,
-            ip_address: ip_address,
-            protocol: protocol,
+///////////////
+// REPORTER //
+/////////////
+
+const DEFAULT_QUEUE_SIZE: usize = 10_000;
+const DEFAULT_BATCH_SIZE: usize = 25;
+const DEFAULT_MAX_RETRIES: u32 = 8;
+/// How long the first attempt of a batch waits for company.
+const BATCH_WINDOW: Duration = Duration::from_millis(500);
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Where and how attempts are delivered to brute-http.
+#[derive(Clone, Debug)]
+pub struct DeliveryConfig {
+    pub url: String,
+    /// `/brute/attack/add/batch`, attempts are sent one by one without it.
+    pub batch_url: Option<String>,
+    /// Sent when there is no `signer`.
+    pub bearer_token: Option<String>,
+    /// Signs every request instead of sending the bearer token.
+    pub signer: Option<Signer>,
+    pub queue_size: usize,
+    pub batch_size: usize,
+    pub max_retries: u32,
+}
+
+/// Shared handle every protocol uses to send attempts to brute-http.
+/// Attempts are queued and delivered in the background, so reporting
+/// never waits on the network.
+#[derive(Clone, Debug)]
+pub struct Reporter {
+    queue: mpsc::Sender<Payload>,
+    url: Arc<str>,
+    ignore: Arc<IpFilter>,
+    delivery: Arc<DeliveryHandle>,
+}
+
+#[derive(Debug)]
+struct DeliveryHandle {
+    close: Notify,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Reporter {
+    /// Starts the delivery task. Must be called inside the runtime.
+    pub fn new(config: DeliveryConfig, ignore: IpFilter) -> Self {
+        let (queue, receiver) = mpsc::channel(config.queue_size);
+        let url = Arc::from(config.url.as_str());
+        let delivery = Arc::new(DeliveryHandle {
+            close: Notify::new(),
+            task: Mutex::new(None),
+        });
+        let task = tokio::spawn(Delivery::new(config).run(receiver, delivery.clone()));
+        *delivery.task.lock().unwrap() = Some(task);
+        Reporter {
+            queue,
+            url,
+            ignore: Arc::new(ignore),
+            delivery,
+        }
+    }
+
+    /// Builds a reporter from `ADD_ATTACK_ENDPOINT`, `BEARER_TOKEN` or
+    /// `SENSOR_ID`/`SENSOR_SECRET`, `IGNORE_CIDRS` and the optional
+    /// `ADD_ATTACK_BATCH_ENDPOINT` and `REPORT_*` settings.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let signer = Signer::from_env();
+        let bearer_token = env::var("BEARER_TOKEN").ok();
+        if signer.is_none() && bearer_token.is_none() {
+            return Err(anyhow::anyhow!("either BEARER_TOKEN or SENSOR_ID and SENSOR_SECRET must be set"));
+        }
+        let config = DeliveryConfig {
+            url: env::var("ADD_ATTACK_ENDPOINT")?,
+            batch_url: env::var("ADD_ATTACK_BATCH_ENDPOINT").ok(),
+            bearer_token,
+            signer,
+            queue_size: env_or("REPORT_QUEUE_SIZE", DEFAULT_QUEUE_SIZE)?,
+            batch_size: env_or("REPORT_BATCH_SIZE", DEFAULT_BATCH_SIZE)?.max(1),
+            max_retries: env_or("REPORT_MAX_RETRIES", DEFAULT_MAX_RETRIES)?,
         };
+        let ignore = IpFilter::from_env("IGNORE_CIDRS", DEFAULT_IGNORE_CIDRS)?;
+        Ok(Self::new(config, ignore))
+    }
+
+    /// Returns true if attempts from `ip` should never be reported.
+    pub fn is_ignored(&self, ip: IpAddr) -> bool {
+        self.ignore.contains(ip)
+    }
+
+    /// Queues an attempt. Fails only when the queue is full or closed.
+    pub async fn report(&self, payload: Payload) -> anyhow::Result<()> {
+        if let Ok(ip) = payload.ip_address.parse::<IpAddr>() {
+            if self.is_ignored(ip) {
+                debug!("Not reporting {} attempt from ignored address {}", payload.protocol, ip);
+                return Ok(());
+            }
+        }
+        info!(
+            "Recieved an {} auth request from {} sending to {}",
+            payload.protocol, payload.ip_address, self.url
+        );
+        // dropping new attempts beats blocking the listeners while brute-http is down.
+        match self.queue.try_send(payload) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(anyhow::anyhow!("report queue is full")),
+            Err(TrySendError::Closed(_)) => Err(anyhow::anyhow!("report queue is closed")),
+        }
+    }
+
+    /// Stops accepting new attempts and waits until everything already
+    /// queued has been delivered (or given up on). Wrap it in a timeout.
+    pub async fn flush(&self) {
+        self.delivery.close.notify_one();
+        let task = self.delivery.task.lock().unwrap().take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+    }
+}
+
+///////////////
+// DELIVERY //
+/////////////
+
+struct Delivery {
+    client: Client,
+    /// Starts at `config.batch_size` and shrinks when brute-http says a
+    /// batch was too large.
+    batch_size: AtomicUsize,
+    config: DeliveryConfig,
+}
+
+/// How a request ended once the retries are over.
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Delivered,
+    /// A 400 or 413 for more than one attempt, smaller batches may pass.
+    TooLarge,
+    Dropped,
+}
+
+impl Delivery {
+    fn new(config: DeliveryConfig) -> Self {
+        Self {
+            client: Client::new(),
+            batch_size: AtomicUsize::new(config.batch_size),
+            config,
+        }
+    }
+
+    /// Sends queued attempts until the queue is closed and empty.
+    async fn run(self, mut receiver: mpsc::Receiver<Payload>, handle: Arc<DeliveryHandle>) {
+        let mut closing = false;
+        loop {
+            let next = if closing {
+                receiver.recv().await
+            } else {
+                tokio::select! {
+                    payload = receiver.recv() => payload,
+                    _ = handle.close.notified() => {
+                        // whatever is already queued is still received.
+                        closing = true;
+                        receiver.close();
+                        receiver.recv().await
+                    }
+                }
+            };
+            let Some(first) = next else {
+                break;
+            };
+            let mut batch = vec![first];
+            let window = tokio::time::sleep(BATCH_WINDOW);
+            tokio::pin!(window);
+            while batch.len() < self.batch_size.load(Ordering::Relaxed) {
+                tokio::select! {
+                    payload = receiver.recv() => match payload {
+                        Some(payload) => batch.push(payload),
+                        None => break,
+                    },
+                    _ = &mut window => break,
+                }
+            }
+
+            match &self.config.batch_url {
+                Some(batch_url) => self.deliver_batch(batch_url, &batch).await,
+                None => {
+                    for payload in &batch {
+                        self.deliver(&self.config.url, payload, 1).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends `batch`, splitting it in halves for as long as brute-http
+    /// rejects it as too large (over its `MAX_BATCH_SIZE`).
+    async fn deliver_batch(&self, url: &str, batch: &[Payload]) {
+        let mut chunks = vec![batch];
+        while let Some(chunk) = chunks.pop() {
+            let limit = self.batch_size.load(Ordering::Relaxed);
+            if chunk.len() > limit {
+                let (first, second) = chunk.split_at(limit);
+                chunks.push(second);
+                chunks.push(first);
+                continue;
+            }
+            if self.deliver(url, chunk, chunk.len()).await != Outcome::TooLarge {
+                continue;
+            }
+            let half = chunk.len().div_ceil(2);
+            let previous = self.batch_size.fetch_min(half, Ordering::Relaxed);
+            if half < previous {
+                warn!("brute-http rejected a batch of {}, sending at most {} at a time from now on.", chunk.len(), half);
+            }
+            let (first, second) = chunk.split_at(half);
+            chunks.push(second);
+            chunks.push(first);
+        }
+    }
 
-        let config = PayloadConfig {
-            url: String::from(url),
-            bearer_token: String::from(bearer_token),
+    /// Posts `body`, retrying with backoff on network and server errors.
+    async fn deliver<T: Serialize + ?Sized>(&self, url: &str, body: &T, count: usize) -> Outcome {
+        let (body, path) = match (serde_json::to_vec(body), Url::parse(url)) {
+            (Ok(body), Ok(url)) => {
+                let path = match url.query() {
+                    Some(query) => format!("{}?{}", url.path(), query),
+                    None => url.path().to_string(),
+                };
+                (body, path)
+            }
+            (Err(e), _) => {
+                error!("Failed to encode {} attempt(s): {}", count, e);
+                return Outcome::Dropped;
+            }
+            (_, Err(e)) => {
+                error!("Invalid endpoint {}: {}", url, e);
+                return Outcome::Dropped;
+            }
         };
-        Ok((payload, config))
+
+        let mut delay = INITIAL_RETRY_DELAY;
+        for attempt in 0..=self.config.max_retries {
+            let mut request = self
+                .client
+                .post(url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            request = match (&self.config.signer, &self.config.bearer_token) {
+                (Some(signer), _) => signer
+                    .sign("POST", &path, &body)
+                    .into_iter()
+                    .fold(request, |request, (name, value)| request.header(name, value)),
+                (None, Some(token)) => request.bearer_auth(token),
+                (None, None) => request,
+            };
+            let result = request.send().await;
+
+            match result {
+                Ok(response) if response.status().is_success() => return Outcome::Delivered,
+                Ok(response)
+                    if count > 1
+                        && matches!(response.status(), StatusCode::BAD_REQUEST | StatusCode::PAYLOAD_TOO_LARGE) =>
+                {
+                    return Outcome::TooLarge;
+                }
+                // brute-http rejected it, sending it again won't help.
+                Ok(response) if response.status().is_client_error() && response.status() != StatusCode::TOO_MANY_REQUESTS => {
+                    error!("brute-http rejected {} attempt(s): {}", count, response.status());
+                    return Outcome::Dropped;
+                }
+                Ok(response) => warn!("brute-http answered {} for {} attempt(s).", response.status(), count),
+                Err(e) => warn!("Failed to send {} attempt(s) to {}: {}", count, url, e),
+            }
+
+            if attempt < self.config.max_retries {
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+        error!("Dropping {} attempt(s) after {} retries.", count, self.config.max_retries);
+        Outcome::Dropped
     }
+}
 
-    pub async fn post(username: &str, password: &str, ip_address: &str, protocol: &str) -> anyhow::Result<()> {
-        let url = env::var("ADD_ATTACK_ENDPOINT")?;
-        let bearer_token = env::var("BEARER_TOKEN")?;
-        let payload = Self::new(
-            String::from(username),
-            String::from(password),
-            String::from(ip_address),
-            String::from(protocol),
-            url,
-            bearer_token,
-        )?;
-        Self::create_post(payload.0, payload.1).await?;
-        Ok(())
-    }
-    
-    async fn create_post(payload: Payload, config: PayloadConfig) -> anyhow::Result<()> {
-        let client = Client::new();
-        client.post(&config.url).bearer_auth(&config.bearer_token).json(&payload).send().await?;
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Answers every POST with a 400 when the JSON array in it is longer
+    /// than `max`, like brute-http's batch endpoint. Returns the base url
+    /// and the length of every array it received.
+    async fn brute_http(max: usize) -> (String, Arc<Mutex<Vec<usize>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/brute/attack/add/batch", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                let (head, length) = loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        let head = String::from_utf8_lossy(&request[..end]).to_lowercase();
+                        let length: usize = head
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length: "))
+                            .unwrap()
+                            .parse()
+                            .unwrap();
+                        break (end + 4, length);
+                    }
+                };
+                while request.len() < head + length {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let batch: Vec<Payload> = serde_json::from_slice(&request[head..head + length]).unwrap();
+                log.lock().unwrap().push(batch.len());
+                let status = if batch.len() > max { "400 Bad Request" } else { "200 OK" };
+                let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn delivery(url: &str, batch_size: usize) -> Delivery {
+        Delivery::new(DeliveryConfig {
+            url: url.to_string(),
+            batch_url: Some(url.to_string()),
+            bearer_token: Some("token".to_string()),
+            signer: None,
+            queue_size: 100,
+            batch_size,
+            max_retries: 0,
+        })
+    }
+
+    fn payloads(count: usize) -> Vec<Payload> {
+        (0..count)
+            .map(|i| Payload::new(&format!("user{}", i), "password", "203.0.113.7", "SSH"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn oversized_batches_are_split_until_they_fit() {
+        let (url, received) = brute_http(3).await;
+        let delivery = delivery(&url, 10);
+
+        delivery.deliver_batch(&url, &payloads(10)).await;
+        assert_eq!(*received.lock().unwrap(), [10, 5, 3, 2, 3, 2]);
+        assert_eq!(delivery.batch_size.load(Ordering::Relaxed), 3);
+
+        // later batches start at the size that worked.
+        received.lock().unwrap().clear();
+        delivery.deliver_batch(&url, &payloads(7)).await;
+        assert_eq!(*received.lock().unwrap(), [3, 3, 1]);
+    }
+
+    #[tokio::test]
+    async fn batches_that_fit_are_sent_whole() {
+        let (url, received) = brute_http(100).await;
+        let delivery = delivery(&url, 25);
+        delivery.deliver_batch(&url, &payloads(25)).await;
+        assert_eq!(*received.lock().unwrap(), [25]);
+        assert_eq!(delivery.batch_size.load(Ordering::Relaxed), 25);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_single_attempt_is_dropped() {
+        let (url, received) = brute_http(0).await;
+        let delivery = delivery(&url, 25);
+        assert_eq!(delivery.deliver(&url, &payloads(1), 1).await, Outcome::Dropped);
+        delivery.deliver_batch(&url, &payloads(2)).await;
+        assert_eq!(*received.lock().unwrap(), [1, 2, 1, 1]);
     }
 }
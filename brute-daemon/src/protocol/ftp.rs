@@ -2,32 +2,57 @@
 // FTP //
 ////////
 
-use std::{env, fs};
+use std::{env, fs, io, time::Duration};
+use std::future::{poll_fn, Future};
+use std::net::{Ipv4Addr, SocketAddr};
 use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
 
+use async_trait::async_trait;
 use libunftp::auth::*;
 use libunftp::auth::{AuthenticationError, Authenticator};
-use log::info;
+use libunftp::ServerError;
+use log::{debug, error, info, warn};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use unftp_sbe_fs::Filesystem;
 
-use crate::payload;
+use crate::payload::{Payload, Reporter};
+use crate::protocol::Protocol;
+use crate::proxy::{self, ProxyConfig};
+use crate::shutdown::Shutdown;
+use crate::tarpit::Tarpit;
 
 #[derive(Debug)]
-pub struct BruteAuthenticator;
+pub struct BruteAuthenticator {
+    reporter: Reporter,
+    tarpit: Tarpit,
+    protocol: &'static str,
+}
 
-#[async_trait::async_trait]
+#[async_trait]
 impl Authenticator<DefaultUser> for BruteAuthenticator {
     async fn authenticate(&self, username: &str, creds: &Credentials) -> Result<DefaultUser, AuthenticationError> {
-        let endpoint = env::var("ADD_ATTACK_ENDPOINT").unwrap();
         let ip = creds.source_ip.to_string();
 
-        if !username.is_empty() && creds.password.is_some() && !ip.eq_ignore_ascii_case("127.0.0.1") {
-            info!("Recieved an auth request sending to {}", endpoint);
-            payload::Payload::post(&username, &<std::option::Option<std::string::String> as Clone>::clone(&creds.password).unwrap(), &ip, "FTP").await.unwrap();
+        if let Some(password) = &creds.password {
+            if !username.is_empty() {
+                if let Err(e) = self.reporter.report(Payload::new(username, password, &ip, self.protocol)).await {
+                    error!("Failed to report {} attempt from {}: {}", self.protocol, ip, e);
+                }
+            }
         }
+        let delay = self.tarpit.record_failure(creds.source_ip);
+        self.tarpit.hold(delay).await;
         Err(AuthenticationError::BadUser)
     }
 }
 
+/// How long open FTP sessions get to finish once shutdown starts.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
 fn get_ftp_path() -> &'static str {
     #[cfg(target_os = "windows")]
     {
@@ -40,17 +65,187 @@ fn get_ftp_path() -> &'static str {
     }
 }
 
-pub async fn start_ftp_server() -> anyhow::Result<()> {
-    let path = Path::new(get_ftp_path());
-    if !path.exists() {
-        fs::create_dir_all(path).expect("Failed to create directory");
+///////////////
+// PROTOCOL //
+/////////////
+
+type FtpServer = libunftp::Server<Filesystem, DefaultUser>;
+type Listening = Pin<Box<dyn Future<Output = Result<(), ServerError>> + Send>>;
+
+/// How many loopback ports libunftp gets to try behind the relay.
+const UPSTREAM_ATTEMPTS: usize = 5;
+
+pub struct Ftp {
+    address: String,
+    proxy: Arc<ProxyConfig>,
+    tarpit: Tarpit,
+    listener: Option<TcpListener>,
+}
+
+impl Ftp {
+    pub fn new(tarpit: Tarpit) -> anyhow::Result<Self> {
+        Ok(Self {
+            address: env::var("FTP_LISTEN_ADDRESS").unwrap_or("0.0.0.0:21".to_string()),
+            proxy: Arc::new(ProxyConfig::from_env("FTP")?),
+            tarpit,
+            listener: None,
+        })
+    }
+}
+
+/// A loopback port nothing listens on. libunftp trusts every PROXY header
+/// it gets, so it must not be reachable from outside and isn't configurable.
+fn free_loopback_address() -> anyhow::Result<SocketAddr> {
+    let probe = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    Ok(probe.local_addr()?)
+}
+
+/// Starts libunftp on a port from `pick`. libunftp binds its own socket,
+/// so another process may take a picked port first: its bind then fails
+/// and the next port is tried. The bind happens on the first poll, once
+/// that is pending the port is libunftp's and the relay may connect to it.
+async fn listen_upstream(
+    mut build: impl FnMut() -> Result<FtpServer, ServerError>,
+    mut pick: impl FnMut() -> anyhow::Result<SocketAddr>,
+) -> anyhow::Result<(Listening, SocketAddr)> {
+    for _ in 0..UPSTREAM_ATTEMPTS {
+        let upstream = pick()?;
+        let mut listening: Listening = Box::pin(build()?.listen(upstream.to_string()));
+        match poll_fn(|cx| Poll::Ready(listening.as_mut().poll(cx))).await {
+            Poll::Pending => return Ok((listening, upstream)),
+            Poll::Ready(Err(e)) if is_addr_in_use(&e) => warn!("FTP upstream {} is taken, picking another port", upstream),
+            Poll::Ready(Err(e)) => return Err(e.into()),
+            // shutdown began before libunftp bound, the relay stops right away too.
+            Poll::Ready(Ok(())) => return Ok((Box::pin(std::future::ready(Ok(()))), upstream)),
+        }
+    }
+    Err(anyhow::anyhow!("no free loopback port for the FTP server after {} attempts", UPSTREAM_ATTEMPTS))
+}
+
+fn is_addr_in_use(e: &ServerError) -> bool {
+    std::error::Error::source(e)
+        .and_then(|source| source.downcast_ref::<io::Error>())
+        .is_some_and(|e| e.kind() == io::ErrorKind::AddrInUse)
+}
+
+#[async_trait]
+impl Protocol for Ftp {
+    fn name(&self) -> &'static str {
+        "FTP"
     }
 
-    info!("FTP server listening on port 21");
-    let server = libunftp::ServerBuilder::with_authenticator(
-        Box::new(move || { unftp_sbe_fs::Filesystem::new(get_ftp_path())}),
-        std::sync::Arc::new(BruteAuthenticator{})
-    ).build().unwrap();
-    server.listen("0.0.0.0:21").await?;
-    Ok(())
-}
\ No newline at end of file
+    async fn bind(&mut self) -> anyhow::Result<()> {
+        let path = Path::new(get_ftp_path());
+        if !path.exists() {
+            fs::create_dir_all(path)?;
+        }
+
+        // without PROXY support libunftp binds its own socket in `listen`.
+        if self.proxy.is_enabled() {
+            self.listener = Some(TcpListener::bind(&self.address).await?);
+        }
+        Ok(())
+    }
+
+    async fn run(&mut self, reporter: Reporter, shutdown: Shutdown) -> anyhow::Result<()> {
+        let authenticator = Arc::new(BruteAuthenticator { reporter, tarpit: self.tarpit.clone(), protocol: self.name() });
+        let builder = || {
+            let mut indicator = shutdown.clone();
+            libunftp::ServerBuilder::with_authenticator(
+                Box::new(move || { Filesystem::new(get_ftp_path())}),
+                authenticator.clone()
+            )
+            // libunftp stops accepting and gives open sessions a moment to finish.
+            .shutdown_indicator(async move {
+                indicator.recv().await;
+                libunftp::options::Shutdown::new().grace_period(SHUTDOWN_GRACE_PERIOD)
+            })
+        };
+
+        if !self.proxy.is_enabled() {
+            let server = builder().build()?;
+            info!("FTP server listening on {}", self.address);
+            server.listen(self.address.clone()).await?;
+            return Ok(());
+        }
+
+        // libunftp only trusts whatever PROXY header it is given, so it
+        // listens privately and the relay decides which peers may send one.
+        // Only the control port is relayed: PASV and EPSV need a login and
+        // every login is rejected, so no data connection is ever opened.
+        let listener = self
+            .listener
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("FTP listener is not bound"))?;
+        let control_port = listener.local_addr()?.port();
+        let (listening, upstream) = listen_upstream(
+            || builder().proxy_protocol_mode(control_port).build(),
+            free_loopback_address,
+        )
+        .await?;
+
+        info!("FTP server listening on {} (PROXY protocol, upstream {})", self.address, upstream);
+        tokio::try_join!(
+            async { listening.await.map_err(anyhow::Error::from) },
+            relay(listener, upstream, self.proxy.clone(), shutdown.clone()),
+        )?;
+        Ok(())
+    }
+}
+
+/// Forwards every connection to libunftp with a normalized PROXY v1 header.
+/// Trusted proxies have their header (v1 or v2) translated, everyone else
+/// gets one describing their real peer address.
+async fn relay(listener: TcpListener, upstream: SocketAddr, proxy: Arc<ProxyConfig>, mut shutdown: Shutdown) -> anyhow::Result<()> {
+    loop {
+        // open relays end when libunftp closes its side.
+        let (mut inbound, peer) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = shutdown.recv() => return Ok(()),
+        };
+        let local = inbound.local_addr()?;
+        let proxy = proxy.clone();
+        tokio::spawn(async move {
+            let result: anyhow::Result<()> = async {
+                let addrs = proxy::accept(&mut inbound, peer, local, &proxy).await?;
+                let mut outbound = TcpStream::connect(upstream).await?;
+                outbound.write_all(addrs.to_v1().as_bytes()).await?;
+                tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await?;
+                Ok(())
+            }
+            .await;
+            if let Err(e) = result {
+                debug!("FTP relay for {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_upstream_is_only_reachable_locally() {
+        let upstream = free_loopback_address().unwrap();
+        assert!(upstream.ip().is_loopback());
+        assert_ne!(upstream.port(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_taken_upstream_port_is_replaced() {
+        let squatter = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut picks = vec![free_loopback_address().unwrap(), squatter.local_addr().unwrap()];
+        let build = || libunftp::ServerBuilder::with_authenticator(
+            Box::new(|| Filesystem::new(std::env::temp_dir())),
+            Arc::new(AnonymousAuthenticator),
+        )
+        .build();
+
+        let (_listening, upstream) = listen_upstream(build, || Ok(picks.pop().unwrap())).await.unwrap();
+        assert!(picks.is_empty());
+        assert_ne!(upstream, squatter.local_addr().unwrap());
+        // libunftp holds the port before listen_upstream returns.
+        assert!(std::net::TcpListener::bind(upstream).is_err());
+    }
+}
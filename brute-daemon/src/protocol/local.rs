@@ -0,0 +1,309 @@
+////////////
+// LOCAL //
+//////////
+
+use std::{
+    env, fs,
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use log::{debug, error, info, warn};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::payload::{Payload, Reporter};
+use crate::protocol::Protocol;
+use crate::shutdown::Shutdown;
+
+const DEFAULT_SOCKET_PATH: &str = "/run/brute-daemon/brute.sock";
+const DEFAULT_SOCKET_MODE: u32 = 0o600;
+/// Longest accepted line, anything longer closes the connection.
+const MAX_LINE_LENGTH: u64 = 4096;
+
+/// Unix socket for agents on the same host, such as the PAM module. Each
+/// line is one JSON attempt: `{"username":"","password":"","ip_address":"","protocol":""}`.
+/// Nothing is written back, agents can send and close.
+pub struct Local {
+    path: PathBuf,
+    mode: u32,
+    listener: Option<UnixListener>,
+}
+
+impl Local {
+    /// Reads `LOCAL_SOCKET_PATH` and `LOCAL_SOCKET_MODE` (octal).
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mode = match env::var("LOCAL_SOCKET_MODE") {
+            Ok(mode) => u32::from_str_radix(&mode, 8)
+                .map_err(|e| anyhow::anyhow!("invalid LOCAL_SOCKET_MODE: {}", e))?,
+            Err(_) => DEFAULT_SOCKET_MODE,
+        };
+        Ok(Self {
+            path: PathBuf::from(env::var("LOCAL_SOCKET_PATH").unwrap_or(DEFAULT_SOCKET_PATH.to_string())),
+            mode,
+            listener: None,
+        })
+    }
+}
+
+#[async_trait]
+impl Protocol for Local {
+    fn name(&self) -> &'static str {
+        "LOCAL"
+    }
+
+    async fn bind(&mut self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        remove_stale_socket(&self.path)?;
+        self.listener = Some(bind_private(&self.path, self.mode)?);
+        info!("Local socket listening on {}", self.path.display());
+        Ok(())
+    }
+
+    async fn run(&mut self, reporter: Reporter, mut shutdown: Shutdown) -> anyhow::Result<()> {
+        let listener = self
+            .listener
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("local socket is not bound"))?;
+
+        loop {
+            let (stream, _) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                _ = shutdown.recv() => break,
+            };
+            let reporter = reporter.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, reporter).await {
+                    debug!("Local socket connection closed: {}", e);
+                }
+            });
+        }
+
+        let _ = fs::remove_file(&self.path);
+        Ok(())
+    }
+}
+
+/// Binds in a directory only we can enter and moves the socket to `path`
+/// once it has `mode`, so nobody can connect while it has the umask's.
+fn bind_private(path: &Path, mode: u32) -> anyhow::Result<UnixListener> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a socket path", path.display()))?;
+    // next to `path`, renames don't cross filesystems.
+    let private = path.with_file_name(format!(".{}.{}", name.to_string_lossy(), uuid::Uuid::new_v4().simple()));
+    fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let staged = private.join(name);
+    let result = UnixListener::bind(&staged).map_err(anyhow::Error::from).and_then(|listener| {
+        fs::set_permissions(&staged, fs::Permissions::from_mode(mode))?;
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&staged);
+    let _ = fs::remove_dir(&private);
+    result
+}
+
+/// A socket left behind by a crash would make `bind` fail.
+fn remove_stale_socket(path: &Path) -> anyhow::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => Ok(fs::remove_file(path)?),
+        Ok(_) => Err(anyhow::anyhow!("{} exists and is not a socket", path.display())),
+        Err(_) => Ok(()),
+    }
+}
+
+async fn handle(stream: UnixStream, reporter: Reporter) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        let read = (&mut reader).take(MAX_LINE_LENGTH + 1).read_until(b'\n', &mut line).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        if line.last() != Some(&b'\n') && read as u64 > MAX_LINE_LENGTH {
+            return Err(anyhow::anyhow!("line is longer than {} bytes", MAX_LINE_LENGTH));
+        }
+
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Payload>(text) {
+            Ok(payload) => {
+                if let Err(e) = reporter.report(payload).await {
+                    error!("Failed to report local attempt: {}", e);
+                }
+            }
+            Err(e) => warn!("Ignoring malformed line on the local socket: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::net::UnixListener as StdUnixListener,
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    use super::*;
+    use crate::filter::IpFilter;
+    use crate::payload::DeliveryConfig;
+
+    /// Accepts every batch and keeps the usernames in it.
+    async fn brute_http() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/brute/attack/add/batch", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                let (head, length) = loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        let head = String::from_utf8_lossy(&request[..end]).to_lowercase();
+                        let length: usize = head
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length: "))
+                            .unwrap()
+                            .parse()
+                            .unwrap();
+                        break (end + 4, length);
+                    }
+                };
+                while request.len() < head + length {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let batch: Vec<serde_json::Value> = serde_json::from_slice(&request[head..head + length]).unwrap();
+                log.lock()
+                    .unwrap()
+                    .extend(batch.iter().map(|attempt| attempt["username"].as_str().unwrap().to_string()));
+                let response = "HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn reporter(url: &str) -> Reporter {
+        let config = DeliveryConfig {
+            url: url.to_string(),
+            batch_url: Some(url.to_string()),
+            bearer_token: Some("token".to_string()),
+            signer: None,
+            queue_size: 100,
+            batch_size: 25,
+            max_retries: 0,
+        };
+        Reporter::new(config, IpFilter::default())
+    }
+
+    fn attempt(username: &str) -> String {
+        format!(
+            "{{\"username\":\"{}\",\"password\":\"hunter2\",\"ip_address\":\"203.0.113.7\",\"protocol\":\"PAM\"}}\n",
+            username
+        )
+    }
+
+    /// Sends `input` on a fresh connection, closes it and returns what
+    /// `handle` made of it along with everything that reached brute-http.
+    async fn send(input: &[u8]) -> (anyhow::Result<()>, Vec<String>) {
+        let (url, received) = brute_http().await;
+        let reporter = reporter(&url);
+        let (mut agent, stream) = UnixStream::pair().unwrap();
+        agent.write_all(input).await.unwrap();
+        drop(agent);
+        let result = handle(stream, reporter.clone()).await;
+        reporter.flush().await;
+        let received = received.lock().unwrap().clone();
+        (result, received)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("brute-local-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn lines_reach_the_reporter() {
+        let input = [attempt("root"), attempt("admin")].concat();
+        let (result, received) = send(input.as_bytes()).await;
+        assert!(result.is_ok());
+        assert_eq!(received, ["root", "admin"]);
+    }
+
+    #[tokio::test]
+    async fn malformed_lines_are_skipped() {
+        let input = [attempt("root"), "not json\n".to_string(), "\n".to_string(), attempt("admin")].concat();
+        let (result, received) = send(input.as_bytes()).await;
+        assert!(result.is_ok());
+        assert_eq!(received, ["root", "admin"]);
+    }
+
+    #[tokio::test]
+    async fn long_lines_close_the_connection() {
+        let long = format!("{}\n", "a".repeat(MAX_LINE_LENGTH as usize + 1));
+        let input = [attempt("root"), long, attempt("admin")].concat();
+        let (result, received) = send(input.as_bytes()).await;
+        assert!(result.is_err());
+        assert_eq!(received, ["root"]);
+    }
+
+    #[tokio::test]
+    async fn lines_at_the_limit_are_read() {
+        let username = "a".repeat(MAX_LINE_LENGTH as usize - attempt("").len());
+        let (result, received) = send(attempt(&username).as_bytes()).await;
+        assert!(result.is_ok());
+        assert_eq!(received, [username]);
+    }
+
+    #[test]
+    fn only_sockets_are_removed() {
+        let missing = temp_path("missing");
+        assert!(remove_stale_socket(&missing).is_ok());
+
+        let socket = temp_path("socket");
+        drop(StdUnixListener::bind(&socket).unwrap());
+        assert!(remove_stale_socket(&socket).is_ok());
+        assert!(!socket.exists());
+
+        let file = temp_path("file");
+        fs::write(&file, "keep me").unwrap();
+        assert!(remove_stale_socket(&file).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "keep me");
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sockets_appear_with_their_mode() {
+        let socket = temp_path("private");
+        let listener = bind_private(&socket, 0o600).unwrap();
+        let metadata = fs::symlink_metadata(&socket).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        // nothing is left next to it.
+        let prefix = format!(".{}.", socket.file_name().unwrap().to_string_lossy());
+        assert!(!fs::read_dir(env::temp_dir())
+            .unwrap()
+            .any(|entry| entry.unwrap().file_name().to_string_lossy().starts_with(&prefix)));
+
+        UnixStream::connect(&socket).await.unwrap();
+        assert!(listener.accept().await.is_ok());
+        fs::remove_file(&socket).unwrap();
+    }
+}
@@ -1,2 +1,245 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::{error, info, warn};
+use tokio::time::Instant;
+
+use crate::{payload::Reporter, shutdown::Shutdown};
+
 pub mod ssh;
-pub mod ftp;
\ No newline at end of file
+pub mod ftp;
+#[cfg(unix)]
+pub mod local;
+
+///////////////
+// PROTOCOL //
+/////////////
+
+/// A honeypot listener. `bind` is called before every `run`, including
+/// restarts, so implementations should (re)acquire their sockets there.
+#[async_trait]
+pub trait Protocol: Send {
+    /// Name used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Binds the listening socket.
+    async fn bind(&mut self) -> anyhow::Result<()>;
+
+    /// Accepts connections and reports attempts until `shutdown` fires.
+    /// Returning an error makes the registry rebind and run it again.
+    async fn run(&mut self, reporter: Reporter, shutdown: Shutdown) -> anyhow::Result<()>;
+}
+
+///////////////
+// REGISTRY //
+/////////////
+
+const INITIAL_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// Holds every enabled protocol and supervises them.
+#[derive(Default)]
+pub struct ProtocolRegistry {
+    protocols: Vec<Box<dyn Protocol>>,
+}
+
+impl ProtocolRegistry {
+    pub fn register<P: Protocol + 'static>(&mut self, protocol: P) -> &mut Self {
+        self.protocols.push(Box::new(protocol));
+        self
+    }
+
+    /// Runs every registered protocol on its own task and waits for all
+    /// of them to stop. A failing listener never takes down the others.
+    pub async fn run(self, reporter: Reporter, shutdown: Shutdown) {
+        let handles: Vec<_> = self
+            .protocols
+            .into_iter()
+            .map(|protocol| tokio::spawn(supervise(protocol, reporter.clone(), shutdown.clone())))
+            .collect();
+
+        for handle in handles {
+            if let Err(e) = handle.await {
+                error!("Protocol supervisor stopped unexpectedly: {}", e);
+            }
+        }
+    }
+}
+
+async fn supervise(mut protocol: Box<dyn Protocol>, reporter: Reporter, mut shutdown: Shutdown) {
+    let name = protocol.name();
+    let mut delay = INITIAL_RESTART_DELAY;
+
+    while !shutdown.is_triggered() {
+        let started = Instant::now();
+        let result = match protocol.bind().await {
+            Ok(()) => protocol.run(reporter.clone(), shutdown.clone()).await,
+            Err(e) => Err(e.context(format!("{} failed to bind", name))),
+        };
+
+        match result {
+            Ok(()) => break,
+            Err(e) => {
+                // a listener that stayed up for a while gets a fresh backoff.
+                if started.elapsed() > MAX_RESTART_DELAY {
+                    delay = INITIAL_RESTART_DELAY;
+                }
+                warn!("{} listener failed: {:#}. Restarting in {:?}.", name, e, delay);
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = shutdown.recv() => break,
+                }
+                delay = (delay * 2).min(MAX_RESTART_DELAY);
+            }
+        }
+    }
+    info!("{} listener stopped.", name);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+    };
+
+
+    use super::*;
+    use crate::{filter::IpFilter, payload::DeliveryConfig, shutdown};
+
+    enum Step {
+        FailBind,
+        /// Runs for a while, then fails.
+        FailAfter(Duration),
+        UntilShutdown,
+    }
+
+    /// Follows `steps`, then fails every bind.
+    struct Scripted {
+        steps: VecDeque<Step>,
+        binds: Arc<Mutex<Vec<Instant>>>,
+    }
+
+    impl Scripted {
+        fn new(steps: Vec<Step>) -> (Self, Arc<Mutex<Vec<Instant>>>) {
+            let binds = Arc::new(Mutex::new(Vec::new()));
+            (Self { steps: steps.into(), binds: binds.clone() }, binds)
+        }
+    }
+
+    #[async_trait]
+    impl Protocol for Scripted {
+        fn name(&self) -> &'static str {
+            "SCRIPTED"
+        }
+
+        async fn bind(&mut self) -> anyhow::Result<()> {
+            self.binds.lock().unwrap().push(Instant::now());
+            match self.steps.front() {
+                None | Some(Step::FailBind) => {
+                    self.steps.pop_front();
+                    Err(anyhow::anyhow!("bind failed"))
+                }
+                Some(_) => Ok(()),
+            }
+        }
+
+        async fn run(&mut self, _: Reporter, mut shutdown: Shutdown) -> anyhow::Result<()> {
+            match self.steps.pop_front() {
+                Some(Step::FailAfter(uptime)) => {
+                    tokio::time::sleep(uptime).await;
+                    Err(anyhow::anyhow!("run failed"))
+                }
+                Some(Step::UntilShutdown) => {
+                    shutdown.recv().await;
+                    Ok(())
+                }
+                _ => unreachable!("run is only called after a successful bind"),
+            }
+        }
+    }
+
+    fn reporter() -> Reporter {
+        let config = DeliveryConfig {
+            url: "http://127.0.0.1:9/brute/attack/add".to_string(),
+            batch_url: None,
+            bearer_token: None,
+            signer: None,
+            queue_size: 1,
+            batch_size: 1,
+            max_retries: 0,
+        };
+        Reporter::new(config, IpFilter::default())
+    }
+
+    fn gaps(binds: &Mutex<Vec<Instant>>) -> Vec<Duration> {
+        let binds = binds.lock().unwrap();
+        binds.windows(2).map(|pair| pair[1] - pair[0]).collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restarts_double_the_delay_up_to_the_maximum() {
+        let mut steps: Vec<_> = (0..8).map(|_| Step::FailBind).collect();
+        steps.push(Step::UntilShutdown);
+        let (protocol, binds) = Scripted::new(steps);
+        let (trigger, shutdown) = shutdown::channel();
+        let supervisor = tokio::spawn(supervise(Box::new(protocol), reporter(), shutdown));
+
+        tokio::time::sleep(Duration::from_secs(600)).await;
+        trigger.send(true).unwrap();
+        supervisor.await.unwrap();
+
+        let seconds: Vec<_> = gaps(&binds).iter().map(Duration::as_secs).collect();
+        assert_eq!(seconds, [1, 2, 4, 8, 16, 32, 60, 60]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_listener_that_stayed_up_starts_over_with_a_short_delay() {
+        let (protocol, binds) = Scripted::new(vec![
+            Step::FailBind,
+            Step::FailBind,
+            Step::FailAfter(MAX_RESTART_DELAY * 2),
+            Step::UntilShutdown,
+        ]);
+        let (trigger, shutdown) = shutdown::channel();
+        let supervisor = tokio::spawn(supervise(Box::new(protocol), reporter(), shutdown));
+
+        tokio::time::sleep(Duration::from_secs(600)).await;
+        trigger.send(true).unwrap();
+        supervisor.await.unwrap();
+
+        let seconds: Vec<_> = gaps(&binds).iter().map(Duration::as_secs).collect();
+        assert_eq!(seconds, [1, 2, MAX_RESTART_DELAY.as_secs() * 2 + 1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_interrupts_the_restart_delay() {
+        let (protocol, binds) = Scripted::new(Vec::new());
+        let (trigger, shutdown) = shutdown::channel();
+        let supervisor = tokio::spawn(supervise(Box::new(protocol), reporter(), shutdown));
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        trigger.send(true).unwrap();
+        tokio::time::timeout(Duration::from_millis(1), supervisor)
+            .await
+            .expect("supervisor kept waiting after shutdown")
+            .unwrap();
+        assert_eq!(binds.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_failing_protocol_does_not_stop_the_others() {
+        let (broken, _) = Scripted::new(Vec::new());
+        let (healthy, healthy_binds) = Scripted::new(vec![Step::UntilShutdown]);
+        let mut registry = ProtocolRegistry::default();
+        registry.register(broken).register(healthy);
+        let (trigger, shutdown) = shutdown::channel();
+        let run = tokio::spawn(registry.run(reporter(), shutdown));
+
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        assert!(!run.is_finished());
+        trigger.send(true).unwrap();
+        run.await.unwrap();
+        assert_eq!(healthy_binds.lock().unwrap().len(), 1);
+    }
+}
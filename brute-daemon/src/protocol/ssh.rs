@@ -9,37 +9,54 @@ use std::env;
 use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use log::info;
+use log::{debug, error, info};
 use russh::server::{Auth, Msg, Server as _, Session};
 use russh::{Channel, ChannelId};
 use russh_keys::key::KeyPair;
+use tokio::net::TcpListener;
 use tokio::sync::Mutex;
+use tokio::task::JoinSet;
 
-use crate::payload;
+use crate::payload::{Payload, Reporter};
+use crate::protocol::Protocol;
+use crate::proxy::{self, ProxyConfig};
+use crate::shutdown::Shutdown;
+use crate::tarpit::Tarpit;
+
+/// Protocol label attempts are reported with.
+pub const NAME: &str = "SSH";
 
 #[derive(Clone)]
-pub struct Server;
+pub struct Server {
+    reporter: Reporter,
+    tarpit: Tarpit,
+    protocol: &'static str,
+}
 
 impl russh::server::Server for Server {
     type Handler = SshSession;
 
     fn new_client(&mut self, client_ip: Option<SocketAddr>) -> Self::Handler {
-        let mut ssh = SshSession::default();
-        ssh.ip = client_ip;
-        ssh
+        SshSession::new(self.reporter.clone(), self.tarpit.clone(), self.protocol, client_ip)
     }
 }
 
 pub struct SshSession {
     clients: Arc<Mutex<HashMap<ChannelId, Channel<Msg>>>>,
     ip: Option<SocketAddr>,
+    reporter: Reporter,
+    tarpit: Tarpit,
+    protocol: &'static str,
 }
 
-impl Default for SshSession {
-    fn default() -> Self {
+impl SshSession {
+    pub fn new(reporter: Reporter, tarpit: Tarpit, protocol: &'static str, ip: Option<SocketAddr>) -> Self {
         Self {
             clients: Arc::new(Mutex::new(HashMap::new())),
-            ip: None,
+            ip,
+            reporter,
+            tarpit,
+            protocol,
         }
     }
 }
@@ -50,15 +67,14 @@ impl russh::server::Handler for SshSession {
 
     #[allow(unused_variables)]
     async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
-        let binding = self.ip.unwrap();
+        let binding = self.ip.ok_or_else(|| anyhow::anyhow!("SSH session has no peer address"))?;
         let ip = binding.ip().to_string();
-        let endpoint = env::var("ADD_ATTACK_ENDPOINT")?;
-        if !ip.eq("127.0.0.1") {
-            info!("Recieved an auth request sending to {}", endpoint);
-            payload::Payload::post(&user, &password, &ip, "SSH").await?;
-        } else {
-            info!("Recieved request but not sending because of debug. {}", endpoint);
+        if let Err(e) = self.reporter.report(Payload::new(user, password, &ip, self.protocol)).await {
+            error!("Failed to report {} attempt from {}: {}", self.protocol, ip, e);
         }
+        // the rejection is only sent once this returns.
+        let delay = self.tarpit.record_failure(binding.ip());
+        self.tarpit.hold(delay).await;
         Ok(Auth::Reject { proceed_with_methods: None })
     }
 
@@ -95,27 +111,114 @@ impl russh::server::Handler for SshSession {
     }
 }
 
-pub async fn start_ssh_server() -> anyhow::Result<()> {
-    let config = russh::server::Config {
-        auth_rejection_time: Duration::from_secs(1),
-        auth_rejection_time_initial: None,
-        keys: vec![KeyPair::generate_ed25519().unwrap()],
-        ..Default::default()
-    };
-
-    let mut server = Server;
-
-    info!("SSH server listening on port 22");
-    server.run_on_address(
-            Arc::new(config),
-            (
-                "0.0.0.0",
-                std::env::var("PORT")
-                    .unwrap_or("22".to_string())
-                    .parse()
-                    .unwrap(),
-            ),
-        )
-        .await?;
-    Ok(())
+///////////////
+// PROTOCOL //
+/////////////
+
+pub struct Ssh {
+    port: u16,
+    config: Arc<russh::server::Config>,
+    proxy: Arc<ProxyConfig>,
+    tarpit: Tarpit,
+    listener: Option<TcpListener>,
+}
+
+impl Ssh {
+    pub fn new(tarpit: Tarpit) -> anyhow::Result<Self> {
+        // the tarpit picks its own delays per source.
+        let auth_rejection_time = if tarpit.is_enabled() {
+            Duration::ZERO
+        } else {
+            Duration::from_secs(1)
+        };
+        let config = russh::server::Config {
+            auth_rejection_time,
+            auth_rejection_time_initial: None,
+            keys: vec![KeyPair::generate_ed25519().unwrap()],
+            ..Default::default()
+        };
+
+        Ok(Self {
+            port: env::var("PORT").unwrap_or("22".to_string()).parse()?,
+            config: Arc::new(config),
+            proxy: Arc::new(ProxyConfig::from_env("SSH")?),
+            tarpit,
+            listener: None,
+        })
+    }
+}
+
+#[async_trait]
+impl Protocol for Ssh {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn bind(&mut self) -> anyhow::Result<()> {
+        self.listener = Some(TcpListener::bind(("0.0.0.0", self.port)).await?);
+        info!("SSH server listening on port {}", self.port);
+        Ok(())
+    }
+
+    async fn run(&mut self, reporter: Reporter, mut shutdown: Shutdown) -> anyhow::Result<()> {
+        let listener = self
+            .listener
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("SSH listener is not bound"))?;
+        let server = Server { reporter, tarpit: self.tarpit.clone(), protocol: self.name() };
+        let mut sessions = JoinSet::new();
+
+        loop {
+            let (mut stream, peer) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                // reap finished sessions so the set doesn't grow forever.
+                Some(_) = sessions.join_next(), if !sessions.is_empty() => continue,
+                _ = shutdown.recv() => break,
+            };
+
+            let local = stream.local_addr()?;
+            let mut server = server.clone();
+            let config = self.config.clone();
+            let proxy = self.proxy.clone();
+            let mut session_shutdown = shutdown.clone();
+            sessions.spawn(async move {
+                // behind a load balancer the peer is the balancer, the
+                // attacker's address comes from the PROXY header.
+                let client = match proxy::accept(&mut stream, peer, local, &proxy).await {
+                    Ok(addrs) => addrs.source,
+                    Err(e) => {
+                        debug!("Dropping SSH connection from {}: {}", peer, e);
+                        return;
+                    }
+                };
+
+                let session = async {
+                    // repeat offenders wait before the server banner, which
+                    // stalls their key exchange as well.
+                    server.tarpit.hold(server.tarpit.delay_for(client.ip())).await;
+
+                    let handler = server.new_client(Some(client));
+                    match russh::server::run_stream(config, stream, handler).await {
+                        Ok(session) => {
+                            if let Err(e) = session.await {
+                                debug!("SSH session with {} ended with an error: {}", client, e);
+                            }
+                        }
+                        Err(e) => debug!("SSH handshake with {} failed: {}", client, e),
+                    }
+                };
+
+                // attempts are queued as soon as they arrive, so on shutdown
+                // the connection can simply be dropped.
+                tokio::select! {
+                    _ = session => {}
+                    _ = session_shutdown.recv() => debug!("Closing SSH session with {} for shutdown.", client),
+                }
+            });
+        }
+
+        info!("SSH server stopped accepting, closing {} session(s).", sessions.len());
+        while sessions.join_next().await.is_some() {}
+        Ok(())
+    }
 }
@@ -0,0 +1,411 @@
+////////////
+// PROXY //
+//////////
+/////////////////////////////////////////////////////////////////
+// https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt //
+/////////////////////////////////////////////////////////////////
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use log::warn;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::config::env_or;
+use crate::filter::IpFilter;
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const V1_MAX_LENGTH: usize = 107;
+const V2_MAX_LENGTH: usize = 4096;
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Per-listener PROXY protocol settings.
+#[derive(Clone, Debug, Default)]
+pub struct ProxyConfig {
+    enabled: bool,
+    trusted: IpFilter,
+}
+
+impl ProxyConfig {
+    pub fn new(enabled: bool, trusted: IpFilter) -> Self {
+        Self { enabled, trusted }
+    }
+
+    /// Reads `{PREFIX}_PROXY_PROTOCOL` and `{PREFIX}_TRUSTED_PROXIES`,
+    /// e.g. `SSH_PROXY_PROTOCOL=true` and `SSH_TRUSTED_PROXIES=10.0.0.0/8`.
+    pub fn from_env(prefix: &str) -> anyhow::Result<Self> {
+        let enabled = env_or(&format!("{}_PROXY_PROTOCOL", prefix), false)?;
+        let trusted = IpFilter::from_env(&format!("{}_TRUSTED_PROXIES", prefix), "")?;
+        if enabled && trusted.is_empty() {
+            warn!("{}_PROXY_PROTOCOL is enabled but {}_TRUSTED_PROXIES is empty, no headers will be accepted.", prefix, prefix);
+        }
+        Ok(Self::new(enabled, trusted))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Only trusted peers may speak for another address.
+    pub fn is_trusted(&self, peer: IpAddr) -> bool {
+        self.enabled && self.trusted.contains(peer)
+    }
+}
+
+/// Source and destination of a proxied connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProxiedAddrs {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+impl ProxiedAddrs {
+    /// Encodes the addresses as a PROXY v1 header.
+    pub fn to_v1(self) -> String {
+        let (source, destination) = match (self.source.ip(), self.destination.ip()) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+                (self.source, self.destination)
+            }
+            // v1 needs both addresses in the same family.
+            _ => (to_v6(self.source), to_v6(self.destination)),
+        };
+        let family = if source.is_ipv4() { "TCP4" } else { "TCP6" };
+        format!(
+            "PROXY {} {} {} {} {}\r\n",
+            family,
+            source.ip(),
+            destination.ip(),
+            source.port(),
+            destination.port()
+        )
+    }
+}
+
+fn to_v6(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) => SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port()),
+        IpAddr::V6(_) => addr,
+    }
+}
+
+/// Resolves the real addresses of a freshly accepted connection.
+///
+/// Connections from trusted proxies must start with a v1 or v2 header, which
+/// is consumed from `stream` and nothing else. Everyone else is taken at
+/// face value and no bytes are read.
+pub async fn accept<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    peer: SocketAddr,
+    local: SocketAddr,
+    config: &ProxyConfig,
+) -> anyhow::Result<ProxiedAddrs> {
+    let direct = ProxiedAddrs {
+        source: peer,
+        destination: local,
+    };
+    if !config.is_trusted(peer.ip()) {
+        return Ok(direct);
+    }
+
+    let header = tokio::time::timeout(HEADER_TIMEOUT, read_header(stream))
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out waiting for PROXY header from {}", peer))??;
+    Ok(header.unwrap_or(direct))
+}
+
+/// Returns `None` for LOCAL/UNKNOWN headers (health checks from the proxy itself).
+async fn read_header<S: AsyncRead + Unpin>(stream: &mut S) -> anyhow::Result<Option<ProxiedAddrs>> {
+    let mut start = [0u8; 12];
+    stream.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        read_v2(stream).await
+    } else if start.starts_with(b"PROXY ") {
+        read_v1(stream, &start).await
+    } else {
+        Err(anyhow::anyhow!("trusted proxy did not send a PROXY header"))
+    }
+}
+
+async fn read_v1<S: AsyncRead + Unpin>(stream: &mut S, start: &[u8]) -> anyhow::Result<Option<ProxiedAddrs>> {
+    // read one byte at a time so nothing after the header is consumed.
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(anyhow::anyhow!("PROXY v1 header is too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])?;
+    parse_v1(line)
+}
+
+fn parse_v1(line: &str) -> anyhow::Result<Option<ProxiedAddrs>> {
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", family @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] => {
+            let source: IpAddr = source.parse()?;
+            let destination: IpAddr = destination.parse()?;
+            if source.is_ipv4() != (*family == "TCP4") || destination.is_ipv4() != (*family == "TCP4") {
+                return Err(anyhow::anyhow!("PROXY v1 address does not match {}", family));
+            }
+            Ok(Some(ProxiedAddrs {
+                source: SocketAddr::new(source, source_port.parse()?),
+                destination: SocketAddr::new(destination, destination_port.parse()?),
+            }))
+        }
+        _ => Err(anyhow::anyhow!("malformed PROXY v1 header")),
+    }
+}
+
+async fn read_v2<S: AsyncRead + Unpin>(stream: &mut S) -> anyhow::Result<Option<ProxiedAddrs>> {
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    let (version_command, family) = (head[0], head[1]);
+    let length = u16::from_be_bytes([head[2], head[3]]) as usize;
+
+    if version_command >> 4 != 2 {
+        return Err(anyhow::anyhow!("unsupported PROXY header version"));
+    }
+    if length > V2_MAX_LENGTH {
+        return Err(anyhow::anyhow!("PROXY v2 header is too long"));
+    }
+
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).await?;
+
+    match version_command & 0x0F {
+        // LOCAL: the proxy is talking to us on its own behalf.
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(anyhow::anyhow!("unsupported PROXY v2 command")),
+    }
+
+    parse_v2_addresses(family >> 4, &body)
+}
+
+fn parse_v2_addresses(family: u8, body: &[u8]) -> anyhow::Result<Option<ProxiedAddrs>> {
+    let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+    match family {
+        // AF_INET
+        0x1 if body.len() >= 12 => {
+            let source = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            let destination = Ipv4Addr::new(body[4], body[5], body[6], body[7]);
+            Ok(Some(ProxiedAddrs {
+                source: SocketAddr::new(IpAddr::V4(source), port(8)),
+                destination: SocketAddr::new(IpAddr::V4(destination), port(10)),
+            }))
+        }
+        // AF_INET6
+        0x2 if body.len() >= 36 => {
+            let mut source = [0u8; 16];
+            let mut destination = [0u8; 16];
+            source.copy_from_slice(&body[0..16]);
+            destination.copy_from_slice(&body[16..32]);
+            Ok(Some(ProxiedAddrs {
+                source: SocketAddr::new(IpAddr::V6(Ipv6Addr::from(source)), port(32)),
+                destination: SocketAddr::new(IpAddr::V6(Ipv6Addr::from(destination)), port(34)),
+            }))
+        }
+        // AF_UNSPEC and AF_UNIX carry no usable client address.
+        0x0 | 0x3 => Ok(None),
+        _ => Err(anyhow::anyhow!("malformed PROXY v2 address block")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER: &str = "10.0.0.2:40000";
+    const LOCAL: &str = "192.0.2.1:22";
+
+    fn trusting(cidrs: &str) -> ProxyConfig {
+        ProxyConfig::new(true, IpFilter::parse(cidrs).unwrap())
+    }
+
+    fn v2(command: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        header.extend_from_slice(body);
+        header
+    }
+
+    #[test]
+    fn a_mistyped_proxy_setting_is_an_error() {
+        // a prefix of its own, other tests read the environment too.
+        std::env::set_var("TYPO_PROXY_PROTOCOL", "yes");
+        assert!(ProxyConfig::from_env("TYPO").is_err());
+        std::env::set_var("TYPO_PROXY_PROTOCOL", "true");
+        assert!(ProxyConfig::from_env("TYPO").unwrap().is_enabled());
+    }
+
+    async fn read(bytes: &[u8]) -> anyhow::Result<Option<ProxiedAddrs>> {
+        read_header(&mut &bytes[..]).await
+    }
+
+    fn addrs(source: &str, destination: &str) -> Option<ProxiedAddrs> {
+        Some(ProxiedAddrs {
+            source: source.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+        })
+    }
+
+    #[tokio::test]
+    async fn v1_tcp4_leaves_the_rest_of_the_stream() {
+        let mut stream = &b"PROXY TCP4 203.0.113.7 192.0.2.1 51234 22\r\nSSH-2.0-client\r\n"[..];
+        let header = read_header(&mut stream).await.unwrap();
+        assert_eq!(header, addrs("203.0.113.7:51234", "192.0.2.1:22"));
+        assert_eq!(stream, b"SSH-2.0-client\r\n");
+    }
+
+    #[tokio::test]
+    async fn v1_tcp6() {
+        let header = read(b"PROXY TCP6 2001:db8::7 2001:db8::1 51234 21\r\n").await.unwrap();
+        assert_eq!(header, addrs("[2001:db8::7]:51234", "[2001:db8::1]:21"));
+    }
+
+    #[tokio::test]
+    async fn v1_unknown_has_no_addresses() {
+        assert_eq!(read(b"PROXY UNKNOWN\r\n").await.unwrap(), None);
+        assert_eq!(read(b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\n").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn v1_rejects_malformed_headers() {
+        // family and addresses disagree.
+        assert!(read(b"PROXY TCP4 2001:db8::7 2001:db8::1 51234 21\r\n").await.is_err());
+        assert!(read(b"PROXY TCP4 203.0.113.7 192.0.2.1 51234\r\n").await.is_err());
+        assert!(read(b"PROXY TCP4 203.0.113.7 192.0.2.1 51234 70000\r\n").await.is_err());
+        assert!(read(b"PROXY UDP4 203.0.113.7 192.0.2.1 51234 22\r\n").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn v1_rejects_truncated_and_oversized_headers() {
+        assert!(read(b"PROXY TCP4 203.0.113.7 192.0.2.1 512").await.is_err());
+        let long = format!("PROXY TCP4 {}\r\n", "1".repeat(V1_MAX_LENGTH));
+        assert!(read(long.as_bytes()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn v2_proxy_tcp4_leaves_the_rest_of_the_stream() {
+        let body = [203, 0, 113, 7, 192, 0, 2, 1, 0xC8, 0x22, 0x00, 0x15];
+        let mut bytes = v2(0x1, 0x11, &body);
+        bytes.extend_from_slice(b"USER root\r\n");
+        let mut stream = &bytes[..];
+        let header = read_header(&mut stream).await.unwrap();
+        assert_eq!(header, addrs("203.0.113.7:51234", "192.0.2.1:21"));
+        assert_eq!(stream, b"USER root\r\n");
+    }
+
+    #[tokio::test]
+    async fn v2_proxy_tcp6_ignores_trailing_tlvs() {
+        let source: Ipv6Addr = "2001:db8::7".parse().unwrap();
+        let destination: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let mut body = Vec::new();
+        body.extend_from_slice(&source.octets());
+        body.extend_from_slice(&destination.octets());
+        body.extend_from_slice(&[0xC8, 0x22, 0x00, 0x16]);
+        // a PP2_TYPE_NOOP TLV.
+        body.extend_from_slice(&[0x04, 0x00, 0x01, 0x00]);
+        let header = read(&v2(0x1, 0x21, &body)).await.unwrap();
+        assert_eq!(header, addrs("[2001:db8::7]:51234", "[2001:db8::1]:22"));
+    }
+
+    #[tokio::test]
+    async fn v2_local_and_unspec_have_no_addresses() {
+        assert_eq!(read(&v2(0x0, 0x00, &[])).await.unwrap(), None);
+        // LOCAL ignores whatever address block it carries.
+        assert_eq!(read(&v2(0x0, 0x11, &[0; 12])).await.unwrap(), None);
+        assert_eq!(read(&v2(0x1, 0x00, &[])).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn v2_rejects_bad_headers() {
+        // address block shorter than AF_INET needs.
+        assert!(read(&v2(0x1, 0x11, &[203, 0, 113, 7])).await.is_err());
+        assert!(read(&v2(0x2, 0x11, &[0; 12])).await.is_err());
+        assert!(read(&v2(0x1, 0x41, &[0; 12])).await.is_err());
+
+        let mut wrong_version = v2(0x1, 0x11, &[0; 12]);
+        wrong_version[12] = 0x11;
+        assert!(read(&wrong_version).await.is_err());
+
+        let mut too_long = v2(0x1, 0x11, &[]);
+        too_long[14..16].copy_from_slice(&(V2_MAX_LENGTH as u16 + 1).to_be_bytes());
+        assert!(read(&too_long).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn v2_rejects_truncated_headers() {
+        let header = v2(0x1, 0x11, &[203, 0, 113, 7, 192, 0, 2, 1, 0xC8, 0x22, 0x00, 0x15]);
+        for length in [5, 12, 14, 20] {
+            assert!(read(&header[..length]).await.is_err(), "accepted {} bytes", length);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_signatures() {
+        assert!(read(b"SSH-2.0-OpenSSH_9.6\r\n").await.is_err());
+        assert!(read(b"\r\n\r\n\0\r\nQUIX\n\x21\x11\x00\x0c").await.is_err());
+        assert!(read(b"proxy TCP4 203.0.113.7 192.0.2.1 51234 22\r\n").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn untrusted_peers_are_taken_at_face_value() {
+        let header = &b"PROXY TCP4 203.0.113.7 192.0.2.1 51234 22\r\n"[..];
+        let mut stream = header;
+        let config = trusting("10.1.0.0/16");
+        let addrs = accept(&mut stream, PEER.parse().unwrap(), LOCAL.parse().unwrap(), &config).await.unwrap();
+        assert_eq!(addrs.source, PEER.parse().unwrap());
+        // nothing was read, the header is passed on as data.
+        assert_eq!(stream, header);
+
+        let disabled = ProxyConfig::new(false, IpFilter::parse("10.0.0.0/8").unwrap());
+        let addrs = accept(&mut stream, PEER.parse().unwrap(), LOCAL.parse().unwrap(), &disabled).await.unwrap();
+        assert_eq!(addrs.source, PEER.parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn trusted_peers_must_send_a_header() {
+        let config = trusting("10.0.0.0/8");
+        let mut stream = &b"PROXY TCP4 203.0.113.7 192.0.2.1 51234 22\r\n"[..];
+        let addrs = accept(&mut stream, PEER.parse().unwrap(), LOCAL.parse().unwrap(), &config).await.unwrap();
+        assert_eq!(addrs.source, "203.0.113.7:51234".parse().unwrap());
+
+        let mut stream = &b"SSH-2.0-OpenSSH_9.6\r\n"[..];
+        assert!(accept(&mut stream, PEER.parse().unwrap(), LOCAL.parse().unwrap(), &config).await.is_err());
+
+        // health checks keep the proxy's own address.
+        let mut stream = &b"PROXY UNKNOWN\r\n"[..];
+        let addrs = accept(&mut stream, PEER.parse().unwrap(), LOCAL.parse().unwrap(), &config).await.unwrap();
+        assert_eq!(addrs.source, PEER.parse().unwrap());
+    }
+
+    #[test]
+    fn to_v1_maps_mixed_families_to_ipv6() {
+        let same = ProxiedAddrs {
+            source: "203.0.113.7:51234".parse().unwrap(),
+            destination: "192.0.2.1:21".parse().unwrap(),
+        };
+        assert_eq!(same.to_v1(), "PROXY TCP4 203.0.113.7 192.0.2.1 51234 21\r\n");
+
+        let mixed = ProxiedAddrs {
+            source: "203.0.113.7:51234".parse().unwrap(),
+            destination: "[2001:db8::1]:21".parse().unwrap(),
+        };
+        assert_eq!(mixed.to_v1(), "PROXY TCP6 ::ffff:203.0.113.7 2001:db8::1 51234 21\r\n");
+    }
+
+    #[tokio::test]
+    async fn to_v1_round_trips() {
+        let original = addrs("[2001:db8::7]:51234", "[2001:db8::1]:21").unwrap();
+        assert_eq!(read(original.to_v1().as_bytes()).await.unwrap(), Some(original));
+    }
+}
@@ -0,0 +1,50 @@
+use tokio::sync::watch;
+
+/// Creates a shutdown trigger and the `Shutdown` handle that every
+/// listener receives. Sending `true` on the trigger asks all listeners to stop.
+pub fn channel() -> (watch::Sender<bool>, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (sender, Shutdown { receiver })
+}
+
+/// Cloneable signal that tells a listener it should stop accepting work.
+#[derive(Clone)]
+pub struct Shutdown {
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// Returns true once shutdown has been requested.
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Waits until shutdown is requested. A dropped trigger counts as a request.
+    pub async fn recv(&mut self) {
+        let _ = self.receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+/// Waits for SIGINT (Ctrl-C) or, on unix, SIGTERM.
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(e) => {
+                log::warn!("Failed to listen for SIGTERM, only Ctrl-C shuts down: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
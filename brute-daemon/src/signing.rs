@@ -0,0 +1,72 @@
+//////////////
+// SIGNING //
+////////////
+
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// Signs requests the way brute-http's `SignatureVerifier` expects:
+/// HMAC-SHA256 over `METHOD\nPATH\nTIMESTAMP\nNONCE\nhex(sha256(body))`,
+/// keyed with the secret as is.
+#[derive(Clone)]
+pub struct Signer {
+    sensor: String,
+    secret: Vec<u8>,
+}
+
+impl std::fmt::Debug for Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // keep the secret out of logs.
+        f.debug_struct("Signer").field("sensor", &self.sensor).finish_non_exhaustive()
+    }
+}
+
+impl Signer {
+    pub fn new(sensor: String, secret: String) -> Self {
+        Self {
+            sensor,
+            secret: secret.into_bytes(),
+        }
+    }
+
+    /// Reads `SENSOR_ID` and `SENSOR_SECRET`, `None` unless both are set.
+    pub fn from_env() -> Option<Self> {
+        match (env::var("SENSOR_ID"), env::var("SENSOR_SECRET")) {
+            (Ok(sensor), Ok(secret)) if !sensor.is_empty() && !secret.is_empty() => Some(Self::new(sensor, secret)),
+            _ => None,
+        }
+    }
+
+    /// Headers for one request. Every call uses a fresh nonce, so retries
+    /// must be signed again.
+    pub fn sign(&self, method: &str, path: &str, body: &[u8]) -> [(&'static str, String); 4] {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+        let signing_string = format!(
+            "{}\n{}\n{}\n{}\n{}",
+            method,
+            path,
+            timestamp,
+            nonce,
+            hex::encode(Sha256::digest(body))
+        );
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(signing_string.as_bytes());
+
+        [
+            ("X-Brute-Sensor", self.sensor.clone()),
+            ("X-Brute-Timestamp", timestamp.to_string()),
+            ("X-Brute-Nonce", nonce),
+            ("X-Brute-Signature", hex::encode(mac.finalize().into_bytes())),
+        ]
+    }
+}
@@ -0,0 +1,384 @@
+/////////////
+// TARPIT //
+///////////
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{error, info};
+use serde::Serialize;
+use tokio::sync::Semaphore;
+
+use crate::config::env_or;
+
+#[derive(Clone, Debug)]
+pub struct TarpitConfig {
+    pub enabled: bool,
+    /// Delay after the first failure, doubled for every failure after that.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Connections held at once, past this they are served without delay.
+    pub max_held: usize,
+    /// Sources that stay quiet this long start over.
+    pub forget_after: Duration,
+    /// Sources remembered at once, the quietest is forgotten past this.
+    pub max_tracked: usize,
+    /// File the stats are written to as JSON, they're only logged without it.
+    pub stats_path: Option<PathBuf>,
+}
+
+impl TarpitConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let max_held = env_or("TARPIT_MAX_HELD", 256)?;
+        if max_held > Semaphore::MAX_PERMITS {
+            return Err(anyhow::anyhow!(
+                "invalid TARPIT_MAX_HELD: at most {} connections can be held",
+                Semaphore::MAX_PERMITS
+            ));
+        }
+        let max_tracked = env_or("TARPIT_MAX_TRACKED", 100_000)?;
+        if max_tracked == 0 {
+            return Err(anyhow::anyhow!("invalid TARPIT_MAX_TRACKED: at least one source has to be tracked"));
+        }
+        Ok(Self {
+            enabled: env_or("TARPIT_ENABLED", false)?,
+            base_delay: Duration::from_millis(env_or("TARPIT_BASE_DELAY_MS", 1_000)?),
+            max_delay: Duration::from_millis(env_or("TARPIT_MAX_DELAY_MS", 30_000)?),
+            max_held,
+            forget_after: Duration::from_secs(env_or("TARPIT_FORGET_AFTER_SECS", 3_600)?),
+            max_tracked,
+            stats_path: std::env::var("TARPIT_STATS_PATH").ok().map(PathBuf::from),
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TarpitStats {
+    /// Connections currently sleeping in the tarpit.
+    pub held: usize,
+    /// Sources with recorded failures.
+    pub tracked: usize,
+    pub total_held: u64,
+    /// Times a delay was skipped because `max_held` was reached.
+    pub total_skipped: u64,
+    pub total_delay_ms: u64,
+}
+
+#[derive(Debug)]
+struct Offender {
+    failures: u32,
+    last_seen: Instant,
+}
+
+/// Sources with failures, forgotten in the order they were last seen.
+#[derive(Debug, Default)]
+struct Offenders {
+    by_ip: HashMap<IpAddr, Offender>,
+    by_last_seen: BTreeSet<(Instant, IpAddr)>,
+}
+
+impl Offenders {
+    /// Failure count of `ip` after one more at `now`, `forget_after`
+    /// restarts it and past `max_tracked` the quietest source is forgotten.
+    fn record(&mut self, ip: IpAddr, now: Instant, forget_after: Duration, max_tracked: usize) -> u32 {
+        let failures = match self.by_ip.remove(&ip) {
+            Some(offender) => {
+                self.by_last_seen.remove(&(offender.last_seen, ip));
+                match now.duration_since(offender.last_seen) > forget_after {
+                    true => 0,
+                    false => offender.failures,
+                }
+            }
+            None => 0,
+        }
+        .saturating_add(1);
+        while self.by_ip.len() >= max_tracked {
+            let Some((_, quietest)) = self.by_last_seen.pop_first() else {
+                break;
+            };
+            self.by_ip.remove(&quietest);
+        }
+        self.by_ip.insert(ip, Offender { failures, last_seen: now });
+        self.by_last_seen.insert((now, ip));
+        failures
+    }
+
+    fn prune(&mut self, forget_after: Duration) {
+        while let Some(&(last_seen, ip)) = self.by_last_seen.first() {
+            if last_seen.elapsed() <= forget_after {
+                break;
+            }
+            self.by_last_seen.pop_first();
+            self.by_ip.remove(&ip);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    config: TarpitConfig,
+    offenders: Mutex<Offenders>,
+    slots: Semaphore,
+    total_held: AtomicU64,
+    total_skipped: AtomicU64,
+    total_delay_ms: AtomicU64,
+}
+
+/// Per-source adaptive delays, shared by every listener.
+#[derive(Clone, Debug)]
+pub struct Tarpit {
+    inner: Arc<Inner>,
+}
+
+impl Tarpit {
+    pub fn new(mut config: TarpitConfig) -> Self {
+        // the semaphore panics past its maximum.
+        config.max_held = config.max_held.min(Semaphore::MAX_PERMITS);
+        Self {
+            inner: Arc::new(Inner {
+                slots: Semaphore::new(config.max_held),
+                config,
+                offenders: Mutex::new(Offenders::default()),
+                total_held: AtomicU64::new(0),
+                total_skipped: AtomicU64::new(0),
+                total_delay_ms: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self::new(TarpitConfig::from_env()?))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.config.enabled
+    }
+
+    /// Delay for a source with its current failure count. Zero until it fails once.
+    pub fn delay_for(&self, ip: IpAddr) -> Duration {
+        if !self.is_enabled() {
+            return Duration::ZERO;
+        }
+        let offenders = self.inner.offenders.lock().unwrap();
+        match offenders.by_ip.get(&ip) {
+            Some(offender) => self.delay_after(offender.failures),
+            None => Duration::ZERO,
+        }
+    }
+
+    fn delay_after(&self, failures: u32) -> Duration {
+        let config = &self.inner.config;
+        if failures == 0 {
+            return Duration::ZERO;
+        }
+        let factor = 1u32.checked_shl(failures - 1).unwrap_or(u32::MAX);
+        config
+            .base_delay
+            .checked_mul(factor)
+            .unwrap_or(config.max_delay)
+            .min(config.max_delay)
+    }
+
+    /// Records a failed attempt and returns the delay the reply should get.
+    pub fn record_failure(&self, ip: IpAddr) -> Duration {
+        if !self.is_enabled() {
+            return Duration::ZERO;
+        }
+        let config = &self.inner.config;
+        let failures = self.inner.offenders.lock().unwrap().record(
+            ip,
+            Instant::now(),
+            config.forget_after,
+            config.max_tracked,
+        );
+        self.delay_after(failures)
+    }
+
+    /// Sleeps for `delay` while holding one of the `max_held` slots. When
+    /// every slot is taken the delay is skipped so held connections stay cheap.
+    pub async fn hold(&self, delay: Duration) {
+        if delay.is_zero() {
+            return;
+        }
+        let Ok(_permit) = self.inner.slots.try_acquire() else {
+            self.inner.total_skipped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        self.inner.total_held.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(delay).await;
+        self.inner
+            .total_delay_ms
+            .fetch_add(delay.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Forgets sources that have been quiet longer than `forget_after`.
+    pub fn prune(&self) {
+        self.inner.offenders.lock().unwrap().prune(self.inner.config.forget_after);
+    }
+
+    pub fn stats(&self) -> TarpitStats {
+        TarpitStats {
+            held: self.inner.config.max_held - self.inner.slots.available_permits(),
+            tracked: self.inner.offenders.lock().unwrap().by_ip.len(),
+            total_held: self.inner.total_held.load(Ordering::Relaxed),
+            total_skipped: self.inner.total_skipped.load(Ordering::Relaxed),
+            total_delay_ms: self.inner.total_delay_ms.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Writes the stats through a temporary file so readers never see half of them.
+    pub fn write_stats(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&self.stats())?)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Prunes old sources, then logs the stats and writes them to
+    /// `stats_path` every `interval`.
+    pub async fn report_stats(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.prune();
+            if let Some(path) = &self.inner.config.stats_path {
+                if let Err(e) = self.write_stats(path) {
+                    error!("Failed to write tarpit stats to {}: {}", path.display(), e);
+                }
+            }
+            let stats = self.stats();
+            info!(
+                "Tarpit: {} held now, {} sources tracked, {} held in total ({}s wasted), {} skipped at capacity.",
+                stats.held,
+                stats.tracked,
+                stats.total_held,
+                stats.total_delay_ms / 1000,
+                stats.total_skipped
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> TarpitConfig {
+        TarpitConfig {
+            enabled: true,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            max_held: 1,
+            forget_after: Duration::from_secs(3_600),
+            max_tracked: 100,
+            stats_path: None,
+        }
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([203, 0, 113, last])
+    }
+
+    #[test]
+    fn delays_double_per_failure_up_to_the_maximum() {
+        let tarpit = Tarpit::new(config());
+        assert_eq!(tarpit.delay_for(ip(1)), Duration::ZERO);
+        let delays: Vec<_> = (0..6).map(|_| tarpit.record_failure(ip(1)).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10]);
+        assert_eq!(tarpit.delay_for(ip(1)), Duration::from_secs(10));
+        // other sources are unaffected.
+        assert_eq!(tarpit.delay_for(ip(2)), Duration::ZERO);
+    }
+
+    #[test]
+    fn many_failures_do_not_overflow() {
+        let tarpit = Tarpit::new(config());
+        for _ in 0..100 {
+            tarpit.record_failure(ip(1));
+        }
+        assert_eq!(tarpit.delay_for(ip(1)), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn quiet_sources_start_over() {
+        let tarpit = Tarpit::new(TarpitConfig {
+            forget_after: Duration::from_millis(20),
+            ..config()
+        });
+        tarpit.record_failure(ip(1));
+        tarpit.record_failure(ip(1));
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(tarpit.record_failure(ip(1)), Duration::from_secs(1));
+
+        std::thread::sleep(Duration::from_millis(40));
+        tarpit.prune();
+        assert_eq!(tarpit.stats().tracked, 0);
+    }
+
+    #[test]
+    fn the_quietest_sources_are_forgotten_past_the_limit() {
+        let tarpit = Tarpit::new(TarpitConfig { max_tracked: 2, ..config() });
+        tarpit.record_failure(ip(1));
+        tarpit.record_failure(ip(2));
+        tarpit.record_failure(ip(1));
+        tarpit.record_failure(ip(3));
+        assert_eq!(tarpit.stats().tracked, 2);
+        assert_eq!(tarpit.delay_for(ip(1)), Duration::from_secs(2));
+        assert_eq!(tarpit.delay_for(ip(2)), Duration::ZERO);
+        assert_eq!(tarpit.delay_for(ip(3)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn disabled_never_delays() {
+        let tarpit = Tarpit::new(TarpitConfig { enabled: false, ..config() });
+        assert_eq!(tarpit.record_failure(ip(1)), Duration::ZERO);
+        assert_eq!(tarpit.delay_for(ip(1)), Duration::ZERO);
+        assert_eq!(tarpit.stats().tracked, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delays_are_skipped_once_every_slot_is_held() {
+        let tarpit = Tarpit::new(config());
+        let held = tokio::spawn({
+            let tarpit = tarpit.clone();
+            async move { tarpit.hold(Duration::from_secs(5)).await }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(tarpit.stats().held, 1);
+
+        let started = tokio::time::Instant::now();
+        tarpit.hold(Duration::from_secs(5)).await;
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        held.await.unwrap();
+        let stats = tarpit.stats();
+        assert_eq!((stats.held, stats.total_held, stats.total_skipped), (0, 1, 1));
+        assert_eq!(stats.total_delay_ms, 5_000);
+    }
+
+    #[test]
+    fn oversized_max_held_is_clamped() {
+        let tarpit = Tarpit::new(TarpitConfig { max_held: usize::MAX, ..config() });
+        assert_eq!(tarpit.stats().held, 0);
+    }
+
+    #[test]
+    fn stats_are_written_as_json() {
+        let tarpit = Tarpit::new(config());
+        tarpit.record_failure(ip(1));
+        let path = std::env::temp_dir().join(format!("tarpit-{}.json", uuid::Uuid::new_v4()));
+        tarpit.write_stats(&path).unwrap();
+        let written: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written["tracked"], 1);
+        assert_eq!(written["held"], 0);
+    }
+}
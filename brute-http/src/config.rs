@@ -1,11 +1,570 @@
+use std::{
+    env::{args_os, var_os},
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use clap::{CommandFactory, FromArgMatches, Subcommand, ValueEnum};
+use ipnetwork::IpNetwork;
+use regex::Regex;
+use serde::{Serialize, Serializer};
+use url::Url;
+
+use crate::http::{auth::ApiKey, signature::SensorSecret};
+
+/// Printed by `--print-config` in place of secrets.
+const REDACTED: &str = "<redacted>";
+
 /// The configuration  parameters in order to run Brute successfully.
-#[derive(clap::Parser)]
+///
+/// Every setting is read from its flag, then its env var, then the
+/// `--config` file and finally its default.
+#[derive(clap::Parser, Serialize)]
 pub struct Config {
+    /// TOML file with any of these settings, keyed by their snake_case names.
+    #[clap(long, env = "CONFIG_FILE")]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// Print the resolved configuration with secrets redacted and exit.
+    #[clap(long)]
+    #[serde(skip)]
+    pub print_config: bool,
+
+    /// Runs a one-off job instead of the server.
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Command>,
+
     /// Database connection string.
     #[clap(long, env)]
+    #[serde(serialize_with = "redact_url")]
     pub database_url: String,
 
+    /// Directory of the SQL migrations, `migrations` in docker and
+    /// `../migrations` otherwise.
+    #[clap(long, env)]
+    pub migrations_directory: Option<PathBuf>,
+
+    /// Whether Brute runs from the docker image.
+    #[clap(long, env, default_value_t = false)]
+    pub running_in_docker: bool,
+
+    ////////////////
+    // LISTENERS //
+    //////////////
+    /// Address of the plain HTTP listener.
+    #[clap(long, env, default_value = "0.0.0.0:7000")]
+    pub listen_address: SocketAddr,
+
+    /// Address of the TLS listener.
+    #[clap(long, env, default_value = "0.0.0.0:7443")]
+    pub listen_address_tls: SocketAddr,
+
+    /// Comma separated origins allowed by CORS, every origin is allowed when
+    /// empty.
+    #[clap(long, env, value_delimiter = ',')]
+    pub cors_origins: Vec<String>,
+
+    /// Seconds to wait for in-flight requests and reports on SIGTERM/SIGINT.
+    #[clap(long, env, default_value_t = 30)]
+    pub shutdown_timeout: u64,
+
+    //////////
+    // TLS //
+    ////////
+    /// Directory holding `cert.pem` and `key.pem` for the TLS listener.
+    #[clap(long, env, default_value = "certs")]
+    pub certs_directory: PathBuf,
+
+    /// Generate a self-signed certificate for localhost when the certs
+    /// directory has none, meant for development.
+    #[clap(long, env, default_value_t = false)]
+    pub self_signed_cert: bool,
+
+    /// PEM bundle of CAs whose client certificates authenticate sensors on
+    /// the TLS listener, the certificate's common name is the id of a
+    /// registered sensor.
+    #[clap(long, env)]
+    pub client_ca: Option<PathBuf>,
+
+    ///////////
+    // AUTH //
+    /////////
+    /// Shared ingest key.
+    #[clap(long, env)]
+    #[serde(serialize_with = "redact")]
+    pub bearer_token: String,
+
+    /// Comma separated `role:key` pairs, roles are `ingest`, `read` and
+    /// `admin`. Stats stay public until a read key is set.
+    #[clap(long, env, value_delimiter = ',')]
+    #[serde(serialize_with = "redact_api_keys")]
+    pub api_keys: Vec<ApiKey>,
+
+    /// Per-sensor HMAC secrets as comma separated `sensor_id:secret` pairs.
+    #[clap(long, env, value_delimiter = ',')]
+    #[serde(serialize_with = "redact_sensor_secrets")]
+    pub sensor_secrets: Vec<SensorSecret>,
+
+    /// Secret the signing keys of registered sensors are derived from, kept
+    /// out of the database so a leak of it can't be used to sign requests.
+    /// Registered sensors can only sign when it is set.
+    #[clap(long, env)]
+    #[serde(serialize_with = "redact_option")]
+    pub signing_key: Option<String>,
+
+    /// Seconds a signed request's timestamp may be off, nonces are kept as long.
+    #[clap(long, env, default_value_t = 300)]
+    pub signature_window: u64,
+
+    /// Reject ingest requests that only carry the bearer token.
+    #[clap(long, env, default_value_t = false)]
+    pub require_signature: bool,
+
+    /// Seconds between reloads of the sensor table, so changes made through
+    /// another instance are picked up.
+    #[clap(long, env, default_value_t = 60)]
+    pub sensor_refresh_interval: u64,
+
+    /////////////
+    // LIMITS //
+    ///////////
+    /// Comma separated CIDRs (IPv4 and IPv6) that are dropped at ingest.
+    #[clap(
+        long,
+        env,
+        value_delimiter = ',',
+        default_value = "10.0.0.0/8,172.16.0.0/12,192.168.0.0/16,127.0.0.0/8,::1/128,fc00::/7,fe80::/10"
+    )]
+    pub ignore_cidrs: Vec<IpNetwork>,
+
+    /// Comma separated CIDRs sensors may report from, matched against the
+    /// address of the connection. Any address when unset.
+    #[clap(long, env, value_delimiter = ',')]
+    pub sensor_cidrs: Vec<IpNetwork>,
+
+    /// Most rows a stats endpoint returns.
+    #[clap(long, env, default_value_t = 100)]
+    pub max_limit: usize,
+
+    /// Most attempts accepted by one batch request.
+    #[clap(long, env, default_value_t = 100)]
+    pub max_batch_size: usize,
+
+    ////////////
+    // STATS //
+    //////////
+    /// Timezone the hourly, daily, weekly and yearly counters are aligned
+    /// to, any name the database knows like `Europe/Berlin`. Existing
+    /// counters are realigned at startup when it changes.
+    #[clap(long, env, default_value = "UTC")]
+    pub stats_timezone: String,
+
+    /////////////////
+    // ENRICHMENT //
+    ///////////////
+    /// Where attempts get their location and network details from.
+    #[clap(long, env, value_enum, default_value_t = EnrichmentProvider::Ipinfo)]
+    pub enrichment_provider: EnrichmentProvider,
+
     /// API token for IPinfo.io service.
     #[clap(long, env)]
-    pub ipinfo_token: String
-}
\ No newline at end of file
+    #[serde(serialize_with = "redact_option")]
+    pub ipinfo_token: Option<String>,
+
+    /// GeoLite2 or DB-IP City database for the mmdb providers.
+    #[clap(long, env)]
+    pub mmdb_city: Option<PathBuf>,
+
+    /// GeoLite2 or DB-IP ASN database, fills the ASN and org fields.
+    #[clap(long, env)]
+    pub mmdb_asn: Option<PathBuf>,
+
+    /// Also look up the PTR name of every IP and check that it resolves
+    /// back to the IP.
+    #[clap(long, env, default_value_t = false)]
+    pub rdns: bool,
+
+    /// DNS server (ip:port) for the rdns lookups, the system's resolvers
+    /// when unset.
+    #[clap(long, env)]
+    pub rdns_resolver: Option<SocketAddr>,
+
+    /// Seconds an IP's details are reused before they're looked up again.
+    #[clap(long, env, default_value_t = 604_800)]
+    pub enrichment_cache_ttl: u64,
+
+    /// IPs whose details are also kept in memory.
+    #[clap(long, env, default_value_t = 10_000)]
+    pub enrichment_cache_size: usize,
+
+    /// Attempts enriched concurrently.
+    #[clap(long, env, default_value_t = 4)]
+    pub enrichment_workers: usize,
+
+    /// Most lookups a second sent to the provider, unlimited when unset.
+    #[clap(long, env)]
+    pub enrichment_rate_limit: Option<u32>,
+
+    ////////////////
+    // RETENTION //
+    //////////////
+    /// Days raw attempts are kept, forever when unset. Counters are kept,
+    /// re-enrichment doesn't rebuild them while it is set.
+    #[clap(long, env)]
+    pub retention_days: Option<u32>,
+}
+
+/// One-off jobs.
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Look up the IPs of past attempts again and rebuild every counter.
+    Reenrich {
+        /// Start of the range in milliseconds since the epoch, the first
+        /// attempt when unset.
+        #[clap(long)]
+        from: Option<i64>,
+
+        /// End of the range (exclusive) in milliseconds since the epoch,
+        /// now when unset.
+        #[clap(long)]
+        to: Option<i64>,
+
+        /// Continue an interrupted or failed job instead of starting one.
+        #[clap(long, conflicts_with_all = ["from", "to"])]
+        resume: Option<String>,
+    },
+}
+
+/// Enrichment backends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EnrichmentProvider {
+    /// The ipinfo.io API, needs `ipinfo_token`.
+    Ipinfo,
+    /// Local MMDB files, needs `mmdb_city`.
+    Mmdb,
+    /// The MMDB files, then ipinfo for IPs they don't know.
+    MmdbIpinfo,
+}
+
+impl EnrichmentProvider {
+    pub fn uses_ipinfo(self) -> bool {
+        matches!(self, Self::Ipinfo | Self::MmdbIpinfo)
+    }
+
+    pub fn uses_mmdb(self) -> bool {
+        matches!(self, Self::Mmdb | Self::MmdbIpinfo)
+    }
+}
+
+/// Request limits the HTTP handlers enforce.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub max_limit: usize,
+    pub max_batch_size: usize,
+}
+
+impl Config {
+    /// Parses and validates the configuration. A value that doesn't parse
+    /// exits with clap's usage error, a bad config file or a value
+    /// `validate` rejects is returned as the error.
+    pub fn load() -> Result<Self, String> {
+        let matches = command(config_path().as_deref())?.get_matches();
+        let config = Config::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks what the parsers can't, like ranges and combinations.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.listen_address == self.listen_address_tls {
+            return Err("listen_address and listen_address_tls must differ.".to_string());
+        }
+        let origin = Regex::new(r"^https?://[^/\s]+$").unwrap();
+        if let Some(invalid) = self.cors_origins.iter().find(|o| !origin.is_match(o)) {
+            return Err(format!(
+                "cors_origins: '{}' isn't an origin like https://example.com.",
+                invalid
+            ));
+        }
+        if let Some(client_ca) = self.client_ca.as_ref().filter(|path| !path.is_file()) {
+            return Err(format!("client_ca: {} doesn't exist.", client_ca.display()));
+        }
+        if self.bearer_token.is_empty() {
+            return Err("bearer_token can't be empty.".to_string());
+        }
+        if self.signing_key.as_ref().is_some_and(|key| key.len() < 32) {
+            return Err("signing_key must be at least 32 characters.".to_string());
+        }
+        for (name, value) in [
+            ("signature_window", self.signature_window),
+            ("sensor_refresh_interval", self.sensor_refresh_interval),
+            ("max_limit", self.max_limit as u64),
+            ("max_batch_size", self.max_batch_size as u64),
+            ("enrichment_cache_size", self.enrichment_cache_size as u64),
+            ("enrichment_workers", self.enrichment_workers as u64),
+        ] {
+            if value == 0 {
+                return Err(format!("{} must be at least 1.", name));
+            }
+        }
+        if self.enrichment_provider.uses_ipinfo() && self.ipinfo_token.is_none() {
+            return Err("ipinfo_token is required by the ipinfo enrichment providers.".to_string());
+        }
+        if self.enrichment_provider.uses_mmdb() {
+            let Some(mmdb_city) = &self.mmdb_city else {
+                return Err("mmdb_city is required by the mmdb enrichment providers.".to_string());
+            };
+            for path in [Some(mmdb_city), self.mmdb_asn.as_ref()].into_iter().flatten() {
+                if !path.is_file() {
+                    return Err(format!("{} doesn't exist.", path.display()));
+                }
+            }
+        }
+        if self.enrichment_rate_limit == Some(0) {
+            return Err("enrichment_rate_limit must be at least 1, leave it unset for no limit.".to_string());
+        }
+        if self.retention_days == Some(0) {
+            return Err("retention_days must be at least 1, leave it unset to keep attempts.".to_string());
+        }
+        Ok(())
+    }
+
+    /// The configuration as TOML, usable as a `--config` file.
+    pub fn redacted(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|e| format!("Failed to print the configuration: {}", e))
+    }
+
+    pub fn migrations_directory(&self) -> PathBuf {
+        match &self.migrations_directory {
+            Some(directory) => directory.clone(),
+            None if self.running_in_docker => PathBuf::from("migrations"),
+            None => Path::new("..").join("migrations"),
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_limit: self.max_limit,
+            max_batch_size: self.max_batch_size,
+        }
+    }
+}
+
+/// `--config`/`--config=`, checked before clap runs so the file can supply
+/// defaults.
+fn config_path() -> Option<PathBuf> {
+    let mut args = args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    var_os("CONFIG_FILE").map(PathBuf::from)
+}
+
+/// The clap command with the settings of the config file, if any, as its
+/// defaults.
+fn command(path: Option<&Path>) -> Result<clap::Command, String> {
+    let mut command = Config::command();
+    let Some(path) = path else {
+        return Ok(command);
+    };
+    for (key, value) in read_file(path)? {
+        if key == "config" || !command.get_arguments().any(|arg| arg.get_id() == key.as_str()) {
+            return Err(format!("{}: unknown setting '{}'.", path.display(), key));
+        }
+        // below flags and env vars, above the built-in defaults.
+        command = command.mut_arg(key, |arg| arg.default_value(value).required(false));
+    }
+    Ok(command)
+}
+
+/// Flattens the file into strings clap parses like env vars, arrays are
+/// joined with commas.
+fn read_file(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let table: toml::Table = contents
+        .parse()
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                toml::Value::Array(values) => values
+                    .iter()
+                    .map(scalar)
+                    .collect::<Option<Vec<_>>>()
+                    .map(|values| values.join(",")),
+                value => scalar(&value),
+            };
+            value
+                .map(|value| (key.clone(), value))
+                .ok_or_else(|| format!("{}: '{}' must be a string, number, boolean or an array of them.", path.display(), key))
+        })
+        .collect()
+}
+
+fn scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+////////////////
+// REDACTION //
+//////////////
+fn redact<T, S: Serializer>(_: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(REDACTED)
+}
+
+fn redact_option<T, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(_) => serializer.serialize_str(REDACTED),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn redact_url<S: Serializer>(url: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&redacted_url(url))
+}
+
+/// Keeps the host and database, hides the password whether it is in the
+/// userinfo or a `password` query parameter. Anything that doesn't parse
+/// as a URL is hidden completely.
+fn redacted_url(url: &str) -> String {
+    let Ok(mut url) = Url::parse(url) else {
+        return REDACTED.to_string();
+    };
+    if url.password().is_some() && url.set_password(Some(REDACTED)).is_err() {
+        return REDACTED.to_string();
+    }
+    if url.query_pairs().any(|(key, _)| is_password(&key)) {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(key, value)| match is_password(&key) {
+                true => (key.into_owned(), REDACTED.to_string()),
+                false => (key.into_owned(), value.into_owned()),
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    // the marker is percent-encoded like any other value.
+    url.to_string().replace("%3Credacted%3E", REDACTED)
+}
+
+/// `password`, `sslpassword` and the like.
+fn is_password(key: &str) -> bool {
+    key.to_ascii_lowercase().contains("password")
+}
+
+fn redact_api_keys<S: Serializer>(keys: &[ApiKey], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(keys.iter().map(|key| format!("{}:{}", key.role, REDACTED)))
+}
+
+fn redact_sensor_secrets<S: Serializer>(secrets: &[SensorSecret], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(secrets.iter().map(|secret| format!("{}:{}", secret.sensor, REDACTED)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config file with `contents`, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("brute-config-{}-{}", std::process::id(), name));
+            fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn reads_files_as_strings() {
+        let file = TempFile::new(
+            "read.toml",
+            "database_url = \"postgres://db/brute\"\nmax_limit = 50\nrdns = true\ncors_origins = [\"https://a.com\", \"https://b.com\"]\n",
+        );
+        let mut settings = read_file(&file.0).unwrap();
+        settings.sort();
+        assert_eq!(
+            settings,
+            [
+                ("cors_origins".to_string(), "https://a.com,https://b.com".to_string()),
+                ("database_url".to_string(), "postgres://db/brute".to_string()),
+                ("max_limit".to_string(), "50".to_string()),
+                ("rdns".to_string(), "true".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let nested = TempFile::new("nested.toml", "[database]\nurl = \"postgres://db\"\n");
+        assert!(read_file(&nested.0).unwrap_err().contains("'database' must be"));
+        let broken = TempFile::new("broken.toml", "max_limit = \n");
+        assert!(read_file(&broken.0).unwrap_err().starts_with("Failed to parse"));
+        assert!(read_file(Path::new("/nonexistent/brute.toml")).unwrap_err().starts_with("Failed to read"));
+
+        let unknown = TempFile::new("unknown.toml", "max_limits = 50\n");
+        assert!(command(Some(&unknown.0)).unwrap_err().contains("unknown setting 'max_limits'"));
+        let recursive = TempFile::new("recursive.toml", "config = \"other.toml\"\n");
+        assert!(command(Some(&recursive.0)).unwrap_err().contains("unknown setting 'config'"));
+    }
+
+    #[test]
+    fn flags_beat_env_vars_beat_the_file() {
+        let file = TempFile::new(
+            "precedence.toml",
+            "bearer_token = \"file\"\nmax_limit = 10\nmax_batch_size = 20\nsignature_window = 30\n",
+        );
+        // only this test sets them.
+        std::env::set_var("MAX_BATCH_SIZE", "21");
+        std::env::set_var("SIGNATURE_WINDOW", "31");
+        let matches = command(Some(&file.0))
+            .unwrap()
+            .try_get_matches_from(["brute-http", "--database-url", "postgres://db/brute", "--signature-window", "32"])
+            .unwrap();
+        let config = Config::from_arg_matches(&matches).unwrap();
+        assert_eq!(config.bearer_token, "file");
+        assert_eq!(config.max_limit, 10);
+        assert_eq!(config.max_batch_size, 21);
+        assert_eq!(config.signature_window, 32);
+        // untouched settings keep their built-in defaults.
+        assert_eq!(config.enrichment_workers, 4);
+    }
+
+    #[test]
+    fn redacts_database_passwords() {
+        for (url, redacted) in [
+            ("postgres://u:hunter2@db/brute", "postgres://u:<redacted>@db/brute"),
+            ("postgres://u:p@ss@db:5432/brute", "postgres://u:<redacted>@db:5432/brute"),
+            ("postgres://db/brute?user=u&password=hunter2", "postgres://db/brute?user=u&password=<redacted>"),
+            ("postgres://u:x@db/brute?sslpassword=y&sslmode=require", "postgres://u:<redacted>@db/brute?sslpassword=<redacted>&sslmode=require"),
+            ("postgres://u@db/brute", "postgres://u@db/brute"),
+            ("postgres://db/brute?host=/var/run/postgresql", "postgres://db/brute?host=/var/run/postgresql"),
+            ("host=db password=hunter2", "<redacted>"),
+        ] {
+            assert_eq!(redacted_url(url), redacted, "{}", url);
+        }
+    }
+}
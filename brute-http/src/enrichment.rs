@@ -0,0 +1,911 @@
+/////////////////
+// ENRICHMENT //
+///////////////
+
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    num::{NonZeroU32, NonZeroUsize},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use hickory_resolver::{
+    config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    error::{ResolveError, ResolveErrorKind},
+    TokioAsyncResolver,
+};
+use ipinfo::{IpErrorKind, IpInfo, IpInfoConfig};
+use log::{info, warn};
+use lru::LruCache;
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use sqlx::{postgres::PgRow, Executor, FromRow, PgConnection, Pool, Postgres, Row};
+use tokio::{sync::OnceCell, time::Instant};
+
+use crate::model::Enrichment;
+
+/// A source of location and network details for an IP.
+#[async_trait]
+pub trait Enricher: Send + Sync {
+    /// Shown in the logs.
+    fn name(&self) -> &'static str;
+
+    async fn enrich(&self, ip: IpAddr) -> anyhow::Result<Enriched>;
+}
+
+/// What an enricher found and the provider that answered, which is stored
+/// with the details. Enrichers that combine others pass on whichever one
+/// actually answered.
+#[derive(Debug)]
+pub struct Enriched {
+    pub provider: &'static str,
+    /// `None` when the provider knows nothing about the IP.
+    pub enrichment: Option<Enrichment>,
+}
+
+/// Returned by an enricher when its provider asks to slow down.
+#[derive(Debug)]
+pub struct RateLimited;
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rate limit exceeded")
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/////////////
+// IPINFO //
+///////////
+/// The ipinfo.io API.
+pub struct IpinfoEnricher {
+    token: Option<String>,
+    /// A lookup needs a client to itself, concurrent lookups each take one
+    /// and put it back when they're done.
+    idle: Mutex<Vec<IpInfo>>,
+}
+
+impl IpinfoEnricher {
+    pub fn new(token: Option<String>) -> Result<Self, String> {
+        let client = Self::connect(token.clone())?;
+        Ok(Self {
+            token,
+            idle: Mutex::new(vec![client]),
+        })
+    }
+
+    fn connect(token: Option<String>) -> Result<IpInfo, String> {
+        let config = IpInfoConfig {
+            token,
+            ..Default::default()
+        };
+        IpInfo::new(config).map_err(|e| format!("Failed to create IpInfo client: {}", e))
+    }
+}
+
+#[async_trait]
+impl Enricher for IpinfoEnricher {
+    fn name(&self) -> &'static str {
+        "ipinfo"
+    }
+
+    async fn enrich(&self, ip: IpAddr) -> anyhow::Result<Enriched> {
+        let idle = self.idle.lock().unwrap().pop();
+        let mut client = match idle {
+            Some(client) => client,
+            None => Self::connect(self.token.clone()).map_err(anyhow::Error::msg)?,
+        };
+        let details = client.lookup(&ip.to_string()).await;
+        self.idle.lock().unwrap().push(client);
+        let details = details.map_err(|e| match e.kind() {
+            IpErrorKind::RateLimitExceededError => anyhow::Error::new(RateLimited),
+            _ => e.into(),
+        })?;
+        let asn = details.asn.as_ref();
+        let company = details.company.as_ref();
+        let privacy = details.privacy.as_ref();
+        let abuse = details.abuse.as_ref();
+        let domains = details.domains.as_ref();
+        let enrichment = Enrichment {
+            asn: asn.map(|a| a.asn.clone()),
+            asn_name: asn.map(|a| a.name.clone()),
+            asn_domain: asn.map(|a| a.domain.clone()),
+            asn_route: asn.map(|a| a.route.clone()),
+            asn_type: asn.map(|a| a.asn_type.clone()),
+            company_name: company.map(|c| c.name.clone()),
+            company_domain: company.map(|c| c.domain.clone()),
+            company_type: company.map(|c| c.company_type.clone()),
+            vpn: privacy.map(|p| p.vpn),
+            proxy: privacy.map(|p| p.proxy),
+            tor: privacy.map(|p| p.tor),
+            relay: privacy.map(|p| p.relay),
+            hosting: privacy.map(|p| p.hosting),
+            service: privacy.map(|p| p.service.clone()),
+            abuse_address: abuse.map(|a| a.address.clone()),
+            abuse_country: abuse.map(|a| a.country.clone()),
+            abuse_email: abuse.map(|a| a.email.clone()),
+            abuse_name: abuse.map(|a| a.name.clone()),
+            abuse_network: abuse.map(|a| a.network.clone()),
+            abuse_phone: abuse.map(|a| a.phone.clone()),
+            domain_ip: domains.and_then(|d| d.ip.clone()),
+            domain_total: domains.map(|d| d.total as i64),
+            domains: domains.map(|d| d.domains.clone()),
+            hostname: details.hostname,
+            city: details.city,
+            region: details.region,
+            country: details.country,
+            loc: details.loc,
+            org: details.org,
+            postal: details.postal,
+            timezone: details.timezone.unwrap_or_default(),
+            ..Default::default()
+        };
+        Ok(Enriched {
+            provider: self.name(),
+            enrichment: Some(enrichment),
+        })
+    }
+}
+
+///////////
+// MMDB //
+/////////
+/// GeoLite2 or DB-IP City and, optionally, ASN databases on disk.
+pub struct MmdbEnricher {
+    city: Reader<Vec<u8>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl MmdbEnricher {
+    pub fn open(city: &Path, asn: Option<&Path>) -> Result<Self, String> {
+        let open = |path: &Path| {
+            let reader = Reader::open_readfile(path)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            info!(
+                "Loaded {} ({}) from {}.",
+                reader.metadata.database_type,
+                reader.metadata.build_epoch,
+                path.display()
+            );
+            Ok::<_, String>(reader)
+        };
+        Ok(Self {
+            city: open(city)?,
+            asn: asn.map(open).transpose()?,
+        })
+    }
+}
+
+/// Lookups of addresses that aren't in the database aren't errors.
+fn found<T>(result: Result<T, MaxMindDBError>) -> anyhow::Result<Option<T>> {
+    match result {
+        Ok(record) => Ok(Some(record)),
+        Err(MaxMindDBError::AddressNotFoundError(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[async_trait]
+impl Enricher for MmdbEnricher {
+    fn name(&self) -> &'static str {
+        "mmdb"
+    }
+
+    async fn enrich(&self, ip: IpAddr) -> anyhow::Result<Enriched> {
+        let city = found(self.city.lookup::<geoip2::City>(ip))?;
+        let asn = match &self.asn {
+            Some(reader) => found(reader.lookup::<geoip2::Asn>(ip))?,
+            None => None,
+        };
+        if city.is_none() && asn.is_none() {
+            return Ok(Enriched {
+                provider: self.name(),
+                enrichment: None,
+            });
+        }
+
+        let mut enrichment = Enrichment::default();
+        if let Some(city) = city {
+            let english = |names: Option<std::collections::BTreeMap<&str, &str>>| {
+                names.and_then(|names| names.get("en").map(|name| name.to_string()))
+            };
+            enrichment.city = english(city.city.and_then(|c| c.names)).unwrap_or_default();
+            enrichment.region = english(
+                city.subdivisions
+                    .and_then(|s| s.into_iter().next())
+                    .and_then(|s| s.names),
+            )
+            .unwrap_or_default();
+            enrichment.country = city
+                .country
+                .and_then(|c| c.iso_code)
+                .unwrap_or_default()
+                .to_string();
+            enrichment.postal = city.postal.and_then(|p| p.code).map(str::to_string);
+            if let Some(location) = city.location {
+                if let (Some(latitude), Some(longitude)) = (location.latitude, location.longitude) {
+                    // same format as ipinfo.
+                    enrichment.loc = format!("{:.4},{:.4}", latitude, longitude);
+                }
+                enrichment.timezone = location.time_zone.unwrap_or_default().to_string();
+            }
+        }
+        if let Some(asn) = asn {
+            if let Some(number) = asn.autonomous_system_number {
+                let name = asn.autonomous_system_organization.unwrap_or_default();
+                enrichment.asn = Some(format!("AS{}", number));
+                enrichment.asn_name = Some(name.to_string());
+                enrichment.org = Some(format!("AS{} {}", number, name));
+            }
+        }
+        Ok(Enriched {
+            provider: self.name(),
+            enrichment: Some(enrichment),
+        })
+    }
+}
+
+///////////////
+// FALLBACK //
+/////////////
+/// Asks `fallback` when `primary` fails or knows nothing about an IP. Its
+/// name is the primary's, the details carry the provider that answered.
+pub struct FallbackEnricher {
+    primary: Box<dyn Enricher>,
+    fallback: Box<dyn Enricher>,
+}
+
+impl FallbackEnricher {
+    pub fn new(primary: Box<dyn Enricher>, fallback: Box<dyn Enricher>) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+#[async_trait]
+impl Enricher for FallbackEnricher {
+    fn name(&self) -> &'static str {
+        self.primary.name()
+    }
+
+    async fn enrich(&self, ip: IpAddr) -> anyhow::Result<Enriched> {
+        match self.primary.enrich(ip).await {
+            Ok(enriched) if enriched.enrichment.is_some() => return Ok(enriched),
+            Ok(_) => info!("{} has nothing on {}, asking {}.", self.primary.name(), ip, self.fallback.name()),
+            Err(e) => info!("{} failed for {}, asking {}: {}", self.primary.name(), ip, self.fallback.name(), e),
+        }
+        self.fallback.enrich(ip).await
+    }
+}
+
+///////////
+// RDNS //
+/////////
+/// Adds the PTR name of an IP, and whether that name resolves back to the
+/// IP, to whatever `inner` found. Details that only have the PTR name came
+/// from `rdns`.
+pub struct RdnsEnricher {
+    inner: Box<dyn Enricher>,
+    resolver: TokioAsyncResolver,
+}
+
+impl RdnsEnricher {
+    /// Asks `resolver` or, when unset, the system's resolvers.
+    pub fn new(inner: Box<dyn Enricher>, resolver: Option<SocketAddr>) -> Result<Self, String> {
+        let resolver = match resolver {
+            Some(address) => {
+                let mut config = ResolverConfig::new();
+                config.add_name_server(NameServerConfig::new(address, Protocol::Udp));
+                TokioAsyncResolver::tokio(config, ResolverOpts::default())
+            }
+            None => TokioAsyncResolver::tokio_from_system_conf()
+                .map_err(|e| format!("Failed to read the system's resolver config: {}", e))?,
+        };
+        Ok(Self { inner, resolver })
+    }
+
+    /// The PTR name of `ip` and whether it's confirmed.
+    async fn reverse(&self, ip: IpAddr) -> anyhow::Result<Option<(String, bool)>> {
+        let names = match self.resolver.reverse_lookup(ip).await {
+            Ok(names) => names,
+            Err(e) if no_records(&e) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let Some(name) = names.iter().next() else {
+            return Ok(None);
+        };
+        // anyone can point the PTR record of their own IP at any name, it
+        // only counts when the name points back.
+        let confirmed = match self.resolver.lookup_ip(name.0.clone()).await {
+            Ok(addresses) => addresses.iter().any(|address| address == ip),
+            Err(e) if no_records(&e) => false,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some((name.0.to_utf8().trim_end_matches('.').to_string(), confirmed)))
+    }
+}
+
+fn no_records(e: &ResolveError) -> bool {
+    matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
+#[async_trait]
+impl Enricher for RdnsEnricher {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn enrich(&self, ip: IpAddr) -> anyhow::Result<Enriched> {
+        let (enrichment, reverse) = tokio::join!(self.inner.enrich(ip), self.reverse(ip));
+        let enrichment = enrichment?;
+        // the rest of the details are worth keeping without it.
+        let reverse = reverse.unwrap_or_else(|e| {
+            warn!("Reverse DNS lookup of {} failed: {}", ip, e);
+            None
+        });
+        let Some((rdns, confirmed)) = reverse else {
+            return Ok(enrichment);
+        };
+        let (provider, mut enrichment) = match enrichment.enrichment {
+            Some(found) => (enrichment.provider, found),
+            None => ("rdns", Enrichment::default()),
+        };
+        enrichment.rdns = Some(rdns);
+        enrichment.rdns_confirmed = Some(confirmed);
+        Ok(Enriched {
+            provider,
+            enrichment: Some(enrichment),
+        })
+    }
+}
+
+///////////////////
+// RATE LIMITER //
+/////////////////
+/// How long lookups are held back after a provider reports a rate limit.
+const RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Times a rate limited lookup is retried when there are no expired details
+/// to fall back to.
+const RATE_LIMIT_RETRIES: usize = 3;
+
+/// Spaces out the lookups that reach the enricher and holds all of them
+/// back once the provider complains.
+struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Unlimited when `per_second` is `None`.
+    fn new(per_second: Option<NonZeroU32>) -> Self {
+        Self {
+            interval: per_second
+                .map(|per_second| Duration::from_secs(1) / per_second.get())
+                .unwrap_or_default(),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits for the next free slot.
+    async fn acquire(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+
+    fn pause(&self, cooldown: Duration) {
+        let mut next = self.next.lock().unwrap();
+        *next = (*next).max(Instant::now() + cooldown);
+    }
+}
+
+////////////
+// CACHE //
+//////////
+/// Details of every IP seen so far, kept in `ip_enrichment` and the most
+/// recent ones in memory. An IP is only looked up again once its details
+/// are older than the TTL, concurrent lookups of the same IP share one.
+pub struct EnrichmentCache {
+    db_pool: Pool<Postgres>,
+    enricher: Box<dyn Enricher>,
+    ttl: Duration,
+    recent: Mutex<LruCache<IpAddr, Cached>>,
+    pending: Mutex<HashMap<IpAddr, PendingLookup>>,
+    limiter: RateLimiter,
+}
+
+/// A lookup in progress, waited on by everyone asking for the same IP.
+type PendingLookup = Arc<OnceCell<Result<Enrichment, String>>>;
+
+#[derive(Clone)]
+struct Cached {
+    enrichment: Enrichment,
+    fetched_at: i64,
+}
+
+impl FromRow<'_, PgRow> for Cached {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            enrichment: Enrichment::from_row(row)?,
+            fetched_at: row.try_get("fetched_at")?,
+        })
+    }
+}
+
+impl EnrichmentCache {
+    /// `rate_limit` is the most lookups a second that reach `enricher`.
+    pub fn new(
+        db_pool: Pool<Postgres>,
+        enricher: Box<dyn Enricher>,
+        ttl: Duration,
+        capacity: NonZeroUsize,
+        rate_limit: Option<NonZeroU32>,
+    ) -> Self {
+        Self {
+            db_pool,
+            enricher,
+            ttl,
+            recent: Mutex::new(LruCache::new(capacity)),
+            pending: Mutex::new(HashMap::new()),
+            limiter: RateLimiter::new(rate_limit),
+        }
+    }
+
+    /// Details of `ip`, guaranteed to be in `ip_enrichment` once this
+    /// returns. Falls back to expired details when the enricher fails.
+    pub async fn lookup(&self, ip: IpAddr) -> anyhow::Result<Enrichment> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        let cached = self.recent.lock().unwrap().get(&ip).cloned();
+        if let Some(cached) = cached.filter(|c| self.is_fresh(c, now)) {
+            return Ok(cached.enrichment);
+        }
+
+        let pending = self.pending.lock().unwrap().entry(ip).or_default().clone();
+        let result = pending
+            .get_or_init(|| async { self.fetch(ip, now).await.map_err(|e| e.to_string()) })
+            .await
+            .clone();
+        let mut pending_lookups = self.pending.lock().unwrap();
+        if pending_lookups.get(&ip).is_some_and(|p| Arc::ptr_eq(p, &pending)) {
+            pending_lookups.remove(&ip);
+        }
+        result.map_err(anyhow::Error::msg)
+    }
+
+    async fn fetch(&self, ip: IpAddr, now: i64) -> anyhow::Result<Enrichment> {
+        let stored = sqlx::query_as::<_, Cached>("SELECT * FROM ip_enrichment WHERE ip = $1")
+            .bind(ip.to_string())
+            .fetch_optional(&self.db_pool)
+            .await?;
+        if let Some(stored) = stored.as_ref().filter(|s| self.is_fresh(s, now)) {
+            self.recent.lock().unwrap().put(ip, stored.clone());
+            return Ok(stored.enrichment.clone());
+        }
+        self.fetch_new(ip, stored).await
+    }
+
+    /// Looks `ip` up again even if its details haven't expired, without
+    /// storing anything. For details that have to change together with
+    /// something else, `store_in` stores them.
+    pub async fn ask(&self, ip: IpAddr) -> anyhow::Result<Enriched> {
+        self.ask_enricher(ip, false).await
+    }
+
+    /// Stores details `ask` found as part of `conn`'s transaction.
+    pub async fn store_in(&self, conn: &mut PgConnection, ip: IpAddr, enriched: Enriched) -> anyhow::Result<()> {
+        let fetched_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        let enrichment = enriched.enrichment.unwrap_or_default();
+        store(conn, ip, &enrichment, enriched.provider, fetched_at).await?;
+        // read from ip_enrichment again, the transaction may not commit.
+        self.recent.lock().unwrap().pop(&ip);
+        Ok(())
+    }
+
+    /// Asks the enricher, `stored` is used if it fails.
+    async fn fetch_new(&self, ip: IpAddr, stored: Option<Cached>) -> anyhow::Result<Enrichment> {
+        let (provider, enrichment) = match (self.ask_enricher(ip, stored.is_some()).await, stored) {
+            (Ok(enriched), _) => (enriched.provider, enriched.enrichment.unwrap_or_default()),
+            (Err(e), Some(stored)) => {
+                warn!("{} failed for {}, reusing expired details: {}", self.enricher.name(), ip, e);
+                return Ok(stored.enrichment);
+            }
+            (Err(e), None) => return Err(e),
+        };
+        let fetched_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        store(&self.db_pool, ip, &enrichment, provider, fetched_at).await?;
+        self.recent.lock().unwrap().put(
+            ip,
+            Cached {
+                enrichment: enrichment.clone(),
+                fetched_at,
+            },
+        );
+        Ok(enrichment)
+    }
+
+    /// Waits for the rate limiter. A rate limited lookup is retried unless
+    /// there are expired details to fall back to.
+    async fn ask_enricher(&self, ip: IpAddr, has_fallback: bool) -> anyhow::Result<Enriched> {
+        let mut retries = 0;
+        loop {
+            self.limiter.acquire().await;
+            info!("Fetching new details from {} for IP: {}", self.enricher.name(), ip);
+            let enriched = self.enricher.enrich(ip).await;
+            if let Err(e) = &enriched {
+                if e.is::<RateLimited>() {
+                    warn!("{} is rate limited, holding lookups back for {:?}.", self.enricher.name(), RATE_LIMIT_COOLDOWN);
+                    self.limiter.pause(RATE_LIMIT_COOLDOWN);
+                    // expired details beat waiting out the cooldown.
+                    if !has_fallback && retries < RATE_LIMIT_RETRIES {
+                        retries += 1;
+                        continue;
+                    }
+                }
+            }
+            return enriched;
+        }
+    }
+
+    fn is_fresh(&self, cached: &Cached, now: i64) -> bool {
+        now - cached.fetched_at <= self.ttl.as_millis() as i64
+    }
+}
+
+async fn store<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    ip: IpAddr,
+    enrichment: &Enrichment,
+    provider: &str,
+    fetched_at: i64,
+) -> anyhow::Result<()> {
+    let query = r#"
+        INSERT INTO ip_enrichment (
+            ip, hostname, city, region, timezone, country, loc, org, postal,
+            asn, asn_name, asn_domain, asn_route, asn_type,
+            company_name, company_domain, company_type,
+            vpn, proxy, tor, relay, hosting, service,
+            abuse_address, abuse_country, abuse_email, abuse_name, abuse_network, abuse_phone,
+            domain_ip, domain_total, domains, rdns, rdns_confirmed, provider, fetched_at
+        ) VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9,
+            $10, $11, $12, $13, $14,
+            $15, $16, $17,
+            $18, $19, $20, $21, $22, $23,
+            $24, $25, $26, $27, $28, $29,
+            $30, $31, $32, $33, $34,
+            $35, $36
+        )
+        ON CONFLICT (ip) DO UPDATE SET
+            hostname = EXCLUDED.hostname, city = EXCLUDED.city, region = EXCLUDED.region,
+            timezone = EXCLUDED.timezone, country = EXCLUDED.country, loc = EXCLUDED.loc,
+            org = EXCLUDED.org, postal = EXCLUDED.postal,
+            asn = EXCLUDED.asn, asn_name = EXCLUDED.asn_name, asn_domain = EXCLUDED.asn_domain,
+            asn_route = EXCLUDED.asn_route, asn_type = EXCLUDED.asn_type,
+            company_name = EXCLUDED.company_name, company_domain = EXCLUDED.company_domain,
+            company_type = EXCLUDED.company_type,
+            vpn = EXCLUDED.vpn, proxy = EXCLUDED.proxy, tor = EXCLUDED.tor, relay = EXCLUDED.relay,
+            hosting = EXCLUDED.hosting, service = EXCLUDED.service,
+            abuse_address = EXCLUDED.abuse_address, abuse_country = EXCLUDED.abuse_country,
+            abuse_email = EXCLUDED.abuse_email, abuse_name = EXCLUDED.abuse_name,
+            abuse_network = EXCLUDED.abuse_network, abuse_phone = EXCLUDED.abuse_phone,
+            domain_ip = EXCLUDED.domain_ip, domain_total = EXCLUDED.domain_total,
+            domains = EXCLUDED.domains, rdns = EXCLUDED.rdns, rdns_confirmed = EXCLUDED.rdns_confirmed,
+            provider = EXCLUDED.provider, fetched_at = EXCLUDED.fetched_at;
+    "#;
+    sqlx::query(query)
+        .bind(ip.to_string())
+        .bind(&enrichment.hostname)
+        .bind(&enrichment.city)
+        .bind(&enrichment.region)
+        .bind(&enrichment.timezone)
+        .bind(&enrichment.country)
+        .bind(&enrichment.loc)
+        .bind(&enrichment.org)
+        .bind(&enrichment.postal)
+        .bind(&enrichment.asn)
+        .bind(&enrichment.asn_name)
+        .bind(&enrichment.asn_domain)
+        .bind(&enrichment.asn_route)
+        .bind(&enrichment.asn_type)
+        .bind(&enrichment.company_name)
+        .bind(&enrichment.company_domain)
+        .bind(&enrichment.company_type)
+        .bind(enrichment.vpn)
+        .bind(enrichment.proxy)
+        .bind(enrichment.tor)
+        .bind(enrichment.relay)
+        .bind(enrichment.hosting)
+        .bind(&enrichment.service)
+        .bind(&enrichment.abuse_address)
+        .bind(&enrichment.abuse_country)
+        .bind(&enrichment.abuse_email)
+        .bind(&enrichment.abuse_name)
+        .bind(&enrichment.abuse_network)
+        .bind(&enrichment.abuse_phone)
+        .bind(&enrichment.domain_ip)
+        .bind(enrichment.domain_total)
+        .bind(&enrichment.domains)
+        .bind(&enrichment.rdns)
+        .bind(enrichment.rdns_confirmed)
+        .bind(provider)
+        .bind(fetched_at)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+
+    /// Answers every lookup the same way.
+    struct Stub {
+        name: &'static str,
+        answer: Option<&'static str>,
+        fails: bool,
+    }
+
+    impl Stub {
+        fn boxed(name: &'static str, answer: Option<&'static str>) -> Box<dyn Enricher> {
+            Box::new(Self { name, answer, fails: false })
+        }
+
+        fn failing(name: &'static str) -> Box<dyn Enricher> {
+            Box::new(Self { name, answer: None, fails: true })
+        }
+    }
+
+    #[async_trait]
+    impl Enricher for Stub {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn enrich(&self, _: IpAddr) -> anyhow::Result<Enriched> {
+            if self.fails {
+                anyhow::bail!("{} is down", self.name);
+            }
+            Ok(Enriched {
+                provider: self.name,
+                enrichment: self.answer.map(|country| Enrichment {
+                    country: country.to_string(),
+                    ..Default::default()
+                }),
+            })
+        }
+    }
+
+    const IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(203, 0, 113, 7));
+
+    #[tokio::test]
+    async fn fallback_credits_the_provider_that_answered() {
+        let enriched = FallbackEnricher::new(Stub::boxed("mmdb", Some("NL")), Stub::boxed("ipinfo", Some("DE")))
+            .enrich(IP)
+            .await
+            .unwrap();
+        assert_eq!(enriched.provider, "mmdb");
+        assert_eq!(enriched.enrichment.unwrap().country, "NL");
+
+        let enriched = FallbackEnricher::new(Stub::boxed("mmdb", None), Stub::boxed("ipinfo", Some("DE")))
+            .enrich(IP)
+            .await
+            .unwrap();
+        assert_eq!(enriched.provider, "ipinfo");
+        assert_eq!(enriched.enrichment.unwrap().country, "DE");
+
+        let enriched = FallbackEnricher::new(Stub::failing("mmdb"), Stub::boxed("ipinfo", Some("DE")))
+            .enrich(IP)
+            .await
+            .unwrap();
+        assert_eq!(enriched.provider, "ipinfo");
+    }
+
+    #[tokio::test]
+    async fn fallback_fails_when_the_fallback_does() {
+        let fallback = FallbackEnricher::new(Stub::boxed("mmdb", None), Stub::failing("ipinfo"));
+        assert!(fallback.enrich(IP).await.is_err());
+    }
+
+    /// Answers PTR queries from `ptr` and A queries from `a`, every other
+    /// name doesn't exist.
+    type Records = &'static [(&'static str, &'static str)];
+
+    async fn stub_resolver(ptr: Records, a: Records) -> SocketAddr {
+        use hickory_resolver::proto::{
+            op::{Message, MessageType, ResponseCode},
+            rr::{rdata, Name, RData, Record, RecordType},
+            serialize::binary::{BinDecodable, BinEncodable},
+        };
+
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0; 512];
+            loop {
+                let (length, peer) = socket.recv_from(&mut buffer).await.unwrap();
+                let request = Message::from_bytes(&buffer[..length]).unwrap();
+                let query = request.queries()[0].clone();
+                let name = query.name().to_utf8();
+                let name = name.trim_end_matches('.');
+                let answers: Vec<RData> = match query.query_type() {
+                    RecordType::PTR => ptr
+                        .iter()
+                        .filter(|(ip, _)| *query.name() == Name::from(ip.parse::<IpAddr>().unwrap()))
+                        .map(|(_, host)| RData::PTR(rdata::PTR(host.parse().unwrap())))
+                        .collect(),
+                    RecordType::A => a
+                        .iter()
+                        .filter(|(host, _)| name == *host)
+                        .map(|(_, ip)| RData::A(rdata::A(ip.parse().unwrap())))
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                let known = ptr.iter().any(|(_, host)| name == *host) || !answers.is_empty();
+
+                let mut response = Message::new();
+                response
+                    .set_id(request.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_desired(true)
+                    .set_recursion_available(true)
+                    .add_query(query.clone());
+                if !known {
+                    response.set_response_code(ResponseCode::NXDomain);
+                }
+                for answer in answers {
+                    response.add_answer(Record::from_rdata(query.name().clone(), 60, answer));
+                }
+                socket.send_to(&response.to_bytes().unwrap(), peer).await.unwrap();
+            }
+        });
+        address
+    }
+
+    const PTR: Records = &[
+        ("203.0.113.7", "host.example.com."),
+        ("203.0.113.8", "spoofed.example.com."),
+    ];
+    const A: Records = &[
+        ("host.example.com", "203.0.113.7"),
+        ("spoofed.example.com", "198.51.100.1"),
+    ];
+
+    async fn rdns(inner: Box<dyn Enricher>, ip: &str) -> Enriched {
+        let resolver = stub_resolver(PTR, A).await;
+        let enricher = RdnsEnricher::new(inner, Some(resolver)).unwrap();
+        enricher.enrich(ip.parse().unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn rdns_confirms_names_that_point_back() {
+        let enriched = rdns(Stub::boxed("mmdb", Some("NL")), "203.0.113.7").await;
+        assert_eq!(enriched.provider, "mmdb");
+        let enrichment = enriched.enrichment.unwrap();
+        assert_eq!(enrichment.country, "NL");
+        assert_eq!(enrichment.rdns.as_deref(), Some("host.example.com"));
+        assert_eq!(enrichment.rdns_confirmed, Some(true));
+    }
+
+    #[tokio::test]
+    async fn rdns_keeps_names_that_dont_point_back_unconfirmed() {
+        let enriched = rdns(Stub::boxed("mmdb", None), "203.0.113.8").await;
+        // only the PTR name is known.
+        assert_eq!(enriched.provider, "rdns");
+        let enrichment = enriched.enrichment.unwrap();
+        assert_eq!(enrichment.rdns.as_deref(), Some("spoofed.example.com"));
+        assert_eq!(enrichment.rdns_confirmed, Some(false));
+    }
+
+    #[tokio::test]
+    async fn rdns_leaves_ips_without_a_ptr_record_alone() {
+        let enriched = rdns(Stub::boxed("mmdb", Some("NL")), "203.0.113.9").await;
+        assert_eq!(enriched.provider, "mmdb");
+        let enrichment = enriched.enrichment.unwrap();
+        assert_eq!(enrichment.rdns, None);
+        assert_eq!(enrichment.rdns_confirmed, None);
+
+        let enriched = rdns(Stub::boxed("mmdb", None), "203.0.113.9").await;
+        assert!(enriched.enrichment.is_none());
+    }
+
+    /// Turns every lookup down and counts them.
+    struct Refusing {
+        calls: Arc<AtomicUsize>,
+        rate_limited: bool,
+    }
+
+    #[async_trait]
+    impl Enricher for Refusing {
+        fn name(&self) -> &'static str {
+            "refusing"
+        }
+
+        async fn enrich(&self, _: IpAddr) -> anyhow::Result<Enriched> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.rate_limited {
+                return Err(anyhow::Error::new(RateLimited));
+            }
+            anyhow::bail!("refusing is down")
+        }
+    }
+
+    /// Never connects, lookups that fail don't touch the database.
+    fn refusing_cache(rate_limited: bool) -> (EnrichmentCache, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let enricher = Refusing {
+            calls: calls.clone(),
+            rate_limited,
+        };
+        let db_pool = PgPoolOptions::new().connect_lazy("postgres://localhost/brute").unwrap();
+        let cache = EnrichmentCache::new(
+            db_pool,
+            Box::new(enricher),
+            Duration::ZERO,
+            NonZeroUsize::new(1).unwrap(),
+            None,
+        );
+        (cache, calls)
+    }
+
+    fn stored(country: &str) -> Cached {
+        Cached {
+            enrichment: Enrichment {
+                country: country.to_string(),
+                ..Default::default()
+            },
+            fetched_at: 0,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limits_hold_every_lookup_back() {
+        let (cache, calls) = refusing_cache(true);
+        let start = Instant::now();
+        assert!(cache.fetch_new(IP, None).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1 + RATE_LIMIT_RETRIES);
+        assert!(start.elapsed() >= RATE_LIMIT_COOLDOWN * RATE_LIMIT_RETRIES as u32);
+
+        // the last refusal paused the limiter as well.
+        let start = Instant::now();
+        cache.limiter.acquire().await;
+        assert!(start.elapsed() >= RATE_LIMIT_COOLDOWN);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_lookups_reuse_expired_details() {
+        let (cache, calls) = refusing_cache(false);
+        let enrichment = cache.fetch_new(IP, Some(stored("NL"))).await.unwrap();
+        assert_eq!(enrichment.country, "NL");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // expired details beat waiting out the cooldown.
+        let (cache, calls) = refusing_cache(true);
+        let start = Instant::now();
+        let enrichment = cache.fetch_new(IP, Some(stored("DE"))).await.unwrap();
+        assert_eq!(enrichment.country, "DE");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() < RATE_LIMIT_COOLDOWN);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lookups_are_spaced_out() {
+        let limiter = RateLimiter::new(NonZeroU32::new(4));
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+}
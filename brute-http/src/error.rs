@@ -7,6 +7,9 @@ pub enum BruteResponeError {
     ValidationError(String),
     InternalError(String),
     BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
 }
 
 impl fmt::Display for BruteResponeError {
@@ -21,6 +24,9 @@ impl ResponseError for BruteResponeError {
             BruteResponeError::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
             BruteResponeError::InternalError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
             BruteResponeError::BadRequest(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            BruteResponeError::Unauthorized(_) => actix_web::http::StatusCode::UNAUTHORIZED,
+            BruteResponeError::Forbidden(_) => actix_web::http::StatusCode::FORBIDDEN,
+            BruteResponeError::NotFound(_) => actix_web::http::StatusCode::NOT_FOUND,
 
         }
     }
@@ -30,6 +36,9 @@ impl ResponseError for BruteResponeError {
             BruteResponeError::ValidationError(msg) => HttpResponse::BadRequest().body(msg.clone()),
             BruteResponeError::InternalError(msg) => HttpResponse::InternalServerError().body(msg.clone()),
             BruteResponeError::BadRequest(msg) => HttpResponse::BadRequest().body(msg.clone()),
+            BruteResponeError::Unauthorized(msg) => HttpResponse::Unauthorized().body(msg.clone()),
+            BruteResponeError::Forbidden(msg) => HttpResponse::Forbidden().body(msg.clone()),
+            BruteResponeError::NotFound(msg) => HttpResponse::NotFound().body(msg.clone()),
         }
     }
 }
@@ -0,0 +1,392 @@
+///////////
+// AUTH //
+/////////
+
+use std::{any::Any, fmt, future::Future, pin::Pin, str::FromStr};
+
+use actix_rt::net::TcpStream;
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::{
+    dev::{Extensions, Payload},
+    web::{self, Data},
+    FromRequest, HttpRequest,
+};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+use x509_parser::parse_x509_certificate;
+
+use crate::{error::BruteResponeError, http::AppState, system::hash_token};
+
+/// What an API key may do. Roles don't overlap except that admin keys can
+/// also read, so a leaked dashboard or admin key can't inject attempts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Report attempts, given to sensors.
+    Ingest,
+    /// Read the stats, given to dashboards.
+    Read,
+    /// Manage sensors.
+    Admin,
+}
+
+impl Role {
+    fn allows(self, required: Role) -> bool {
+        self == required || (self == Role::Admin && required == Role::Read)
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ingest" => Ok(Role::Ingest),
+            "read" => Ok(Role::Read),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("unknown role '{}', expected ingest, read or admin", s)),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Ingest => write!(f, "ingest"),
+            Role::Read => write!(f, "read"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// `role:key`, as given to `--api-keys`.
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub role: Role,
+    pub key: String,
+}
+
+impl FromStr for ApiKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((role, key)) if !key.is_empty() => Ok(Self {
+                role: role.parse()?,
+                key: key.to_string(),
+            }),
+            _ => Err(format!("expected role:key, got '{}'", s)),
+        }
+    }
+}
+
+/// Configured API keys, looked up by their hash.
+#[derive(Default)]
+pub struct ApiKeys {
+    roles: Vec<(String, Role)>,
+    read_protected: bool,
+}
+
+impl ApiKeys {
+    pub fn new(keys: Vec<ApiKey>) -> Self {
+        let read_protected = keys.iter().any(|k| k.role == Role::Read);
+        Self {
+            roles: keys
+                .into_iter()
+                .map(|k| (hash_token(&k.key), k.role))
+                .collect(),
+            read_protected,
+        }
+    }
+
+    pub fn role(&self, key: &str) -> Option<Role> {
+        let hash = hash_token(key);
+        // every key is compared in constant time, so timing says nothing about them.
+        self.roles.iter().fold(None, |found, (key_hash, role)| {
+            match constant_time_eq(key_hash, &hash) {
+                true => Some(*role),
+                false => found,
+            }
+        })
+    }
+
+    /// Stats stay public until at least one read key is configured.
+    pub fn is_read_protected(&self) -> bool {
+        self.read_protected
+    }
+}
+
+/// Compares two secrets without stopping at the first difference.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    access_token: Option<String>,
+}
+
+/// A token and whether it came from the query string.
+struct Token {
+    value: String,
+    from_query: bool,
+}
+
+/// The bearer token or, for read access only, the `access_token` query
+/// parameter, which browser websockets need since they can't set headers.
+/// Query strings end up in logs, so they never carry admin or ingest keys.
+fn token(req: &HttpRequest, role: Role) -> Option<Token> {
+    if let Ok(bearer) = BearerAuth::extract(req).into_inner() {
+        return Some(Token { value: bearer.token().to_string(), from_query: false });
+    }
+    if role != Role::Read {
+        return None;
+    }
+    web::Query::<TokenQuery>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.into_inner().access_token)
+        .map(|value| Token { value, from_query: true })
+}
+
+fn app_state(req: &HttpRequest) -> Result<&AppState, BruteResponeError> {
+    req.app_data::<Data<AppState>>()
+        .map(|state| state.get_ref())
+        .ok_or_else(|| BruteResponeError::InternalError("app state is missing.".to_string()))
+}
+
+fn require(req: &HttpRequest, role: Role) -> Result<(), BruteResponeError> {
+    authorize(&app_state(req)?.keys, req, role)
+}
+
+fn authorize(keys: &ApiKeys, req: &HttpRequest, role: Role) -> Result<(), BruteResponeError> {
+    if role == Role::Read && !keys.is_read_protected() {
+        return Ok(());
+    }
+    let Some(token) = token(req, role) else {
+        return Err(BruteResponeError::Unauthorized("invalid bearer token.".to_string()));
+    };
+    match keys.role(&token.value) {
+        // the key is in the logs now, only read keys are accepted from there.
+        Some(granted) if token.from_query && granted != Role::Read => Err(BruteResponeError::Forbidden(format!(
+            "{} keys can't be passed as access_token.",
+            granted
+        ))),
+        Some(granted) if granted.allows(role) => Ok(()),
+        Some(_) => Err(BruteResponeError::Forbidden(format!("this key doesn't have the {} role.", role))),
+        None => Err(BruteResponeError::Unauthorized("invalid bearer token.".to_string())),
+    }
+}
+
+////////////
+// ADMIN //
+//////////
+/// Guards an endpoint that needs an admin key.
+pub struct AdminAccess;
+
+impl FromRequest for AdminAccess {
+    type Error = BruteResponeError;
+    type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        std::future::ready(require(req, Role::Admin).map(|_| AdminAccess))
+    }
+}
+
+///////////
+// READ //
+/////////
+/// Guards a stats endpoint, a no-op while no read keys are configured.
+pub struct ReadAccess;
+
+impl FromRequest for ReadAccess {
+    type Error = BruteResponeError;
+    type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        std::future::ready(require(req, Role::Read).map(|_| ReadAccess))
+    }
+}
+
+/////////////
+// CLIENT //
+///////////
+/// Common name of a client certificate that was verified against
+/// `--client-ca`, used as the sensor id.
+#[derive(Clone, Debug)]
+pub struct ClientIdentity(pub String);
+
+/// `on_connect` hook of the TLS listener, stores the client certificate's
+/// identity with the connection.
+pub fn client_identity(connection: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let (_, session) = stream.get_ref();
+    // rustls only exposes certificates that passed verification.
+    let common_name = session
+        .peer_certificates()
+        .and_then(|certificates| certificates.first())
+        .and_then(|certificate| {
+            let (_, parsed) = parse_x509_certificate(certificate.as_ref()).ok()?;
+            let common_name = parsed.subject().iter_common_name().next()?.as_str().ok()?;
+            Some(common_name.to_string())
+        });
+    if let Some(common_name) = common_name {
+        data.insert(ClientIdentity(common_name));
+    }
+}
+
+/////////////
+// INGEST //
+///////////
+/// Body of an authenticated ingest request and the sensor it is
+/// attributed to, `None` for shared ingest keys.
+pub struct Ingest {
+    pub sensor: Option<String>,
+    pub body: web::Bytes,
+}
+
+impl FromRequest for Ingest {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        // signatures cover the raw body, so it is read before authenticating.
+        let body = web::Bytes::from_request(&req, payload);
+        Box::pin(async move {
+            let body = body.await?;
+            let sensor = authenticate(&req, &body)?;
+            Ok(Ingest { sensor, body })
+        })
+    }
+}
+
+/// Accepts a valid sensor signature, a client certificate or, unless
+/// signatures are required, a sensor token or an ingest key, from the
+/// sensor networks only.
+fn authenticate(req: &HttpRequest, body: &[u8]) -> Result<Option<String>, BruteResponeError> {
+    let state = app_state(req)?;
+    // the connection's address, forwarded headers could be forged.
+    state.ip_filter.check_sensor(req.peer_addr().map(|peer| peer.ip()))?;
+    let path = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or(req.path());
+    if let Some(sensor) = state.verifier.verify(req.method().as_str(), path, req.headers(), body)? {
+        return Ok(Some(sensor));
+    }
+    if let Some(ClientIdentity(sensor)) = req.conn_data::<ClientIdentity>() {
+        // the CA vouches for the name, the registry decides whether it may report.
+        return match state.sensors.get(sensor) {
+            Some(registered) if registered.is_revoked() => {
+                Err(BruteResponeError::Unauthorized("sensor is revoked.".to_string()))
+            }
+            Some(_) => Ok(Some(sensor.clone())),
+            None => Err(BruteResponeError::Unauthorized(format!("'{}' is not a registered sensor.", sensor))),
+        };
+    }
+    if state.verifier.is_required() {
+        return Err(BruteResponeError::Unauthorized("a signed request is required.".to_string()));
+    }
+    let Some(token) = BearerAuth::extract(req).into_inner().ok() else {
+        return Err(BruteResponeError::Unauthorized("invalid bearer token.".to_string()));
+    };
+    if let Some(sensor) = state.sensors.authenticate(token.token()) {
+        return Ok(Some(sensor));
+    }
+    match state.keys.role(token.token()) {
+        Some(Role::Ingest) => Ok(None),
+        Some(role) => Err(BruteResponeError::Forbidden(format!("{} keys can't report attempts.", role))),
+        None => Err(BruteResponeError::Unauthorized("invalid bearer token.".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn keys(keys: &[&str]) -> ApiKeys {
+        ApiKeys::new(keys.iter().map(|k| k.parse().unwrap()).collect())
+    }
+
+    fn bearer(path: &str, token: &str) -> HttpRequest {
+        TestRequest::get()
+            .uri(path)
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_http_request()
+    }
+
+    fn query(token: &str) -> HttpRequest {
+        TestRequest::get().uri(&format!("/ws?access_token={}", token)).to_http_request()
+    }
+
+    #[test]
+    fn parses_keys() {
+        let key: ApiKey = "Admin:secret:with:colons".parse().unwrap();
+        assert_eq!(key.role, Role::Admin);
+        assert_eq!(key.key, "secret:with:colons");
+        assert!("read:".parse::<ApiKey>().is_err());
+        assert!("secret".parse::<ApiKey>().is_err());
+        assert!("owner:secret".parse::<ApiKey>().is_err());
+    }
+
+    #[test]
+    fn admin_keys_can_read_but_nothing_else_overlaps() {
+        assert!(Role::Admin.allows(Role::Admin));
+        assert!(Role::Admin.allows(Role::Read));
+        assert!(!Role::Admin.allows(Role::Ingest));
+        assert!(!Role::Read.allows(Role::Admin));
+        assert!(!Role::Read.allows(Role::Ingest));
+        assert!(!Role::Ingest.allows(Role::Read));
+    }
+
+    #[test]
+    fn looks_up_roles_by_key() {
+        let keys = keys(&["read:r", "admin:a", "ingest:i"]);
+        assert_eq!(keys.role("r"), Some(Role::Read));
+        assert_eq!(keys.role("a"), Some(Role::Admin));
+        assert_eq!(keys.role("i"), Some(Role::Ingest));
+        assert_eq!(keys.role("x"), None);
+    }
+
+    #[test]
+    fn stats_are_public_until_a_read_key_is_set() {
+        let open = keys(&["admin:a"]);
+        assert!(authorize(&open, &TestRequest::get().to_http_request(), Role::Read).is_ok());
+        assert!(authorize(&open, &TestRequest::get().to_http_request(), Role::Admin).is_err());
+
+        let protected = keys(&["read:r", "admin:a", "ingest:i"]);
+        assert!(authorize(&protected, &TestRequest::get().to_http_request(), Role::Read).is_err());
+        assert!(authorize(&protected, &bearer("/brute/stats/total", "r"), Role::Read).is_ok());
+        assert!(authorize(&protected, &bearer("/brute/stats/total", "a"), Role::Read).is_ok());
+        assert!(authorize(&protected, &bearer("/brute/stats/total", "i"), Role::Read).is_err());
+    }
+
+    #[test]
+    fn admin_endpoints_need_an_admin_key() {
+        let keys = keys(&["read:r", "admin:a"]);
+        assert!(authorize(&keys, &bearer("/brute/sensor", "a"), Role::Admin).is_ok());
+        assert!(matches!(
+            authorize(&keys, &bearer("/brute/sensor", "r"), Role::Admin),
+            Err(BruteResponeError::Forbidden(_))
+        ));
+        assert!(matches!(
+            authorize(&keys, &bearer("/brute/sensor", "x"), Role::Admin),
+            Err(BruteResponeError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn query_tokens_only_grant_read_access() {
+        let keys = keys(&["read:r", "admin:a", "ingest:i"]);
+        assert!(authorize(&keys, &query("r"), Role::Read).is_ok());
+        for key in ["a", "i"] {
+            assert!(matches!(authorize(&keys, &query(key), Role::Read), Err(BruteResponeError::Forbidden(_))));
+        }
+        assert!(authorize(&keys, &query("a"), Role::Admin).is_err());
+        assert!(token(&query("a"), Role::Ingest).is_none());
+        let bearer = token(&bearer("/ws?access_token=r", "a"), Role::Read).unwrap();
+        assert_eq!(bearer.value, "a");
+        assert!(!bearer.from_query);
+    }
+}
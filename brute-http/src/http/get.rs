@@ -1,53 +1,172 @@
-use std::time::Instant;
+use std::{
+    net::IpAddr,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use actix::Addr;
-use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{get, web, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use ipnetwork::IpNetwork;
 use serde::Deserialize;
 
 use crate::{
+    error::BruteResponeError,
     http::{
+        auth::ReadAccess,
         websocket::{BruteServer, BruteSession},
         AppState,
     },
     model::{
-        ProcessedIndividual, TopCity, TopCountry, TopHourly, TopIp, TopLocation, TopOrg, TopPassword, TopPostal, TopProtocol, TopRegion, TopTimezone, TopUsername, TopUsrPassCombo
+        AttackCursor, TimeBucket, TopCity, TopCountry, TopDaily, TopHourly, TopIp, TopLocation, TopOrg, TopPassword, TopPostal, TopProtocol, TopRegion, TopTimezone, TopUsername, TopUsrPassCombo, TopWeekly, TopYearly
     },
-    system::RequestWithLimit,
+    system::{RequestWithLimit, SearchAttacks, Timeseries, MAX_TIMESERIES_BUCKETS},
 };
 
 #[derive(Debug, Deserialize)]
 struct LimitParameter {
     limit: Option<usize>,
+    /// Only count attempts reported by this sensor.
+    sensor: Option<String>,
+    /// Only count attempts from this time on, in milliseconds since the epoch.
+    from: Option<i64>,
+    /// Only count attempts before this time, in milliseconds since the epoch.
+    to: Option<i64>,
+    /// Only count the attempts of the last `30m`, `24h`, `7d` or `4w`.
+    window: Option<String>,
+}
+
+impl LimitParameter {
+    fn range(&self) -> Result<(Option<i64>, Option<i64>), BruteResponeError> {
+        time_range(self.from, self.to, self.window.as_deref())
+    }
+}
+
+/// `from` and `to`, or the start of `window` and no end.
+fn time_range(
+    from: Option<i64>,
+    to: Option<i64>,
+    window: Option<&str>,
+) -> Result<(Option<i64>, Option<i64>), BruteResponeError> {
+    let range = match window {
+        Some(_) if from.is_some() || to.is_some() => {
+            return Err(BruteResponeError::BadRequest(
+                "input validation error: use either window or from and to.".to_string(),
+            ));
+        }
+        Some(window) => {
+            let window = parse_window(window).ok_or_else(|| {
+                BruteResponeError::BadRequest(format!(
+                    "input validation error: window '{}' isn't like 30m, 24h, 7d or 4w.",
+                    window
+                ))
+            })?;
+            (Some(now_millis() - window), None)
+        }
+        None => (from, to),
+    };
+    if let (Some(from), Some(to)) = range {
+        if from >= to {
+            return Err(BruteResponeError::BadRequest(
+                "input validation error: from must be before to.".to_string(),
+            ));
+        }
+    }
+    Ok(range)
 }
 
-static MAX_LIMIT: usize = 100;
+/// `30m`, `24h`, `7d` or `4w` in milliseconds.
+fn parse_window(window: &str) -> Option<i64> {
+    let unit = match window.chars().last()? {
+        'm' => 60_000,
+        'h' => 3_600_000,
+        'd' => 86_400_000,
+        'w' => 604_800_000,
+        _ => return None,
+    };
+    let amount = window[..window.len() - 1].parse::<i64>().ok().filter(|amount| *amount > 0)?;
+    amount.checked_mul(unit)
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
 
 ////////////
 /// GET ///
-//////////////////////////////////////////
-/// brute/stats/attack?limit={amount} ///
-////////////////////////////////////////
+///////////////////////////////////////////////////////////////
+/// brute/stats/attack?limit={amount}&cursor={next_cursor} ///
+/////////////////////////////////////////////////////////////
+#[derive(Debug, Deserialize)]
+struct AttackParameter {
+    limit: Option<usize>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+    ip: Option<IpAddr>,
+    cidr: Option<IpNetwork>,
+    username: Option<String>,
+    password: Option<String>,
+    protocol: Option<String>,
+    country: Option<String>,
+    /// `AS15169` or `15169`.
+    asn: Option<String>,
+    sensor: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    window: Option<String>,
+}
+
 #[get("/stats/attack")]
 async fn get_brute_attackers(
     state: web::Data<AppState>,
-    params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
-    let mut request = RequestWithLimit {
-        table: ProcessedIndividual::default(),
-        limit,
-        max_limit: MAX_LIMIT,
+    _: ReadAccess,
+    params: web::Query<AttackParameter>,
+) -> Result<HttpResponse, BruteResponeError> {
+    let params = params.into_inner();
+    let (from, to) = time_range(params.from, params.to, params.window.as_deref())?;
+    let after = match params.cursor {
+        Some(cursor) => Some(cursor.parse::<AttackCursor>().map_err(|_| {
+            BruteResponeError::BadRequest("input validation error: cursor isn't a next_cursor.".to_string())
+        })?),
+        None => None,
     };
-    if limit > request.max_limit {
-        request.limit = request.max_limit;
+    // an empty page would look like the last one.
+    if params.limit == Some(0) {
+        return Err(BruteResponeError::BadRequest(
+            "input validation error: limit must be at least 1.".to_string(),
+        ));
     }
+    let request = SearchAttacks {
+        limit: params.limit.unwrap_or(state.limits.max_limit).min(state.limits.max_limit),
+        after,
+        ip: params.ip,
+        cidr: params.cidr,
+        username: params.username,
+        password: params.password,
+        protocol: params.protocol,
+        country: params.country,
+        asn: params.asn.as_deref().map(normalize_asn),
+        sensor: params.sensor,
+        from,
+        to,
+    };
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
+/// `AS15169`, `as15169` or `15169` as `AS15169`.
+fn normalize_asn(asn: &str) -> String {
+    let number = match asn.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("as") => &asn[2..],
+        _ => asn,
+    };
+    format!("AS{}", number)
+}
+
 ////////////
 /// GET ///
 ////////////////////////////////////////////
@@ -56,20 +175,25 @@ async fn get_brute_attackers(
 #[get("/stats/protocol")]
 async fn get_brute_protocol(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopProtocol::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -81,20 +205,25 @@ async fn get_brute_protocol(
 #[get("/stats/country")]
 async fn get_brute_country(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopCountry::default(),
         limit,
         max_limit: 195, // there can only be 195 countries...
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -106,20 +235,25 @@ async fn get_brute_country(
 #[get("/stats/city")]
 async fn get_brute_city(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopCity::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -131,20 +265,25 @@ async fn get_brute_city(
 #[get("/stats/region")]
 async fn get_brute_region(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopRegion::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -156,20 +295,25 @@ async fn get_brute_region(
 #[get("/stats/username")]
 async fn get_brute_username(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopUsername::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -181,20 +325,25 @@ async fn get_brute_username(
 #[get("/stats/password")]
 async fn get_brute_password(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopPassword::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -206,21 +355,26 @@ async fn get_brute_password(
 #[get("/stats/ip")]
 async fn get_brute_ip(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopIp::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
 
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -232,20 +386,25 @@ async fn get_brute_ip(
 #[get("/stats/combo")]
 async fn get_brute_usr_pass_combo(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopUsrPassCombo::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -257,20 +416,25 @@ async fn get_brute_usr_pass_combo(
 #[get("/stats/timezone")]
 async fn get_brute_timezone(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopTimezone::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -282,20 +446,25 @@ async fn get_brute_timezone(
 #[get("/stats/org")]
 async fn get_brute_org(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopOrg::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -307,20 +476,25 @@ async fn get_brute_org(
 #[get("/stats/postal")]
 async fn get_brute_postal(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopPostal::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -332,20 +506,25 @@ async fn get_brute_postal(
 #[get("/stats/loc")]
 async fn get_brute_loc(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+) -> Result<HttpResponse, BruteResponeError> {
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopLocation::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -357,21 +536,173 @@ async fn get_brute_loc(
 #[get("/stats/hourly")]
 async fn get_hourly(
     state: web::Data<AppState>,
+    _: ReadAccess,
     params: web::Query<LimitParameter>,
-) -> impl Responder {
+) -> Result<HttpResponse, BruteResponeError> {
     // sorted by most recent.
-    let limit = params.limit.unwrap_or(MAX_LIMIT);
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
     let mut request = RequestWithLimit {
         table: TopHourly::default(),
         limit,
-        max_limit: MAX_LIMIT,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
     };
     if limit > request.max_limit {
         request.limit = request.max_limit;
     }
     match state.actor.send(request).await {
-        Ok(result) => HttpResponse::Ok().json(result.unwrap()),
-        Err(er) => HttpResponse::Ok().body(format!("{}", er.to_string())),
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+////////////
+/// GET ///
+/////////////////////////////////////////
+/// brute/stats/daily?limit={amount} ///
+///////////////////////////////////////
+#[get("/stats/daily")]
+async fn get_daily(
+    state: web::Data<AppState>,
+    _: ReadAccess,
+    params: web::Query<LimitParameter>,
+) -> Result<HttpResponse, BruteResponeError> {
+    // sorted by most recent.
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
+    let mut request = RequestWithLimit {
+        table: TopDaily::default(),
+        limit,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
+    };
+    if limit > request.max_limit {
+        request.limit = request.max_limit;
+    }
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+////////////
+/// GET ///
+//////////////////////////////////////////
+/// brute/stats/weekly?limit={amount} ///
+////////////////////////////////////////
+#[get("/stats/weekly")]
+async fn get_weekly(
+    state: web::Data<AppState>,
+    _: ReadAccess,
+    params: web::Query<LimitParameter>,
+) -> Result<HttpResponse, BruteResponeError> {
+    // sorted by most recent.
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
+    let mut request = RequestWithLimit {
+        table: TopWeekly::default(),
+        limit,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
+    };
+    if limit > request.max_limit {
+        request.limit = request.max_limit;
+    }
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+////////////
+/// GET ///
+//////////////////////////////////////////
+/// brute/stats/yearly?limit={amount} ///
+////////////////////////////////////////
+#[get("/stats/yearly")]
+async fn get_yearly(
+    state: web::Data<AppState>,
+    _: ReadAccess,
+    params: web::Query<LimitParameter>,
+) -> Result<HttpResponse, BruteResponeError> {
+    // sorted by most recent.
+    let (from, to) = params.range()?;
+    let limit = params.limit.unwrap_or(state.limits.max_limit);
+    let mut request = RequestWithLimit {
+        table: TopYearly::default(),
+        limit,
+        max_limit: state.limits.max_limit,
+        sensor: params.sensor.clone(),
+        from,
+        to,
+    };
+    if limit > request.max_limit {
+        request.limit = request.max_limit;
+    }
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+////////////
+/// GET ///
+///////////////////////////////////////////////////////////////////
+/// brute/stats/timeseries?bucket={bucket}&from={from}&to={to} ///
+/////////////////////////////////////////////////////////////////
+#[derive(Debug, Deserialize)]
+struct TimeseriesParameter {
+    bucket: TimeBucket,
+    /// Milliseconds since the epoch.
+    from: i64,
+    /// Milliseconds since the epoch (exclusive), now when missing.
+    to: Option<i64>,
+    /// Only count attempts reported by this sensor.
+    sensor: Option<String>,
+}
+
+/// Fails when `[from, to)` is empty or spans too many buckets.
+fn check_timeseries(bucket: TimeBucket, from: i64, to: i64) -> Result<(), BruteResponeError> {
+    if from >= to {
+        return Err(BruteResponeError::BadRequest(
+            "input validation error: from must be before to.".to_string(),
+        ));
+    }
+    // a span that overflows is too long either way.
+    match to.checked_sub(from) {
+        Some(span) if span / bucket.min_millis() < MAX_TIMESERIES_BUCKETS => Ok(()),
+        _ => Err(BruteResponeError::BadRequest(format!(
+            "input validation error: at most {} buckets, use larger buckets or a shorter range.",
+            MAX_TIMESERIES_BUCKETS
+        ))),
+    }
+}
+
+#[get("/stats/timeseries")]
+async fn get_timeseries(
+    state: web::Data<AppState>,
+    _: ReadAccess,
+    params: web::Query<TimeseriesParameter>,
+) -> Result<HttpResponse, BruteResponeError> {
+    let params = params.into_inner();
+    let request = Timeseries {
+        bucket: params.bucket,
+        from: params.from,
+        to: params.to.unwrap_or_else(now_millis),
+        sensor: params.sensor,
+    };
+    check_timeseries(request.bucket, request.from, request.to)?;
+
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
@@ -385,6 +716,7 @@ async fn get_hourly(
 #[allow(unused_variables)]
 async fn get_websocket(
     req: HttpRequest,
+    _: ReadAccess,
     stream: web::Payload,
     srv: web::Data<Addr<BruteServer>>,
 ) -> Result<HttpResponse, actix_web::Error> {
@@ -398,3 +730,58 @@ async fn get_websocket(
         stream,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_windows() {
+        assert_eq!(parse_window("30m"), Some(1_800_000));
+        assert_eq!(parse_window("24h"), Some(86_400_000));
+        assert_eq!(parse_window("7d"), Some(604_800_000));
+        assert_eq!(parse_window("4w"), Some(2_419_200_000));
+        for invalid in ["", "m", "0d", "-1d", "1.5h", "7", "7s", "d7", "1é"] {
+            assert_eq!(parse_window(invalid), None, "{}", invalid);
+        }
+        assert_eq!(parse_window(&format!("{}w", i64::MAX)), None);
+    }
+
+    #[test]
+    fn normalizes_asns() {
+        assert_eq!(normalize_asn("AS15169"), "AS15169");
+        assert_eq!(normalize_asn("as15169"), "AS15169");
+        assert_eq!(normalize_asn("15169"), "AS15169");
+        assert_eq!(normalize_asn("ASAS1"), "ASAS1");
+        assert_eq!(normalize_asn("é1"), "ASé1");
+    }
+
+    #[test]
+    fn resolves_time_ranges() {
+        assert_eq!(time_range(None, None, None).unwrap(), (None, None));
+        assert_eq!(time_range(Some(1), Some(2), None).unwrap(), (Some(1), Some(2)));
+        assert_eq!(time_range(Some(1), None, None).unwrap(), (Some(1), None));
+        assert!(time_range(Some(2), Some(2), None).is_err());
+        assert!(time_range(Some(3), Some(2), None).is_err());
+
+        let before = now_millis();
+        let (from, to) = time_range(None, None, Some("1h")).unwrap();
+        assert!(from.unwrap() >= before - 3_600_000 && from.unwrap() <= now_millis() - 3_600_000);
+        assert_eq!(to, None);
+        assert!(time_range(Some(1), None, Some("1h")).is_err());
+        assert!(time_range(None, Some(1), Some("1h")).is_err());
+        assert!(time_range(None, None, Some("1y")).is_err());
+    }
+
+    #[test]
+    fn bounds_timeseries() {
+        let hour = TimeBucket::Hour.min_millis();
+        assert!(check_timeseries(TimeBucket::Hour, 0, hour).is_ok());
+        assert!(check_timeseries(TimeBucket::Hour, 0, (MAX_TIMESERIES_BUCKETS - 1) * hour).is_ok());
+        assert!(check_timeseries(TimeBucket::Hour, 0, MAX_TIMESERIES_BUCKETS * hour).is_err());
+        assert!(check_timeseries(TimeBucket::Hour, hour, hour).is_err());
+        // would overflow rather than count the buckets.
+        assert!(check_timeseries(TimeBucket::Hour, i64::MIN, i64::MAX).is_err());
+        assert!(check_timeseries(TimeBucket::Month, -1, i64::MAX).is_err());
+    }
+}
@@ -1,27 +1,39 @@
+use std::{net::SocketAddr, sync::Arc};
+
 use actix::{Actor, Addr};
 use actix_cors::Cors;
 use actix_web::{
     body::{BoxBody, EitherBody},
-    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    dev::{Server, ServiceFactory, ServiceRequest, ServiceResponse},
     http::header,
     web::{self, Data},
     App, HttpServer,
 };
 use get::{
-    get_brute_attackers, get_brute_city, get_brute_country, get_brute_ip, get_brute_loc, get_brute_org, get_brute_password, get_brute_postal, get_brute_protocol, get_brute_region, get_brute_timezone, get_brute_username, get_brute_usr_pass_combo, get_hourly, get_websocket
+    get_brute_attackers, get_brute_city, get_brute_country, get_brute_ip, get_brute_loc, get_brute_org, get_brute_password, get_brute_postal, get_brute_protocol, get_brute_region, get_brute_timezone, get_brute_username, get_brute_usr_pass_combo, get_daily, get_hourly, get_timeseries, get_websocket, get_weekly, get_yearly
 };
 use log::info;
 use post::{
-    post_brute_attack_add, post_brute_fake_http_login, post_brute_fake_https_login,
+    post_brute_attack_add, post_brute_attack_add_batch, post_brute_fake_http_login, post_brute_fake_https_login,
     post_brute_protocol_increment,
 };
+use profile::{get_brute_ip_profile, get_brute_network_profile};
+use reenrich::{get_brute_reenrich_job, get_brute_reenrich_jobs, post_brute_reenrich, post_brute_reenrich_resume};
 use rustls::ServerConfig;
+use sensor::{get_brute_sensors, post_brute_sensor, post_brute_sensor_revoke, post_brute_sensor_rotate};
 use websocket::BruteServer;
 
-use crate::system::BruteSystem;
+use crate::{config::Limits, system::{BruteSystem, SensorRegistry}, validator::IpFilter};
+use auth::ApiKeys;
+use signature::SignatureVerifier;
 
+pub mod auth;
 mod get;
 mod post;
+mod profile;
+mod reenrich;
+mod sensor;
+pub mod signature;
 
 ///////////////
 // APPSTATE //
@@ -30,60 +42,91 @@ mod post;
 #[derive(Clone)]
 pub struct AppState {
     actor: Addr<BruteSystem>,
-    bearer: String,
+    keys: Arc<ApiKeys>,
+    ip_filter: IpFilter,
+    verifier: Arc<SignatureVerifier>,
+    sensors: Arc<SensorRegistry>,
+    limits: Limits,
+    /// Every origin is allowed when empty.
+    cors_origins: Arc<[String]>,
+}
+
+impl AppState {
+    pub fn new(
+        actor: Addr<BruteSystem>,
+        keys: Arc<ApiKeys>,
+        ip_filter: IpFilter,
+        verifier: Arc<SignatureVerifier>,
+        sensors: Arc<SensorRegistry>,
+        limits: Limits,
+        cors_origins: Arc<[String]>,
+    ) -> Self {
+        Self {
+            actor,
+            keys,
+            ip_filter,
+            verifier,
+            sensors,
+            limits,
+            cors_origins,
+        }
+    }
 }
 
 //////////////
 // NON-TLS //
 ////////////
 
-pub async fn serve(
-    ip: &str,
-    port: u16,
-    brute_actor: Addr<BruteSystem>,
-    bearer_token: String,
-) -> anyhow::Result<()> {
-    info!("Listening on {}:{}", ip, port);
-    HttpServer::new(move || {
-        configure_app(brute_actor.clone(), bearer_token.clone())
+/// Binds the server, the returned `Server` runs it when awaited and its
+/// handle stops it. Signals are left to the caller.
+pub fn serve(
+    address: SocketAddr,
+    state: AppState,
+    shutdown_timeout: u64,
+) -> anyhow::Result<Server> {
+    info!("Listening on {}", address);
+    let server = HttpServer::new(move || {
+        configure_app(state.clone())
             .service(web::scope("auth").service(post_brute_fake_http_login))
             .service(get_websocket)
     })
-    .bind((ip, port))?
-    .run()
-    .await?;
-    Ok(())
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout)
+    .bind(address)?
+    .run();
+    Ok(server)
 }
 
 //////////
 // TLS //
 ////////
 
-pub async fn serve_tls(
-    ip: &str,
-    port: u16,
-    brute_actor: Addr<BruteSystem>,
+pub fn serve_tls(
+    address: SocketAddr,
+    state: AppState,
     tls_config: ServerConfig,
-    bearer_token: String,
-) -> anyhow::Result<()> {
-    info!("(TLS) Listening on {}:{}", ip, port);
-    HttpServer::new(move || {
-        configure_app(brute_actor.clone(), bearer_token.clone())
+    shutdown_timeout: u64,
+) -> anyhow::Result<Server> {
+    info!("(TLS) Listening on {}", address);
+    let server = HttpServer::new(move || {
+        configure_app(state.clone())
             .service(web::scope("auth").service(post_brute_fake_https_login))
     })
-    .bind_rustls_0_23((ip, port), tls_config)?
-    .run()
-    .await?;
-    Ok(())
+    .on_connect(auth::client_identity)
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout)
+    .bind_rustls_0_23(address, tls_config)?
+    .run();
+    Ok(server)
 }
 
 ////////////////
 // CONFIGURE //
 //////////////
 
-fn configure_app(
-    brute_actor: Addr<BruteSystem>,
-    bearer_token: String,
+/// Routes both listeners serve.
+pub fn configure_app(
+    state: AppState,
 ) -> App<
     impl ServiceFactory<
         ServiceRequest,
@@ -93,8 +136,14 @@ fn configure_app(
         InitError = (),
     >,
 > {
-    let cors = Cors::default()
-        .allowed_origin_fn(|_origin, _request_head| true) // Allow all origins
+    let mut cors = Cors::default();
+    if state.cors_origins.is_empty() {
+        cors = cors.allowed_origin_fn(|_origin, _request_head| true); // Allow all origins
+    }
+    for origin in state.cors_origins.iter() {
+        cors = cors.allowed_origin(origin);
+    }
+    let cors = cors
         .allowed_methods(vec!["GET", "POST"])
         .allowed_headers(vec![
             header::AUTHORIZATION,
@@ -104,15 +153,13 @@ fn configure_app(
         .max_age(3600);
 
     App::new()
-        .app_data(Data::new(AppState {
-            actor: brute_actor,
-            bearer: bearer_token,
-        }))
+        .app_data(Data::new(state))
         .wrap(cors)
         .app_data(web::Data::new(BruteServer.start()))
         .service(
             web::scope("brute")
                 .service(post_brute_attack_add)
+                .service(post_brute_attack_add_batch)
                 .service(post_brute_protocol_increment)
                 .service(get_brute_attackers)
                 .service(get_brute_protocol)
@@ -127,7 +174,21 @@ fn configure_app(
                 .service(get_brute_org)
                 .service(get_brute_postal)
                 .service(get_brute_loc)
-                .service(get_hourly),
+                .service(get_hourly)
+                .service(get_daily)
+                .service(get_weekly)
+                .service(get_yearly)
+                .service(get_timeseries)
+                .service(get_brute_ip_profile)
+                .service(get_brute_network_profile)
+                .service(post_brute_sensor)
+                .service(get_brute_sensors)
+                .service(post_brute_sensor_rotate)
+                .service(post_brute_sensor_revoke)
+                .service(post_brute_reenrich)
+                .service(get_brute_reenrich_jobs)
+                .service(get_brute_reenrich_job)
+                .service(post_brute_reenrich_resume),
         )
         .service(get_websocket)
 }
@@ -178,7 +239,7 @@ pub mod websocket {
     }
 
     impl BruteMessage {
-        fn new<T: Serialize>(parse_type: ParseType, message: String) -> Self {
+        fn new(parse_type: ParseType, message: String) -> Self {
             Self {
                 parse_type,
                 message,
@@ -206,7 +267,7 @@ pub mod websocket {
     impl BruteServer {
         pub fn broadcast<T: Serialize>(parse_type: ParseType, message: T) {
             for (_, session) in CLIENTS.lock().unwrap().clone().into_iter() {
-                let message = BruteMessage::new::<T>(
+                let message = BruteMessage::new(
                     parse_type.clone(),
                     serde_json::to_string(&message).unwrap(),
                 );
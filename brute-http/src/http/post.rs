@@ -1,14 +1,21 @@
 use actix_web::{post, web, HttpRequest, HttpResponse};
-use actix_web_httpauth::extractors::bearer::BearerAuth;
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
     error::BruteResponeError,
-    http::{websocket, AppState},
+    http::{auth::Ingest, AppState},
     model::{Individual, TopProtocol},
+    system::ReportBatch,
     validator::{validate_and_check_ip, Validate},
 };
 
+/// Signed requests are checked against the raw body, so it is parsed here
+/// instead of through `web::Json`.
+fn parse_json<T: DeserializeOwned>(body: &[u8]) -> Result<T, BruteResponeError> {
+    serde_json::from_slice(body)
+        .map_err(|e| BruteResponeError::BadRequest(format!("input validation error: {}", e)))
+}
+
 /////////////
 /// POST ///
 /////////////////////////
@@ -24,16 +31,10 @@ struct IndividualPayload {
 #[post("/attack/add")]
 async fn post_brute_attack_add(
     state: web::Data<AppState>,
-    payload: web::Json<IndividualPayload>,
-    bearer: BearerAuth,
+    ingest: Ingest,
 ) -> Result<HttpResponse, BruteResponeError> {
-    if !bearer.token().eq(&state.bearer) {
-        return Ok(HttpResponse::Unauthorized().body("body"));
-    }
-
-    if payload.ip_address.eq("127.0.0.1") {
-        return Err(BruteResponeError::ValidationError("empty ip or local ip".to_string()));
-    } 
+    let Ingest { sensor, body } = ingest;
+    let payload: IndividualPayload = parse_json(&body)?;
 
     let mut individual = Individual::new_short(
         payload.username.clone(),
@@ -41,18 +42,77 @@ async fn post_brute_attack_add(
         payload.ip_address.clone(),
         payload.protocol.clone(),
     );
+    individual.sensor_id = sensor;
 
     individual.validate()?;
-    
+    validate_and_check_ip(individual.ip(), &state.ip_filter)?;
+
+    // answered once the attempt is stored, it's enriched and broadcast
+    // in the background.
     match state.actor.send(individual).await {
-        Ok(res) => {
-            websocket::BruteServer::broadcast(websocket::ParseType::ProcessedIndividual, res.unwrap());
-            Ok(HttpResponse::Ok().into())
-        },
+        Ok(Ok(_)) => Ok(HttpResponse::Ok().into()),
+        Ok(Err(er)) => Err(er),
         Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
     }
 }
 
+/////////////
+/// POST ///
+///////////////////////////////
+/// brute/attack/add/batch ///
+/////////////////////////////
+#[derive(Serialize)]
+struct BatchResult {
+    accepted: usize,
+    rejected: usize,
+}
+
+#[post("/attack/add/batch")]
+async fn post_brute_attack_add_batch(
+    state: web::Data<AppState>,
+    ingest: Ingest,
+) -> Result<HttpResponse, BruteResponeError> {
+    let Ingest { sensor, body } = ingest;
+    let payload: Vec<IndividualPayload> = parse_json(&body)?;
+
+    if payload.len() > state.limits.max_batch_size {
+        return Err(BruteResponeError::BadRequest(format!(
+            "input validation error: batch is too large max is {} attempts.",
+            state.limits.max_batch_size
+        )));
+    }
+
+    // invalid attempts are skipped so one bad entry doesn't make the
+    // sender retry the whole batch.
+    let mut attempts = Vec::with_capacity(payload.len());
+    let mut rejected = 0;
+    for attempt in payload {
+        let mut individual = Individual::new_short(
+            attempt.username,
+            attempt.password,
+            attempt.ip_address,
+            attempt.protocol,
+        );
+        individual.sensor_id = sensor.clone();
+
+        if individual.validate().is_err() || validate_and_check_ip(individual.ip(), &state.ip_filter).is_err() {
+            rejected += 1;
+            continue;
+        }
+        attempts.push(individual);
+    }
+
+    // stored in one transaction, a failure stores nothing so the sender
+    // can retry the whole batch without storing anything twice.
+    let result = match state.actor.send(ReportBatch { attempts }).await {
+        Ok(Ok(stored)) => BatchResult { accepted: stored.len(), rejected },
+        Ok(Err(er)) => return Err(er),
+        Err(er) => return Err(BruteResponeError::InternalError(er.to_string())),
+    };
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
 /////////////
 /// POST ///
 /////////////////////////////////
@@ -66,12 +126,9 @@ struct ProtocolPayload {
 #[post("/protocol/increment")]
 async fn post_brute_protocol_increment(
     state: web::Data<AppState>,
-    payload: web::Json<ProtocolPayload>,
-    bearer: BearerAuth,
+    ingest: Ingest,
 ) -> Result<HttpResponse, BruteResponeError> {
-    if !bearer.token().eq(&state.bearer) {
-        return Ok(HttpResponse::Unauthorized().body("body"));
-    }
+    let payload: ProtocolPayload = parse_json(&ingest.body)?;
 
     let individual = TopProtocol::new(payload.protocol.clone(), payload.amount);
     match state.actor.send(individual).await {
@@ -100,13 +157,13 @@ async fn post_brute_fake_https_login(
     payload: web::Json<FakeLoginPayload>,
     req: HttpRequest
 ) -> Result<HttpResponse, BruteResponeError> {
-    let conn = req.connection_info();
-    let ip_address = conn.realip_remote_addr();
+    // not borrowed across the await below.
+    let ip_address = req.connection_info().realip_remote_addr().map(str::to_string);
     if ip_address.is_none() {
         return Err(BruteResponeError::ValidationError("input validation error: ip_address is empty.".to_string()))
     }
 
-    validate_and_check_ip(ip_address.unwrap())?;
+    validate_and_check_ip(ip_address.as_deref().unwrap(), &state.ip_filter)?;
 
     // empty passwords are not allowed for HTTP or HTTPS
     if payload.password.is_empty() {
@@ -118,7 +175,7 @@ async fn post_brute_fake_https_login(
     let individual = Individual::new_short(
         payload.username.clone(),
         payload.password.clone(),
-        ip_address.unwrap().to_string(),
+        ip_address.unwrap(),
         "HTTPS".to_string(),
     );
 
@@ -142,8 +199,8 @@ async fn post_brute_fake_http_login(
     payload: web::Json<FakeLoginPayload>,
     req: HttpRequest
 ) -> Result<HttpResponse, BruteResponeError> {
-    let conn = req.connection_info();
-    let ip_address = conn.realip_remote_addr();
+    // not borrowed across the await below.
+    let ip_address = req.connection_info().realip_remote_addr().map(str::to_string);
     if ip_address.is_none() {
         return Err(BruteResponeError::ValidationError("input validation error: ip_address is empty.".to_string()))
     }
@@ -155,12 +212,12 @@ async fn post_brute_fake_http_login(
         ));
     }
     
-    validate_and_check_ip(ip_address.unwrap())?;
+    validate_and_check_ip(ip_address.as_deref().unwrap(), &state.ip_filter)?;
 
     let individual = Individual::new_short(
         payload.username.clone(),
         payload.password.clone(),
-        ip_address.unwrap().to_string(),
+        ip_address.unwrap(),
         "HTTP".to_string(),
     );
 
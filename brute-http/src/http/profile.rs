@@ -0,0 +1,74 @@
+use std::net::IpAddr;
+
+use actix_web::{get, web, HttpResponse};
+use ipnetwork::IpNetwork;
+use serde::Deserialize;
+
+use crate::{
+    error::BruteResponeError,
+    http::{auth::ReadAccess, AppState},
+    model::TimeBucket,
+    system::GetProfile,
+};
+
+/// Rows in each of a profile's top lists when `limit` is missing.
+const DEFAULT_PROFILE_LIMIT: usize = 10;
+
+#[derive(Debug, Deserialize)]
+struct ProfileParameter {
+    limit: Option<usize>,
+    /// Width of the activity buckets, days when missing.
+    bucket: Option<TimeBucket>,
+}
+
+impl ProfileParameter {
+    fn request(&self, network: IpNetwork, max_limit: usize) -> GetProfile {
+        GetProfile {
+            network,
+            limit: self.limit.unwrap_or(DEFAULT_PROFILE_LIMIT).min(max_limit),
+            bucket: self.bucket.unwrap_or(TimeBucket::Day),
+        }
+    }
+}
+
+////////////
+/// GET ///
+//////////////////////
+/// brute/ip/{ip} ///
+////////////////////
+#[get("/ip/{ip}")]
+async fn get_brute_ip_profile(
+    state: web::Data<AppState>,
+    _: ReadAccess,
+    ip: web::Path<IpAddr>,
+    params: web::Query<ProfileParameter>,
+) -> Result<HttpResponse, BruteResponeError> {
+    let request = params.request(IpNetwork::from(ip.into_inner()), state.limits.max_limit);
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+////////////
+/// GET ///
+////////////////////////////////////
+/// brute/network/{ip}/{prefix} ///
+//////////////////////////////////
+#[get("/network/{ip}/{prefix}")]
+async fn get_brute_network_profile(
+    state: web::Data<AppState>,
+    _: ReadAccess,
+    path: web::Path<(IpAddr, u8)>,
+    params: web::Query<ProfileParameter>,
+) -> Result<HttpResponse, BruteResponeError> {
+    let (ip, prefix) = path.into_inner();
+    let network = IpNetwork::new(ip, prefix).map_err(|e| {
+        BruteResponeError::BadRequest(format!("input validation error: {}/{} isn't a network: {}", ip, prefix, e))
+    })?;
+    let request = params.request(network, state.limits.max_limit);
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
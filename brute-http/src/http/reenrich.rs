@@ -0,0 +1,95 @@
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    error::BruteResponeError,
+    http::{auth::AdminAccess, AppState},
+    system::{GetReenrichJob, ListReenrichJobs, ResumeReenrich, StartReenrich},
+};
+
+/////////////
+/// POST ///
+///////////////////////
+/// brute/reenrich ///
+/////////////////////
+#[derive(Deserialize)]
+struct ReenrichPayload {
+    /// Milliseconds since the epoch, the first attempt when missing.
+    from: Option<i64>,
+    /// Milliseconds since the epoch (exclusive), now when missing.
+    to: Option<i64>,
+}
+
+#[post("/reenrich")]
+async fn post_brute_reenrich(
+    state: web::Data<AppState>,
+    _: AdminAccess,
+    payload: web::Json<ReenrichPayload>,
+) -> Result<HttpResponse, BruteResponeError> {
+    let request = StartReenrich {
+        from: payload.from.unwrap_or(0),
+        to: payload.to.unwrap_or(i64::MAX),
+    };
+    if request.from >= request.to {
+        return Err(BruteResponeError::BadRequest(
+            "input validation error: from must be before to.".to_string(),
+        ));
+    }
+
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Accepted().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+////////////
+/// GET ///
+///////////////////////
+/// brute/reenrich ///
+/////////////////////
+#[get("/reenrich")]
+async fn get_brute_reenrich_jobs(
+    state: web::Data<AppState>,
+    _: AdminAccess,
+) -> Result<HttpResponse, BruteResponeError> {
+    match state.actor.send(ListReenrichJobs).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+////////////
+/// GET ///
+////////////////////////////
+/// brute/reenrich/{id} ///
+//////////////////////////
+#[get("/reenrich/{id}")]
+async fn get_brute_reenrich_job(
+    state: web::Data<AppState>,
+    _: AdminAccess,
+    id: web::Path<String>,
+) -> Result<HttpResponse, BruteResponeError> {
+    let request = GetReenrichJob { id: id.into_inner() };
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+/////////////
+/// POST ///
+///////////////////////////////////
+/// brute/reenrich/{id}/resume ///
+/////////////////////////////////
+#[post("/reenrich/{id}/resume")]
+async fn post_brute_reenrich_resume(
+    state: web::Data<AppState>,
+    _: AdminAccess,
+    id: web::Path<String>,
+) -> Result<HttpResponse, BruteResponeError> {
+    let request = ResumeReenrich { id: id.into_inner() };
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Accepted().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
@@ -0,0 +1,99 @@
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    error::BruteResponeError,
+    http::{auth::AdminAccess, AppState},
+    system::{CreateSensor, ListSensors, RevokeSensor, RotateSensor},
+    model::SensorToken,
+    validator::Validate,
+};
+
+/// The signing key is derived here since only the verifier knows `--signing-key`.
+fn with_signing_key(state: &AppState, mut issued: SensorToken) -> SensorToken {
+    issued.signing_key = state.verifier.signing_key(&issued.sensor);
+    issued
+}
+
+/////////////
+/// POST ///
+/////////////////////
+/// brute/sensor ///
+///////////////////
+#[derive(Deserialize)]
+struct SensorPayload {
+    id: String,
+    #[serde(default)]
+    name: String,
+}
+
+#[post("/sensor")]
+async fn post_brute_sensor(
+    state: web::Data<AppState>,
+    _: AdminAccess,
+    payload: web::Json<SensorPayload>,
+) -> Result<HttpResponse, BruteResponeError> {
+    let payload = payload.into_inner();
+    let mut request = CreateSensor {
+        id: payload.id,
+        name: payload.name,
+    };
+    request.validate()?;
+
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(with_signing_key(&state, result?))),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+////////////
+/// GET ///
+/////////////////////
+/// brute/sensor ///
+///////////////////
+#[get("/sensor")]
+async fn get_brute_sensors(
+    state: web::Data<AppState>,
+    _: AdminAccess,
+) -> Result<HttpResponse, BruteResponeError> {
+    match state.actor.send(ListSensors).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+/////////////
+/// POST ///
+/////////////////////////////////
+/// brute/sensor/{id}/rotate ///
+///////////////////////////////
+#[post("/sensor/{id}/rotate")]
+async fn post_brute_sensor_rotate(
+    state: web::Data<AppState>,
+    _: AdminAccess,
+    id: web::Path<String>,
+) -> Result<HttpResponse, BruteResponeError> {
+    let request = RotateSensor { id: id.into_inner() };
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(with_signing_key(&state, result?))),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
+
+/////////////
+/// POST ///
+/////////////////////////////////
+/// brute/sensor/{id}/revoke ///
+///////////////////////////////
+#[post("/sensor/{id}/revoke")]
+async fn post_brute_sensor_revoke(
+    state: web::Data<AppState>,
+    _: AdminAccess,
+    id: web::Path<String>,
+) -> Result<HttpResponse, BruteResponeError> {
+    let request = RevokeSensor { id: id.into_inner() };
+    match state.actor.send(request).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result?)),
+        Err(er) => Err(BruteResponeError::InternalError(er.to_string())),
+    }
+}
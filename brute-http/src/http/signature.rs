@@ -0,0 +1,348 @@
+////////////////
+// SIGNATURE //
+//////////////
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::http::header::HeaderMap;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::BruteResponeError,
+    model::Sensor,
+    system::SensorRegistry,
+};
+
+pub const SENSOR_HEADER: &str = "X-Brute-Sensor";
+pub const TIMESTAMP_HEADER: &str = "X-Brute-Timestamp";
+pub const NONCE_HEADER: &str = "X-Brute-Nonce";
+pub const SIGNATURE_HEADER: &str = "X-Brute-Signature";
+
+/// `sensor_id:secret`, as given to `--sensor-secrets`.
+#[derive(Clone, Debug)]
+pub struct SensorSecret {
+    pub sensor: String,
+    pub secret: String,
+}
+
+impl FromStr for SensorSecret {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((sensor, secret)) if !sensor.is_empty() && !secret.is_empty() => Ok(Self {
+                sensor: sensor.to_string(),
+                secret: secret.to_string(),
+            }),
+            _ => Err(format!("expected sensor_id:secret, got '{}'", s)),
+        }
+    }
+}
+
+/// The string a sensor signs:
+/// `METHOD\nPATH\nTIMESTAMP\nNONCE\nhex(sha256(body))`.
+pub fn signing_string(method: &str, path: &str, timestamp: i64, nonce: &str, body: &[u8]) -> String {
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        method,
+        path,
+        timestamp,
+        nonce,
+        hex::encode(Sha256::digest(body))
+    )
+}
+
+/// Checks HMAC-SHA256 signed ingest requests and rejects replays.
+///
+/// Sensors from `--sensor-secrets` sign with their secret. Registered
+/// sensors sign with a key derived from `--signing-key` and their current
+/// token hash, which nothing in the database is enough to recreate.
+pub struct SignatureVerifier {
+    secrets: HashMap<String, Vec<u8>>,
+    signing_key: Option<String>,
+    sensors: Arc<SensorRegistry>,
+    window: i64,
+    required: bool,
+    nonces: Mutex<Nonces>,
+}
+
+/// Nonces seen inside the window, forgotten in the order they expire.
+#[derive(Default)]
+struct Nonces {
+    /// (sensor, nonce) -> unix time after which it can be forgotten.
+    seen: HashMap<(String, String), i64>,
+    expiry: BTreeSet<(i64, (String, String))>,
+}
+
+impl Nonces {
+    /// Remembers `key` until `expires`, false if it is still remembered.
+    fn insert(&mut self, key: (String, String), expires: i64, now: i64) -> bool {
+        while let Some((at, _)) = self.expiry.first() {
+            if *at > now {
+                break;
+            }
+            let (at, expired) = self.expiry.pop_first().unwrap();
+            if self.seen.get(&expired) == Some(&at) {
+                self.seen.remove(&expired);
+            }
+        }
+        if self.seen.contains_key(&key) {
+            return false;
+        }
+        self.seen.insert(key.clone(), expires);
+        self.expiry.insert((expires, key));
+        true
+    }
+}
+
+impl SignatureVerifier {
+    /// `window` is how many seconds a timestamp may be off from ours.
+    /// Registered sensors take precedence over `secrets`.
+    pub fn new(
+        secrets: Vec<SensorSecret>,
+        signing_key: Option<String>,
+        sensors: Arc<SensorRegistry>,
+        window: u64,
+        required: bool,
+    ) -> Self {
+        Self {
+            secrets: secrets
+                .into_iter()
+                .map(|s| (s.sensor, s.secret.into_bytes()))
+                .collect(),
+            signing_key,
+            sensors,
+            window: window as i64,
+            required,
+            nonces: Mutex::new(Nonces::default()),
+        }
+    }
+
+    /// Hex key `sensor` signs with, `None` without `--signing-key`. It
+    /// changes whenever the sensor's token is rotated.
+    pub fn signing_key(&self, sensor: &Sensor) -> Option<String> {
+        let master = self.signing_key.as_ref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(master.as_bytes()).ok()?;
+        mac.update(format!("{}\n{}", sensor.id(), sensor.token_hash()).as_bytes());
+        Some(hex::encode(mac.finalize().into_bytes()))
+    }
+
+    /// Whether requests with only the bearer token are turned away.
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// Returns the sensor id of a correctly signed request, `None` when it
+    /// carries no signature at all.
+    pub fn verify(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Option<String>, BruteResponeError> {
+        self.verify_at(method, path, headers, body, unix_now())
+    }
+
+    fn verify_at(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+        now: i64,
+    ) -> Result<Option<String>, BruteResponeError> {
+        let Some(signature) = header(headers, SIGNATURE_HEADER) else {
+            return Ok(None);
+        };
+        let sensor = header(headers, SENSOR_HEADER).ok_or_else(|| unauthorized("missing sensor"))?;
+        let nonce = header(headers, NONCE_HEADER).ok_or_else(|| unauthorized("missing nonce"))?;
+        let timestamp: i64 = header(headers, TIMESTAMP_HEADER)
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| unauthorized("missing or invalid timestamp"))?;
+
+        let secret = match self.sensors.get(sensor) {
+            Some(registered) if registered.is_revoked() => return Err(unauthorized("sensor is revoked")),
+            Some(registered) => self
+                .signing_key(&registered)
+                .ok_or_else(|| unauthorized("registered sensors can't sign without a signing key"))?
+                .into_bytes(),
+            None => self
+                .secrets
+                .get(sensor)
+                .cloned()
+                .ok_or_else(|| unauthorized("unknown sensor"))?,
+        };
+        if now.abs_diff(timestamp) > self.window as u64 {
+            return Err(unauthorized("timestamp is outside the allowed window"));
+        }
+
+        let signature = hex::decode(signature).map_err(|_| unauthorized("malformed signature"))?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(&secret)
+            .map_err(|e| BruteResponeError::InternalError(e.to_string()))?;
+        mac.update(signing_string(method, path, timestamp, nonce, body).as_bytes());
+        // constant time.
+        mac.verify_slice(&signature).map_err(|_| unauthorized("signature mismatch"))?;
+
+        // only remembered once the signature checks out, so forged requests can't fill the cache.
+        // the window is inclusive, so the nonce is still needed at timestamp + window.
+        let key = (sensor.to_string(), nonce.to_string());
+        if !self.nonces.lock().unwrap().insert(key, timestamp.saturating_add(self.window + 1), now) {
+            return Err(unauthorized("nonce was already used"));
+        }
+
+        Ok(Some(sensor.to_string()))
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn unauthorized(reason: &str) -> BruteResponeError {
+    BruteResponeError::Unauthorized(format!("invalid signature: {}.", reason))
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    const BODY: &[u8] = br#"{"username":"root"}"#;
+
+    fn verifier(signing_key: Option<&str>) -> SignatureVerifier {
+        let secrets = vec!["sensor-1:static-secret".parse().unwrap()];
+        SignatureVerifier::new(secrets, signing_key.map(str::to_string), Arc::default(), 300, false)
+    }
+
+    fn signed(key: &[u8], nonce: &str) -> HeaderMap {
+        signed_at(key, nonce, unix_now())
+    }
+
+    fn signed_at(key: &[u8], nonce: &str, timestamp: i64) -> HeaderMap {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(signing_string("POST", "/brute/attack/add", timestamp, nonce, BODY).as_bytes());
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            (SENSOR_HEADER, "sensor-1".to_string()),
+            (TIMESTAMP_HEADER, timestamp.to_string()),
+            (NONCE_HEADER, nonce.to_string()),
+            (SIGNATURE_HEADER, hex::encode(mac.finalize().into_bytes())),
+        ] {
+            headers.insert(HeaderName::from_bytes(name.as_bytes()).unwrap(), HeaderValue::from_str(&value).unwrap());
+        }
+        headers
+    }
+
+    fn verify(verifier: &SignatureVerifier, headers: &HeaderMap) -> Result<Option<String>, BruteResponeError> {
+        verifier.verify("POST", "/brute/attack/add", headers, BODY)
+    }
+
+    #[test]
+    fn static_secrets_sign_as_they_are() {
+        let verifier = verifier(None);
+        let sensor = verify(&verifier, &signed(b"static-secret", "n1")).unwrap();
+        assert_eq!(sensor.as_deref(), Some("sensor-1"));
+
+        // the hash of the secret is not the key.
+        let hashed = hex::encode(Sha256::digest(b"static-secret"));
+        assert!(verify(&verifier, &signed(hashed.as_bytes(), "n2")).is_err());
+    }
+
+    #[test]
+    fn replays_and_tampering_are_rejected() {
+        let verifier = verifier(None);
+        let headers = signed(b"static-secret", "n1");
+        assert!(verify(&verifier, &headers).is_ok());
+        assert!(verify(&verifier, &headers).is_err());
+        assert!(verifier.verify("POST", "/brute/attack/add", &signed(b"static-secret", "n2"), b"{}").is_err());
+        assert!(verify(&verifier, &signed(b"wrong-secret", "n3")).is_err());
+    }
+
+    #[test]
+    fn replays_are_rejected_up_to_the_edge_of_the_window() {
+        let verifier = verifier(None);
+        let headers = signed_at(b"static-secret", "n1", 1_000);
+        let verify_at = |headers: &HeaderMap, now| verifier.verify_at("POST", "/brute/attack/add", headers, BODY, now);
+        assert!(verify_at(&headers, 1_000).is_ok());
+        // another request prunes whatever has expired by now.
+        assert!(verify_at(&signed_at(b"static-secret", "n2", 1_300), 1_300).is_ok());
+        assert!(verify_at(&headers, 1_300).is_err());
+        assert!(verify_at(&signed_at(b"static-secret", "n3", 1_000), 1_300).is_ok());
+        assert!(verify_at(&signed_at(b"static-secret", "n4", 1_000), 1_301).is_err());
+    }
+
+    #[test]
+    fn extreme_timestamps_are_outside_the_window() {
+        let verifier = verifier(None);
+        for (nonce, timestamp) in [("n1", i64::MIN), ("n2", i64::MIN + 1), ("n3", i64::MAX)] {
+            let headers = signed_at(b"static-secret", nonce, timestamp);
+            assert!(verifier.verify_at("POST", "/brute/attack/add", &headers, BODY, 1_000).is_err());
+        }
+        // a window reaching past i64::MAX still remembers the nonce.
+        let headers = signed_at(b"static-secret", "n4", i64::MAX - 10);
+        assert!(verifier.verify_at("POST", "/brute/attack/add", &headers, BODY, i64::MAX - 10).is_ok());
+        assert!(verifier.verify_at("POST", "/brute/attack/add", &headers, BODY, i64::MAX - 10).is_err());
+    }
+
+    #[test]
+    fn unsigned_requests_are_left_to_the_other_checks() {
+        assert_eq!(verify(&verifier(None), &HeaderMap::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn registered_sensors_need_the_signing_key() {
+        let sensor = Sensor::default();
+        assert_eq!(verifier(None).signing_key(&sensor), None);
+        let one = verifier(Some("a-signing-key-of-32-characters!!")).signing_key(&sensor).unwrap();
+        let other = verifier(Some("another-signing-key-of-32-chars!")).signing_key(&sensor).unwrap();
+        assert_ne!(one, other);
+    }
+
+    fn key(nonce: &str) -> (String, String) {
+        ("sensor-1".to_string(), nonce.to_string())
+    }
+
+    #[test]
+    fn nonces_are_rejected_until_they_expire() {
+        let mut nonces = Nonces::default();
+        assert!(nonces.insert(key("a"), 100, 0));
+        assert!(!nonces.insert(key("a"), 100, 50));
+        assert!(nonces.insert(key("a"), 200, 100));
+    }
+
+    #[test]
+    fn expired_nonces_are_pruned_on_every_insert() {
+        let mut nonces = Nonces::default();
+        for (i, expires) in [10, 20, 30].into_iter().enumerate() {
+            nonces.insert(key(&i.to_string()), expires, 0);
+        }
+        nonces.insert(key("new"), 40, 20);
+        assert_eq!(nonces.seen.len(), 2);
+        assert_eq!(nonces.expiry.len(), 2);
+    }
+
+    #[test]
+    fn a_reused_nonce_keeps_its_new_expiry() {
+        let mut nonces = Nonces::default();
+        nonces.insert(key("a"), 10, 0);
+        nonces.insert(key("a"), 50, 10);
+        // pruning the first expiry must not forget the second use.
+        nonces.insert(key("b"), 60, 20);
+        assert!(!nonces.insert(key("a"), 70, 30));
+    }
+}
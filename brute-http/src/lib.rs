@@ -1,6 +1,8 @@
 pub mod config;
+pub mod enrichment;
 pub mod error;
 pub mod http;
 pub mod system;
 pub mod model;
+pub mod tls;
 pub mod validator;
\ No newline at end of file
@@ -1,13 +1,17 @@
-use std::{env::var, fs::File, io::BufReader, path::Path};
+use std::{fs::File, io::BufReader, num::{NonZeroU32, NonZeroUsize}, sync::Arc, time::Duration};
 
 use actix::Actor;
-use brute_http::{config::Config, http::{serve, serve_tls}, system::BruteSystem};
-use clap::Parser;
-use ipinfo::{IpInfo, IpInfoConfig};
-use log::info;
+use brute_http::{config::{Command, Config, EnrichmentProvider}, enrichment::{Enricher, EnrichmentCache, FallbackEnricher, IpinfoEnricher, MmdbEnricher, RdnsEnricher}, http::{auth::{ApiKey, ApiKeys, Role}, serve, serve_tls, signature::SignatureVerifier, AppState}, system::{BruteSystem, Drain, ListReenrichJobs, ListSensors, PruneAttempts, ResumeReenrich, RetryEnrichment}, tls::{generate_self_signed, spawn_reload, CertificatePaths, CertificateResolver}, validator::IpFilter};
+use log::{info, warn};
+use rustls::server::WebPkiClientVerifier;
 use sqlx::{migrate::Migrator, postgres::PgPoolOptions};
+use tokio::time::{timeout_at, Instant};
 
-static CERTS_DIRECTORY: &str = "certs";
+/// How often attempts past the retention period are deleted.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How often attempts whose enrichment failed are looked for.
+const ENRICHMENT_RETRY_INTERVAL: Duration = Duration::from_secs(30);
 
 #[actix_rt::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -16,8 +20,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     /////////////////////
     // ENV AND LOGGER //
     ///////////////////
+    // optional, everything can come from --config instead.
     #[cfg(debug_assertions)]
-    dotenvy::dotenv().unwrap();
+    dotenvy::dotenv().ok();
 
     env_logger::builder()
         .filter_module("async_io", log::LevelFilter::Off)
@@ -31,13 +36,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .filter_module("rustls", log::LevelFilter::Off)
         .init();
 
-    ////////////////////
-    // ENV VARIABLES //
-    //////////////////
-    let listen_address = var("LISTEN_ADDRESS").expect("LISTEN_ADDRESS should be set");
-    let listen_address_tls = var("LISTEN_ADDRESS_TLS").expect("LISTEN_ADDRESS_TLS should be set");
-    let running_in_docker = var("RUNNING_IN_DOCKER").expect("RUNNING_IN_DOCKER should be set");
-    let bearer_token = var("BEARER_TOKEN").expect("BEARER_TOKEN should be set");
+    /////////////
+    // CONFIG //
+    ///////////
+    let config = Config::load()?;
+    if config.print_config {
+        print!("{}", config.redacted()?);
+        return Ok(());
+    }
 
     //////////
     // TLS //
@@ -46,28 +52,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .install_default()
         .unwrap();
 
-    let mut certs_file =
-        BufReader::new(File::open(format!("{}/cert.pem", CERTS_DIRECTORY)).unwrap());
-    let mut key_file = BufReader::new(File::open(format!("{}/key.pem", CERTS_DIRECTORY)).unwrap());
-
-    let tls_certs = rustls_pemfile::certs(&mut certs_file)
-        .collect::<Result<Vec<_>, _>>()
-        .unwrap();
-    let tls_key = rustls_pemfile::pkcs8_private_keys(&mut key_file)
-        .next()
-        .unwrap()
-        .unwrap();
-
-    let tls_config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(tls_certs, rustls::pki_types::PrivateKeyDer::Pkcs8(tls_key))
-        .unwrap();
-
-    ///////////
-    // CLAP //
-    /////////
-    let config = Config::parse();
-
     ///////////
     // SQLX //
     /////////
@@ -80,13 +64,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     /////////////////////
     // SQLX MIGRATION //
     ///////////////////
-    let migration_path = if running_in_docker.parse::<bool>().unwrap_or(false) {
-        Path::new("migrations")
-    } else {
-        Path::new("..\\migrations\\")
-    };
-
-    Migrator::new(migration_path)
+    Migrator::new(config.migrations_directory())
         .await
         .map_err(|e| format!("Failed to create migrator: {}", e))
         .unwrap()
@@ -97,36 +75,285 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Migration process completed successfully.");
 
-    /////////////
-    // IPINFO //
-    ///////////
-    let ipinfo_config = IpInfoConfig {
-        token: Some(config.ipinfo_token.to_string()),
-        ..Default::default()
+    // checked here rather than on the first report.
+    sqlx::query("SELECT now() AT TIME ZONE $1;")
+        .bind(&config.stats_timezone)
+        .execute(&db)
+        .await
+        .map_err(|_| format!("stats_timezone: '{}' isn't a timezone the database knows.", config.stats_timezone))?;
+
+    /////////////////
+    // ENRICHMENT //
+    ///////////////
+    let open_mmdb = || match &config.mmdb_city {
+        Some(city) => MmdbEnricher::open(city, config.mmdb_asn.as_deref()),
+        None => Err("mmdb_city is not set.".to_string()),
     };
-    let ipinfo_client = IpInfo::new(ipinfo_config)
-        .map_err(|e| format!("Failed to create IpInfo client: {}", e))
-        .unwrap();
+    let mut enricher: Box<dyn Enricher> = match config.enrichment_provider {
+        EnrichmentProvider::Ipinfo => Box::new(IpinfoEnricher::new(config.ipinfo_token.clone())?),
+        EnrichmentProvider::Mmdb => Box::new(open_mmdb()?),
+        EnrichmentProvider::MmdbIpinfo => Box::new(FallbackEnricher::new(
+            Box::new(open_mmdb()?),
+            Box::new(IpinfoEnricher::new(config.ipinfo_token.clone())?),
+        )),
+    };
+    if config.rdns {
+        enricher = Box::new(RdnsEnricher::new(enricher, config.rdns_resolver)?);
+    }
+    let enrichment = EnrichmentCache::new(
+        db.clone(),
+        enricher,
+        Duration::from_secs(config.enrichment_cache_ttl),
+        NonZeroUsize::new(config.enrichment_cache_size).ok_or("enrichment_cache_size must be at least 1.")?,
+        config.enrichment_rate_limit.and_then(NonZeroU32::new),
+    );
 
     ////////////
     // ACTOR //
     //////////
-    let brute_system = BruteSystem::new_brute(db, ipinfo_client).await;
+    let brute_system = BruteSystem::new_brute(
+        db,
+        enrichment,
+        config.enrichment_workers,
+        &config.stats_timezone,
+        config.retention_days.is_some(),
+    )
+    .await;
+    match brute_system.align_time_buckets().await {
+        Ok(Some(previous)) => info!("Realigned the time buckets from {} to {}.", previous, config.stats_timezone),
+        Ok(None) => {}
+        Err(e) => return Err(format!("Failed to realign the time buckets: {}", e).into()),
+    }
+    if let Some(Command::Reenrich { from, to, resume }) = config.command.clone() {
+        return reenrich(&brute_system, from, to, resume).await;
+    }
+    let sensors = brute_system.sensors.clone();
+    let ip_filter = IpFilter::new(config.ignore_cidrs.clone(), config.sensor_cidrs.clone());
+    // the shared BEARER_TOKEN can only report attempts.
+    let mut api_keys = config.api_keys.clone();
+    api_keys.push(ApiKey { role: Role::Ingest, key: config.bearer_token.clone() });
+    let keys = Arc::new(ApiKeys::new(api_keys));
+    let verifier = Arc::new(SignatureVerifier::new(
+        config.sensor_secrets.clone(),
+        config.signing_key.clone(),
+        sensors.clone(),
+        config.signature_window,
+        config.require_signature,
+    ));
     let brute_actor = brute_system.start();
 
+    //////////////
+    // SENSORS //
+    ////////////
+    let registered = brute_actor
+        .send(ListSensors)
+        .await
+        .map_err(|e| format!("Failed to load sensors: {}", e))?
+        .map_err(|e| format!("Failed to load sensors: {}", e))?;
+    info!("Loaded {} sensor(s).", registered.len());
+    sensors.replace(registered);
+
+    // jobs that were running when brute-http stopped.
+    let jobs = brute_actor
+        .send(ListReenrichJobs)
+        .await
+        .map_err(|e| format!("Failed to load re-enrichment jobs: {}", e))?
+        .map_err(|e| format!("Failed to load re-enrichment jobs: {}", e))?;
+    for job in jobs.into_iter().filter(|job| !job.is_finished()) {
+        if let Ok(Err(e)) = brute_actor.send(ResumeReenrich { id: job.id().clone() }).await {
+            warn!("Failed to resume re-enrichment job {}: {}", job.id(), e);
+        }
+    }
+    let refresh_actor = brute_actor.clone();
+    let refresh_sensors = sensors.clone();
+    let sensor_refresh_interval = Duration::from_secs(config.sensor_refresh_interval);
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(sensor_refresh_interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match refresh_actor.send(ListSensors).await {
+                Ok(Ok(list)) => refresh_sensors.replace(list),
+                Ok(Err(e)) => warn!("Failed to reload sensors: {}", e),
+                Err(e) => warn!("Failed to reload sensors: {}", e),
+            }
+        }
+    });
+
+    ///////////////////////
+    // ENRICHMENT RETRY //
+    /////////////////////
+    let retry_actor = brute_actor.clone();
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(ENRICHMENT_RETRY_INTERVAL);
+        loop {
+            interval.tick().await;
+            match retry_actor.send(RetryEnrichment).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(queued)) => info!("Retrying enrichment of {} attempt(s).", queued),
+                Ok(Err(e)) => warn!("Failed to retry enrichment: {}", e),
+                Err(e) => warn!("Failed to retry enrichment: {}", e),
+            }
+        }
+    });
+
+    ////////////////
+    // RETENTION //
+    //////////////
+    if let Some(retention_days) = config.retention_days {
+        let retention = Duration::from_secs(u64::from(retention_days) * 86_400);
+        let retention_actor = brute_actor.clone();
+        actix_rt::spawn(async move {
+            let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+            loop {
+                interval.tick().await;
+                match retention_actor.send(PruneAttempts { older_than: retention }).await {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(deleted)) => info!("Deleted {} attempt(s) past the retention period.", deleted),
+                    Ok(Err(e)) => warn!("Failed to delete old attempts: {}", e),
+                    Err(e) => warn!("Failed to delete old attempts: {}", e),
+                }
+            }
+        });
+    }
+
+    ///////////////////
+    // CERTIFICATES //
+    /////////////////
+    let cert_paths = CertificatePaths::new(&config.certs_directory);
+    if config.self_signed_cert && generate_self_signed(&cert_paths)? {
+        warn!("Generated a self-signed certificate in {}.", config.certs_directory.display());
+    }
+    let cert_resolver = Arc::new(CertificateResolver::load(cert_paths)?);
+
+    // sensors may authenticate with a client certificate, everyone else
+    // can still connect without one.
+    let tls_builder = match &config.client_ca {
+        Some(client_ca) => {
+            let mut roots = rustls::RootCertStore::empty();
+            let mut ca_file = BufReader::new(
+                File::open(client_ca)
+                    .map_err(|e| format!("Failed to open {}: {}", client_ca.display(), e))?,
+            );
+            for ca in rustls_pemfile::certs(&mut ca_file) {
+                roots.add(ca?)?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .allow_unauthenticated()
+                .build()?;
+            info!("Accepting client certificates issued by {}.", client_ca.display());
+            rustls::ServerConfig::builder().with_client_cert_verifier(verifier)
+        }
+        None => rustls::ServerConfig::builder().with_no_client_auth(),
+    };
+    let tls_config = tls_builder.with_cert_resolver(cert_resolver.clone());
+    spawn_reload(cert_resolver);
+
     ////////////////////////////////////
     // HTTP SERVER (TLS and NON-TLS) //
     //////////////////////////////////
-    let (ip_tls, port_tls) = listen_address_tls
-        .split_once(':')
-        .expect("Invalid LISTEN_ADDRESS_TLS format. Expected format: IP:PORT");
+    let state = AppState::new(
+        brute_actor.clone(),
+        keys,
+        ip_filter,
+        verifier,
+        sensors,
+        config.limits(),
+        config.cors_origins.clone().into(),
+    );
+    let server_tls = serve_tls(config.listen_address_tls, state.clone(), tls_config, config.shutdown_timeout)?;
+    let server = serve(config.listen_address, state, config.shutdown_timeout)?;
+    let (handle_tls, handle) = (server_tls.handle(), server.handle());
+    let servers = async { tokio::try_join!(server_tls, server) };
+    tokio::pin!(servers);
 
-    let (ip, port) = listen_address
-        .split_once(':')
-        .expect("Invalid LISTEN_ADDRESS format. Expected format: IP:PORT");
+    tokio::select! {
+        result = &mut servers => {
+            result?;
+            return Ok(());
+        }
+        _ = shutdown_signal() => {}
+    }
 
-    let serve_tls_future = serve_tls(ip_tls, port_tls.parse::<u16>().unwrap(), brute_actor.clone(), tls_config, bearer_token.clone());
-    let serve_future = serve(ip, port.parse::<u16>().unwrap(), brute_actor, bearer_token);
-    tokio::try_join!(serve_tls_future, serve_future)?;
+    ///////////////
+    // SHUTDOWN //
+    /////////////
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout);
+    info!("Shutting down, waiting up to {:?} for requests and reports.", shutdown_timeout);
+    let deadline = Instant::now() + shutdown_timeout;
+
+    // stops accepting and lets in-flight requests finish.
+    let stopped = async { tokio::join!(handle_tls.stop(true), handle.stop(true), &mut servers) };
+    if timeout_at(deadline, stopped).await.is_err() {
+        warn!("HTTP servers did not stop before the shutdown deadline.");
+    }
+    // reports already handed to the actor are written before exiting.
+    if timeout_at(deadline, brute_actor.send(Drain)).await.is_err() {
+        warn!("Some reports were still being written at the shutdown deadline.");
+    }
     Ok(())
+}
+
+/// `brute-http reenrich`, runs the job in the foreground and prints its
+/// progress.
+async fn reenrich(
+    brute_system: &BruteSystem,
+    from: Option<i64>,
+    to: Option<i64>,
+    resume: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (from, to) = (from.unwrap_or(0), to.unwrap_or(i64::MAX));
+    if from >= to {
+        return Err("--from must be before --to.".into());
+    }
+    let lock = brute_system.lock_reenrich().await.map_err(|e| e.to_string())?;
+    let job = match resume {
+        Some(id) => brute_system.resume_reenrich(&id).await,
+        None => brute_system.create_reenrich(from, to).await,
+    }
+    .map_err(|e| e.to_string())?;
+    println!("Re-enrichment job {}, resume it with --resume {} if it's interrupted.", job.id(), job.id());
+
+    let job = brute_system
+        .run_reenrich(lock, job, |job| {
+            println!(
+                "{}: {}/{} IP(s) looked up, {} failed.",
+                job.status(),
+                job.enriched_ips() + job.failed_ips(),
+                job.total_ips(),
+                job.failed_ips()
+            )
+        })
+        .await;
+    if *job.counters_rebuilt() == Some(false) {
+        println!("Only the location counters of the re-enriched IPs were updated, retention_days prunes the attempts the others would be recounted from.");
+    }
+    match job.error() {
+        Some(error) => Err(format!("Re-enrichment job {} failed: {}", job.id(), error).into()),
+        None => Ok(()),
+    }
+}
+
+/// Waits for SIGINT (Ctrl-C) or, on unix, SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM, only Ctrl-C shuts down: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
\ No newline at end of file
@@ -1,9 +1,11 @@
+use std::{fmt, str::FromStr};
+
 use actix::Message;
 use derive_getters::Getters;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 
-use crate::{error::BruteResponeError, system::RequestWithLimit};
+use crate::{error::BruteResponeError, system::{CreateSensor, Drain, GetReenrichJob, ListReenrichJobs, GetProfile, ListSensors, PruneAttempts, ReportBatch, RequestWithLimit, ResumeReenrich, RetryEnrichment, RevokeSensor, RotateSensor, SearchAttacks, StartReenrich, Timeseries}};
 
 #[derive(Default, Clone, Debug, sqlx::FromRow, Getters, Serialize)]
 pub struct Individual {
@@ -13,6 +15,8 @@ pub struct Individual {
     ip: String,
     pub protocol: String,
     pub timestamp: i64,
+    /// Sensor that reported the attempt, `None` for the shared token.
+    pub sensor_id: Option<String>,
 }
 
 impl Individual {
@@ -24,6 +28,7 @@ impl Individual {
             ip,
             protocol,
             timestamp,
+            sensor_id: None,
         }
     }
 
@@ -35,13 +40,119 @@ impl Individual {
             ip, 
             protocol,
             timestamp: 0,
+            sensor_id: None,
         }
     }
 }
 
 // allow as a message in actix actor.
 impl Message for Individual {
-    type Result = Result<ProcessedIndividual, BruteResponeError>;
+    type Result = Result<Individual, BruteResponeError>;
+}
+
+impl Message for ReportBatch {
+    type Result = Result<Vec<Individual>, BruteResponeError>;
+}
+
+impl Message for Drain {
+    type Result = ();
+}
+
+impl Message for PruneAttempts {
+    type Result = Result<u64, BruteResponeError>;
+}
+
+impl Message for RetryEnrichment {
+    type Result = Result<usize, BruteResponeError>;
+}
+
+#[derive(Default, Clone, Debug, sqlx::FromRow, Getters, Serialize)]
+pub struct Sensor {
+    id: String,
+    name: String,
+    #[serde(skip_serializing)]
+    token_hash: String,
+    created_at: i64,
+    rotated_at: Option<i64>,
+    revoked_at: Option<i64>,
+}
+
+impl Sensor {
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+}
+
+/// A sensor with its plaintext token, only returned when the token is
+/// created or rotated.
+#[derive(Debug, Serialize)]
+pub struct SensorToken {
+    pub sensor: Sensor,
+    pub token: String,
+    /// Key the sensor signs requests with, only when `signing_key` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<String>,
+}
+
+/// Progress of a re-enrichment job.
+#[derive(Default, Clone, Debug, sqlx::FromRow, Getters, Serialize)]
+pub struct ReenrichJob {
+    id: String,
+    range_from: i64,
+    range_to: i64,
+    /// `enriching`, `rebuilding`, `done` or `failed`.
+    status: String,
+    total_ips: i32,
+    enriched_ips: i32,
+    failed_ips: i32,
+    #[serde(skip_serializing)]
+    last_ip: String,
+    error: Option<String>,
+    /// Set once the job is done. `false` while `retention_days` prunes
+    /// attempts, only the location counters of the re-enriched IPs were
+    /// moved then.
+    counters_rebuilt: Option<bool>,
+    created_at: i64,
+    updated_at: i64,
+    finished_at: Option<i64>,
+}
+
+impl ReenrichJob {
+    pub fn is_finished(&self) -> bool {
+        self.status == "done" || self.status == "failed"
+    }
+}
+
+impl Message for StartReenrich {
+    type Result = Result<ReenrichJob, BruteResponeError>;
+}
+
+impl Message for ListReenrichJobs {
+    type Result = Result<Vec<ReenrichJob>, BruteResponeError>;
+}
+
+impl Message for GetReenrichJob {
+    type Result = Result<ReenrichJob, BruteResponeError>;
+}
+
+impl Message for ResumeReenrich {
+    type Result = Result<ReenrichJob, BruteResponeError>;
+}
+
+impl Message for CreateSensor {
+    type Result = Result<SensorToken, BruteResponeError>;
+}
+
+impl Message for ListSensors {
+    type Result = Result<Vec<Sensor>, BruteResponeError>;
+}
+
+impl Message for RotateSensor {
+    type Result = Result<SensorToken, BruteResponeError>;
+}
+
+impl Message for RevokeSensor {
+    type Result = Result<Sensor, BruteResponeError>;
 }
 
 #[derive(Default, Clone, Debug, sqlx::FromRow, Getters, Serialize, Deserialize)]
@@ -52,6 +163,8 @@ pub struct ProcessedIndividual {
     ip: String,
     protocol: String,
     hostname: Option<String>,
+    rdns: Option<String>,
+    rdns_confirmed: Option<bool>,
     city: Option<String>,
     region: Option<String>,
     timezone: String,
@@ -83,10 +196,141 @@ pub struct ProcessedIndividual {
     domain_total: Option<i64>,
     domains: Option<Vec<String>>,
     pub timestamp: i64,
+    sensor_id: Option<String>,
+}
+
+/// Location and network details of an IP, whichever enricher they came
+/// from. Text that feeds a counter is empty rather than missing.
+#[derive(Default, Clone, Debug, sqlx::FromRow, Serialize)]
+pub struct Enrichment {
+    pub hostname: Option<String>,
+    /// PTR name, trusted only when `rdns_confirmed`.
+    pub rdns: Option<String>,
+    pub rdns_confirmed: Option<bool>,
+    pub city: String,
+    pub region: String,
+    pub country: String,
+    pub loc: String,
+    pub org: Option<String>,
+    pub postal: Option<String>,
+    pub timezone: String,
+    pub asn: Option<String>,
+    pub asn_name: Option<String>,
+    pub asn_domain: Option<String>,
+    pub asn_route: Option<String>,
+    pub asn_type: Option<String>,
+    pub company_name: Option<String>,
+    pub company_domain: Option<String>,
+    pub company_type: Option<String>,
+    pub vpn: Option<bool>,
+    pub proxy: Option<bool>,
+    pub tor: Option<bool>,
+    pub relay: Option<bool>,
+    pub hosting: Option<bool>,
+    pub service: Option<String>,
+    pub abuse_address: Option<String>,
+    pub abuse_country: Option<String>,
+    pub abuse_email: Option<String>,
+    pub abuse_name: Option<String>,
+    pub abuse_network: Option<String>,
+    pub abuse_phone: Option<String>,
+    pub domain_ip: Option<String>,
+    pub domain_total: Option<i64>,
+    pub domains: Option<Vec<String>>,
+}
+
+/// A stored attempt and its IP's details, which are missing until it has
+/// been looked up.
+#[derive(Default, Clone, Debug, sqlx::FromRow, Getters, Serialize)]
+pub struct Attack {
+    id: String,
+    username: String,
+    password: String,
+    ip: String,
+    protocol: String,
+    timestamp: i64,
+    sensor_id: Option<String>,
+    /// `pending`, `enriched` or `failed`.
+    enrichment_status: String,
+    hostname: Option<String>,
+    rdns: Option<String>,
+    rdns_confirmed: Option<bool>,
+    city: Option<String>,
+    region: Option<String>,
+    timezone: Option<String>,
+    country: Option<String>,
+    loc: Option<String>,
+    org: Option<String>,
+    postal: Option<String>,
+    asn: Option<String>,
+    asn_name: Option<String>,
+    asn_domain: Option<String>,
+    asn_route: Option<String>,
+    asn_type: Option<String>,
+    company_name: Option<String>,
+    company_domain: Option<String>,
+    company_type: Option<String>,
+    vpn: Option<bool>,
+    proxy: Option<bool>,
+    tor: Option<bool>,
+    relay: Option<bool>,
+    hosting: Option<bool>,
+    service: Option<String>,
+    abuse_address: Option<String>,
+    abuse_country: Option<String>,
+    abuse_email: Option<String>,
+    abuse_name: Option<String>,
+    abuse_network: Option<String>,
+    abuse_phone: Option<String>,
+    domain_ip: Option<String>,
+    domain_total: Option<i64>,
+    domains: Option<Vec<String>>,
+}
+
+/// Where a page of attacks ended, the next page starts with the attempt
+/// that comes after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttackCursor {
+    pub timestamp: i64,
+    pub id: String,
 }
 
-impl Message for RequestWithLimit<ProcessedIndividual> {
-    type Result = Result<Vec<ProcessedIndividual>, BruteResponeError>;
+impl fmt::Display for AttackCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.timestamp, self.id)
+    }
+}
+
+impl FromStr for AttackCursor {
+    type Err = ();
+
+    fn from_str(cursor: &str) -> Result<Self, Self::Err> {
+        let (timestamp, id) = cursor.split_once('-').ok_or(())?;
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(());
+        }
+        Ok(Self {
+            timestamp: timestamp.parse().map_err(|_| ())?,
+            id: id.to_string(),
+        })
+    }
+}
+
+impl Serialize for AttackCursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Attempts newest first, `next_cursor` is unset on the last page.
+#[derive(Debug, Serialize)]
+pub struct AttackPage {
+    pub attacks: Vec<Attack>,
+    pub next_cursor: Option<AttackCursor>,
+}
+
+impl Message for SearchAttacks {
+    type Result = Result<AttackPage, BruteResponeError>;
 }
 
 #[derive(Default, Debug, sqlx::FromRow, Getters, Serialize, Deserialize)]
@@ -205,6 +449,12 @@ pub struct TopOrg {
     amount: i32,
 }
 
+impl TopOrg {
+    pub fn new(org: String, amount: i32) -> Self {
+        TopOrg { org, amount }
+    }
+}
+
 impl Message for RequestWithLimit<TopOrg> {
     type Result = Result<Vec<TopOrg>, BruteResponeError>;
 }
@@ -240,6 +490,8 @@ impl Message for RequestWithLimit<TopLocation> {
 
 #[derive(Default, Debug, sqlx::FromRow, Getters, Serialize, Deserialize)]
 pub struct TopUsrPassCombo {
+    /// Random, given when the combination is first counted. The same with
+    /// or without `from`/`to`.
     id: String,
     username: String,
     password: String,
@@ -272,20 +524,132 @@ impl Message for RequestWithLimit<TopHourly> {
     type Result = Result<Vec<TopHourly>, BruteResponeError>;
 }
 
-#[derive(Debug, Clone, sqlx::FromRow, Getters)]
+#[derive(Default, Debug, Clone, sqlx::FromRow, Getters, Serialize)]
 pub struct TopDaily {
     pub timestamp: i64,
     pub amount: i32,
 }
 
-#[derive(Debug, Clone, sqlx::FromRow, Getters)]
+impl Message for RequestWithLimit<TopDaily> {
+    type Result = Result<Vec<TopDaily>, BruteResponeError>;
+}
+
+#[derive(Default, Debug, Clone, sqlx::FromRow, Getters, Serialize)]
 pub struct TopWeekly {
     pub timestamp: i64,
     pub amount: i32,
 }
 
-#[derive(Debug, Clone, sqlx::FromRow, Getters)]
+impl Message for RequestWithLimit<TopWeekly> {
+    type Result = Result<Vec<TopWeekly>, BruteResponeError>;
+}
+
+#[derive(Default, Debug, Clone, sqlx::FromRow, Getters, Serialize)]
 pub struct TopYearly {
     pub timestamp: i64,
     pub amount: i32,
 }
+
+impl Message for RequestWithLimit<TopYearly> {
+    type Result = Result<Vec<TopYearly>, BruteResponeError>;
+}
+
+/// Width of the buckets in a timeseries.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeBucket {
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl TimeBucket {
+    /// The `date_trunc` field.
+    pub fn unit(self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    /// The shortest a bucket can be, months and DST days vary.
+    pub fn min_millis(self) -> i64 {
+        match self {
+            Self::Hour => 3_600_000,
+            Self::Day => 82_800_000,
+            Self::Week => 601_200_000,
+            Self::Month => 2_415_600_000,
+        }
+    }
+}
+
+/// Attempts in the bucket that starts at `timestamp`.
+#[derive(Default, Debug, Clone, sqlx::FromRow, Getters, Serialize)]
+pub struct TimeseriesPoint {
+    pub timestamp: i64,
+    pub amount: i64,
+}
+
+impl Message for Timeseries {
+    type Result = Result<Vec<TimeseriesPoint>, BruteResponeError>;
+}
+
+#[derive(Default, Debug, sqlx::FromRow, Getters, Serialize)]
+pub struct TopSensor {
+    sensor_id: String,
+    amount: i32,
+}
+
+/// What an IP or a network has done. Everything but
+/// `total_attempts_all_time` is counted from the attempts that are still
+/// stored.
+#[derive(Debug, Serialize)]
+pub struct Profile {
+    pub network: String,
+    pub total_attempts: i64,
+    /// Every attempt ever reported, past the retention period too.
+    pub total_attempts_all_time: i64,
+    pub first_seen: Option<i64>,
+    pub last_seen: Option<i64>,
+    pub protocols: Vec<TopProtocol>,
+    pub usernames: Vec<TopUsername>,
+    pub passwords: Vec<TopPassword>,
+    pub sensors: Vec<TopSensor>,
+    /// Attempts per bucket from `first_seen` to `last_seen`.
+    pub activity: Vec<TimeseriesPoint>,
+    /// The IP's details, only for a single IP.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enrichment: Option<Enrichment>,
+    /// The most active IPs, only for a network.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ips: Option<Vec<TopIp>>,
+}
+
+impl Message for GetProfile {
+    type Result = Result<Profile, BruteResponeError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = AttackCursor {
+            timestamp: 1_727_740_800_000,
+            id: "0a1b2c3d".to_string(),
+        };
+        assert_eq!(cursor.to_string(), "1727740800000-0a1b2c3d");
+        assert_eq!(cursor.to_string().parse::<AttackCursor>(), Ok(cursor));
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        for invalid in ["", "1727740800000", "1727740800000-", "-0a1b", "now-0a1b", "1-0a1b-2", "1-xyz"] {
+            assert!(invalid.parse::<AttackCursor>().is_err(), "{}", invalid);
+        }
+    }
+}
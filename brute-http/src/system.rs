@@ -2,19 +2,34 @@ use actix::{
     Actor, ActorFutureExt, AsyncContext, Context, Handler, ResponseActFuture, ResponseFuture,
     WrapFuture,
 };
-use ipinfo::IpInfo;
-use log::{error, info};
+use ipnetwork::IpNetwork;
+use log::{error, info, warn};
 use reporter::BruteReporter;
-use sqlx::{Pool, Postgres};
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use sha2::{Digest, Sha256};
+use sqlx::{postgres::PgRow, FromRow, PgConnection, Pool, Postgres};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+use tokio::{
+    sync::{mpsc, Mutex, Notify},
+    task::JoinSet,
+};
 
 use crate::{
+    enrichment::{Enriched, EnrichmentCache},
     error::BruteResponeError,
+    http::{auth::constant_time_eq, websocket},
     model::{
-        Individual, ProcessedIndividual, TopCity, TopCountry, TopHourly, TopIp, TopLocation,
-        TopOrg, TopPassword, TopPostal, TopProtocol, TopRegion, TopTimezone, TopUsername,
-        TopUsrPassCombo,
+        Attack, AttackCursor, AttackPage, Enrichment, Individual, Profile, ReenrichJob, Sensor, SensorToken, TimeBucket, TimeseriesPoint,
+        TopCity, TopCountry, TopDaily, TopHourly, TopIp, TopLocation, TopOrg, TopPassword, TopPostal,
+        TopProtocol, TopRegion, TopSensor, TopTimezone, TopUsername, TopUsrPassCombo, TopWeekly, TopYearly,
     },
 };
 
@@ -28,6 +43,369 @@ pub struct RequestWithLimit<T> {
     pub table: T, // just call ::default()
     pub limit: usize,
     pub max_limit: usize,
+    /// Only count attempts reported by this sensor.
+    pub sensor: Option<String>,
+    /// Only count attempts from this time on, in milliseconds since the epoch.
+    pub from: Option<i64>,
+    /// Only count attempts before this time, in milliseconds since the epoch.
+    pub to: Option<i64>,
+}
+
+impl<T> RequestWithLimit<T> {
+    /// `None` when every attempt is counted, the counters answer that.
+    fn filter(&self) -> Option<StatsFilter> {
+        if self.sensor.is_none() && self.from.is_none() && self.to.is_none() {
+            return None;
+        }
+        Some(StatsFilter {
+            sensor: self.sensor.clone(),
+            from: self.from.unwrap_or(0),
+            to: self.to.unwrap_or(i64::MAX),
+        })
+    }
+}
+
+/// Attempts a stats request is counted from when the counters can't answer
+/// it.
+struct StatsFilter {
+    sensor: Option<String>,
+    from: i64,
+    to: i64,
+}
+
+/// Registers a sensor and issues its first token.
+pub struct CreateSensor {
+    pub id: String,
+    pub name: String,
+}
+
+pub struct ListSensors;
+
+/// Issues a new token, the old one stops working immediately.
+pub struct RotateSensor {
+    pub id: String,
+}
+
+/// Permanently disables a sensor's token, its attempts are kept.
+pub struct RevokeSensor {
+    pub id: String,
+}
+
+/// Stores every attempt in one transaction, so either all of them are
+/// stored or none is and the sender can retry the whole batch.
+pub struct ReportBatch {
+    pub attempts: Vec<Individual>,
+}
+
+/// Resolves once every `Individual` the actor has accepted is fully written
+/// and enriched.
+pub struct Drain;
+
+/// Deletes raw attempts older than `older_than`, counters are kept.
+/// Resolves to how many attempts were deleted.
+pub struct PruneAttempts {
+    pub older_than: Duration,
+}
+
+/// Queues pending attempts whose retry is due, resolves to how many.
+pub struct RetryEnrichment;
+
+/// Looks up the IPs of attempts in `[from, to)` again, then rebuilds every
+/// counter. Resolves once the job is started.
+pub struct StartReenrich {
+    pub from: i64,
+    pub to: i64,
+}
+
+pub struct ListReenrichJobs;
+
+pub struct GetReenrichJob {
+    pub id: String,
+}
+
+/// Continues an interrupted or failed job from where it stopped.
+pub struct ResumeReenrich {
+    pub id: String,
+}
+
+/// Stored attempts newest first, only those matching every filter that's
+/// set. Resolves to one page and where the next one starts.
+pub struct SearchAttacks {
+    pub limit: usize,
+    /// Only attempts that come after this one.
+    pub after: Option<AttackCursor>,
+    pub ip: Option<IpAddr>,
+    pub cidr: Option<IpNetwork>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub protocol: Option<String>,
+    pub country: Option<String>,
+    /// Like `AS15169`.
+    pub asn: Option<String>,
+    pub sensor: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+/// Everything stored about the attempts from `network`, a single IP is a
+/// /32 or /128. The top lists have at most `limit` rows.
+pub struct GetProfile {
+    pub network: IpNetwork,
+    pub limit: usize,
+    pub bucket: TimeBucket,
+}
+
+/// Attempts in `[from, to)` counted per bucket, empty buckets included.
+pub struct Timeseries {
+    pub bucket: TimeBucket,
+    pub from: i64,
+    pub to: i64,
+    /// Only count attempts reported by this sensor.
+    pub sensor: Option<String>,
+}
+
+/// Most buckets one timeseries can cover.
+pub const MAX_TIMESERIES_BUCKETS: i64 = 10_000;
+
+/// Attempts waiting for a worker before ingest has to wait too.
+const ENRICHMENT_QUEUE_SIZE: usize = 10_000;
+
+/// A queued attempt that still isn't enriched after this long is queued
+/// again, so attempts queued when brute-http stopped aren't lost.
+const ENRICHMENT_LEASE: Duration = Duration::from_secs(600);
+
+/// Wait before retrying a failed enrichment, doubled after every failure.
+const ENRICHMENT_BACKOFF: Duration = Duration::from_secs(60);
+
+const ENRICHMENT_MAX_BACKOFF: Duration = Duration::from_secs(6 * 3600);
+
+/// Failed enrichments are given up on after this many tries.
+const ENRICHMENT_MAX_ATTEMPTS: i32 = 10;
+
+/// Most attempts queued again by one `RetryEnrichment`.
+const ENRICHMENT_RETRY_BATCH: i64 = 100;
+
+////////////////
+// IN FLIGHT //
+//////////////
+/// Counts reports that are still being written.
+#[derive(Default)]
+pub struct InFlight {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+impl InFlight {
+    fn start(self: &Arc<Self>) -> InFlightGuard {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(self.clone())
+    }
+
+    async fn wait_idle(&self) {
+        loop {
+            let notified = self.idle.notified();
+            tokio::pin!(notified);
+            // registered before checking so a wake up in between isn't lost.
+            notified.as_mut().enable();
+            if self.count.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+//////////////
+// SENSORS //
+////////////
+/// In-memory copy of the `sensor` table so ingest can authenticate a
+/// sensor without a database round trip.
+#[derive(Default)]
+pub struct SensorRegistry {
+    sensors: RwLock<HashMap<String, Sensor>>,
+}
+
+impl SensorRegistry {
+    pub fn replace(&self, sensors: Vec<Sensor>) {
+        *self.sensors.write().unwrap() = sensors
+            .into_iter()
+            .map(|sensor| (sensor.id().clone(), sensor))
+            .collect();
+    }
+
+    pub fn insert(&self, sensor: Sensor) {
+        self.sensors.write().unwrap().insert(sensor.id().clone(), sensor);
+    }
+
+    pub fn get(&self, id: &str) -> Option<Sensor> {
+        self.sensors.read().unwrap().get(id).cloned()
+    }
+
+    /// Id of the active sensor `token` was issued to.
+    pub fn authenticate(&self, token: &str) -> Option<String> {
+        let hash = hash_token(token);
+        self.sensors
+            .read()
+            .unwrap()
+            .values()
+            // every hash is compared, no early return on a match.
+            .filter(|sensor| constant_time_eq(sensor.token_hash(), &hash))
+            .last()
+            .filter(|sensor| !sensor.is_revoked())
+            .map(|sensor| sensor.id().clone())
+    }
+}
+
+/// Only the sha256 of a token is stored, a database leak doesn't leak
+/// working tokens.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn generate_token() -> String {
+    format!("{}{}", Uuid::new_v4().as_simple(), Uuid::new_v4().as_simple())
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Runs `query`, or `filtered_query` when the request is filtered. Both
+/// take the limit as `$1`, `filtered_query` also takes the sensor (or null)
+/// as `$2` and the range as `$3` and `$4`.
+async fn fetch_with_limit<T>(
+    db_pool: &Pool<Postgres>,
+    query: &str,
+    filtered_query: &str,
+    limit: usize,
+    filter: Option<StatsFilter>,
+) -> Result<Vec<T>, sqlx::Error>
+where
+    T: for<'r> FromRow<'r, PgRow> + Send + Unpin,
+{
+    match filter {
+        Some(filter) => {
+            sqlx::query_as::<_, T>(filtered_query)
+                .bind(limit as i64)
+                .bind(filter.sensor)
+                .bind(filter.from)
+                .bind(filter.to)
+                .fetch_all(db_pool)
+                .await
+        }
+        None => {
+            sqlx::query_as::<_, T>(query)
+                .bind(limit as i64)
+                .fetch_all(db_pool)
+                .await
+        }
+    }
+}
+
+/// Makes `date_trunc` and interval steps in the rest of the transaction use
+/// `timezone`, where the time buckets are aligned.
+async fn set_stats_timezone(conn: &mut PgConnection, timezone: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT set_config('TimeZone', $1, true);")
+        .bind(timezone)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Attempts in `[from, to)` per bucket, empty buckets included. Only
+/// attempts of `sensor` and from IPs in `network` when they're set. Needs
+/// `set_stats_timezone` first.
+async fn fetch_timeseries(
+    conn: &mut PgConnection,
+    bucket: TimeBucket,
+    from: i64,
+    to: i64,
+    sensor: Option<String>,
+    network: Option<String>,
+) -> Result<Vec<TimeseriesPoint>, sqlx::Error> {
+    // the first bucket can start before `from`, only attempts from then on
+    // are counted in it.
+    let query = r#"
+        SELECT (EXTRACT(EPOCH FROM bucket) * 1000)::BIGINT AS timestamp, COALESCE(counts.amount, 0) AS amount
+        FROM generate_series(
+            date_trunc($1, to_timestamp($2 / 1000.0)),
+            to_timestamp(($3 - 1) / 1000.0),
+            ('1 ' || $1)::INTERVAL
+        ) AS bucket
+        LEFT JOIN (
+            SELECT date_trunc($1, to_timestamp(timestamp / 1000.0)) AS bucket, COUNT(*) AS amount
+            FROM individual
+            WHERE timestamp >= $2 AND timestamp < $3
+                AND ($4::TEXT IS NULL OR sensor_id = $4)
+                AND ($5::CIDR IS NULL OR ip::INET <<= $5::CIDR)
+            GROUP BY 1
+        ) counts USING (bucket)
+        ORDER BY bucket;
+    "#;
+    sqlx::query_as::<_, TimeseriesPoint>(query)
+        .bind(bucket.unit())
+        .bind(from)
+        .bind(to)
+        .bind(sensor)
+        .bind(network)
+        .fetch_all(conn)
+        .await
+}
+
+/// Rows of the `table` time-bucket counter, or the same `unit` buckets
+/// counted from the filtered attempts. Most recent first.
+async fn fetch_buckets<T>(
+    db_pool: &Pool<Postgres>,
+    table: &str,
+    unit: &str,
+    timezone: &str,
+    limit: usize,
+    filter: Option<StatsFilter>,
+) -> Result<Vec<T>, sqlx::Error>
+where
+    T: for<'r> FromRow<'r, PgRow> + Send + Unpin,
+{
+    match filter {
+        Some(filter) => {
+            let query = format!(
+                r#"
+                SELECT (EXTRACT(EPOCH FROM date_trunc('{unit}', to_timestamp(timestamp / 1000.0), $5)) * 1000)::BIGINT AS timestamp,
+                    COUNT(*)::int AS amount
+                FROM individual
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4
+                GROUP BY 1 ORDER BY 1 DESC LIMIT $1;
+                "#
+            );
+            sqlx::query_as::<_, T>(&query)
+                .bind(limit as i64)
+                .bind(filter.sensor)
+                .bind(filter.from)
+                .bind(filter.to)
+                .bind(timezone)
+                .fetch_all(db_pool)
+                .await
+        }
+        None => {
+            let query = format!("SELECT * FROM {table} ORDER BY timestamp DESC LIMIT $1;");
+            sqlx::query_as::<_, T>(&query)
+                .bind(limit as i64)
+                .fetch_all(db_pool)
+                .await
+        }
+    }
 }
 
 //////////////////////
@@ -38,8 +416,29 @@ pub struct BruteSystem {
     /// PostgreSQL connection pool.
     pub db_pool: Pool<Postgres>,
 
-    /// IP info client with shared access.
-    pub ipinfo_client: Arc<Mutex<IpInfo>>,
+    /// Location and network details of the IPs seen so far.
+    pub enrichment: Arc<EnrichmentCache>,
+
+    /// Reports that have been accepted but not written yet.
+    pub in_flight: Arc<InFlight>,
+
+    /// Registered sensors, shared with the HTTP server.
+    pub sensors: Arc<SensorRegistry>,
+
+    /// Timezone the hourly, daily, weekly and yearly counters are aligned to.
+    pub stats_timezone: Arc<str>,
+
+    /// Whether attempts past a retention period are deleted.
+    pub prunes_attempts: bool,
+
+    /// Stored attempts waiting for their location and network details.
+    enrichment_queue: mpsc::Sender<PendingEnrichment>,
+}
+
+/// A stored attempt, the in-flight guard is held until it is enriched.
+struct PendingEnrichment {
+    individual: Individual,
+    _in_flight: InFlightGuard,
 }
 
 impl BruteSystem {
@@ -58,10 +457,61 @@ impl BruteSystem {
     /// // Create an instance of BruteSystem
     /// let brute_system = BruteSystem::new(brute_config); // as an actor you will append .start() at the end.s
     /// ```
-    pub async fn new_brute(pg_pool: Pool<Postgres>, ipinfo_client: IpInfo) -> Self {
-        Self {
+    pub async fn new_brute(
+        pg_pool: Pool<Postgres>,
+        enrichment: EnrichmentCache,
+        workers: usize,
+        stats_timezone: &str,
+        prunes_attempts: bool,
+    ) -> Self {
+        let (enrichment_queue, pending) = mpsc::channel(ENRICHMENT_QUEUE_SIZE);
+        let brute_system = Self {
             db_pool: pg_pool,
-            ipinfo_client: Arc::new(Mutex::new(ipinfo_client)),
+            enrichment: Arc::new(enrichment),
+            in_flight: Arc::new(InFlight::default()),
+            sensors: Arc::new(SensorRegistry::default()),
+            stats_timezone: Arc::from(stats_timezone),
+            prunes_attempts,
+            enrichment_queue,
+        };
+        let pending = Arc::new(Mutex::new(pending));
+        for _ in 0..workers {
+            actix_rt::spawn(brute_system.clone().enrichment_worker(pending.clone()));
+        }
+        brute_system
+    }
+
+    /// Enriches stored attempts one at a time, several workers share the
+    /// queue so a slow lookup doesn't hold up the others.
+    async fn enrichment_worker(self, pending: Arc<Mutex<mpsc::Receiver<PendingEnrichment>>>) {
+        let reporter = self.reporter();
+        loop {
+            let Some(job) = pending.lock().await.recv().await else {
+                return;
+            };
+            match reporter.finish_report(&job.individual).await {
+                Ok(result) => {
+                    info!(
+                        "Successfully processed Individual with ID: {}. Details: Username: '{}', IP: '{}', Protocol: '{}', Timestamp: {}, Location: {} - {}, {}, {}",
+                        result.id(),
+                        result.username(),
+                        result.ip(),
+                        result.protocol(),
+                        result.timestamp(),
+                        result.city().as_ref().unwrap_or(&"{EMPTY}".to_string()),
+                        result.region().as_ref().unwrap_or(&"{EMPTY}".to_string()),
+                        result.country().as_ref().unwrap_or(&"{EMPTY}".to_string()),
+                        result.postal().as_ref().unwrap_or(&"{EMPTY}".to_string())
+                    );
+                    websocket::BruteServer::broadcast(websocket::ParseType::ProcessedIndividual, result);
+                }
+                // the attempt is already stored, it's retried later.
+                Err(e) => match reporter.enrichment_failed(&job.individual).await {
+                    Ok(true) => error!("Failed to enrich attempt {}, giving up: {}", job.individual.id(), e),
+                    Ok(false) => warn!("Failed to enrich attempt {}, retrying later: {}", job.individual.id(), e),
+                    Err(update) => error!("Failed to enrich attempt {}: {} ({})", job.individual.id(), e, update),
+                },
+            }
         }
     }
 
@@ -82,26 +532,25 @@ impl Actor for BruteSystem {
 // INDIVIDUAL MESSAGE //
 ///////////////////////
 impl Handler<Individual> for BruteSystem {
-    type Result = ResponseActFuture<Self, Result<ProcessedIndividual, BruteResponeError>>;
+    type Result = ResponseActFuture<Self, Result<Individual, BruteResponeError>>;
 
     fn handle(&mut self, msg: Individual, _: &mut Self::Context) -> Self::Result {
         let reporter = self.reporter();
+        let enrichment_queue = self.enrichment_queue.clone();
+        let in_flight = self.in_flight.start();
         let fut = async move {
+            // the raw attempt is stored right away, its location and network
+            // details are filled in by an enrichment worker.
             match reporter.start_report(msg).await {
-                Ok(result) => {
-                    info!(
-                        "Successfully processed Individual with ID: {}. Details: Username: '{}', IP: '{}', Protocol: '{}', Timestamp: {}, Location: {} - {}, {}, {}",
-                        result.id(),
-                        result.username(),
-                        result.ip(),
-                        result.protocol(),
-                        result.timestamp(),
-                        result.city().as_ref().unwrap_or(&"{EMPTY}".to_string()),
-                        result.region().as_ref().unwrap_or(&"{EMPTY}".to_string()),
-                        result.country().as_ref().unwrap_or(&"{EMPTY}".to_string()),
-                        result.postal().as_ref().unwrap_or(&"{EMPTY}".to_string())
-                    );
-                    Ok(result)
+                Ok(individual) => {
+                    let job = PendingEnrichment {
+                        individual: individual.clone(),
+                        _in_flight: in_flight,
+                    };
+                    if enrichment_queue.send(job).await.is_err() {
+                        error!("Enrichment workers are gone, attempt {} won't be enriched.", individual.id());
+                    }
+                    Ok(individual)
                 }
                 Err(e) => {
                     error!("Failed to process report: {}", e);
@@ -114,6 +563,720 @@ impl Handler<Individual> for BruteSystem {
         fut.into_actor(self).map(|res, _, _| res).boxed_local()
     }
 }
+///////////////////////////
+// REPORT BATCH MESSAGE //
+/////////////////////////
+impl Handler<ReportBatch> for BruteSystem {
+    type Result = ResponseFuture<Result<Vec<Individual>, BruteResponeError>>;
+
+    fn handle(&mut self, msg: ReportBatch, _: &mut Self::Context) -> Self::Result {
+        let reporter = self.reporter();
+        let enrichment_queue = self.enrichment_queue.clone();
+        let in_flight: Vec<_> = msg.attempts.iter().map(|_| self.in_flight.start()).collect();
+        Box::pin(async move {
+            let stored = reporter.start_reports(msg.attempts).await.map_err(|e| {
+                error!("Failed to process batch: {}", e);
+                BruteResponeError::InternalError("something definitely broke on our side".to_string())
+            })?;
+            for (individual, in_flight) in stored.iter().zip(in_flight) {
+                let job = PendingEnrichment {
+                    individual: individual.clone(),
+                    _in_flight: in_flight,
+                };
+                if enrichment_queue.send(job).await.is_err() {
+                    error!("Enrichment workers are gone, attempt {} won't be enriched.", individual.id());
+                }
+            }
+            Ok(stored)
+        })
+    }
+}
+
+////////////////////
+// DRAIN MESSAGE //
+//////////////////
+impl Handler<Drain> for BruteSystem {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, _: Drain, _: &mut Self::Context) -> Self::Result {
+        let in_flight = self.in_flight.clone();
+        Box::pin(async move { in_flight.wait_idle().await })
+    }
+}
+
+////////////////////
+// PRUNE MESSAGE //
+//////////////////
+impl Handler<PruneAttempts> for BruteSystem {
+    type Result = ResponseFuture<Result<u64, BruteResponeError>>;
+
+    fn handle(&mut self, msg: PruneAttempts, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+
+        let fut = async move {
+            let internal = |_| BruteResponeError::InternalError("something definitely broke on our side".to_string());
+            let cutoff = now_millis() - msg.older_than.as_millis() as i64;
+            // both go or neither does, an attempt is never left half pruned.
+            let mut transaction = db_pool.begin().await.map_err(internal)?;
+            sqlx::query("DELETE FROM processed_individual WHERE timestamp < $1;")
+                .bind(cutoff)
+                .execute(&mut *transaction)
+                .await
+                .map_err(internal)?;
+            let deleted = sqlx::query("DELETE FROM individual WHERE timestamp < $1;")
+                .bind(cutoff)
+                .execute(&mut *transaction)
+                .await
+                .map_err(internal)?
+                .rows_affected();
+            transaction.commit().await.map_err(internal)?;
+            Ok(deleted)
+        };
+        Box::pin(fut)
+    }
+}
+
+////////////////////
+// RETRY MESSAGE //
+//////////////////
+impl Handler<RetryEnrichment> for BruteSystem {
+    type Result = ResponseFuture<Result<usize, BruteResponeError>>;
+
+    fn handle(&mut self, _: RetryEnrichment, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+        let enrichment_queue = self.enrichment_queue.clone();
+        let in_flight = self.in_flight.clone();
+
+        let fut = async move {
+            // claimed rows get a new lease so the next run skips them.
+            let query = r#"
+                UPDATE individual SET enrichment_retry_at = $1 + $2
+                WHERE id IN (
+                    SELECT id FROM individual
+                    WHERE enrichment_status = 'pending' AND enrichment_retry_at <= $1
+                    ORDER BY enrichment_retry_at
+                    LIMIT $3
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING *;
+            "#;
+            let due = sqlx::query_as::<_, Individual>(query)
+                .bind(now_millis())
+                .bind(ENRICHMENT_LEASE.as_millis() as i64)
+                .bind(ENRICHMENT_RETRY_BATCH)
+                .fetch_all(&db_pool)
+                .await
+                .map_err(|_| BruteResponeError::InternalError("something definitely broke on our side".to_string()))?;
+            let queued = due.len();
+            for individual in due {
+                let job = PendingEnrichment {
+                    individual,
+                    _in_flight: in_flight.start(),
+                };
+                if enrichment_queue.send(job).await.is_err() {
+                    return Err(BruteResponeError::InternalError("something definitely broke on our side".to_string()));
+                }
+            }
+            Ok(queued)
+        };
+        Box::pin(fut)
+    }
+}
+
+///////////////////
+// TIME BUCKETS //
+/////////////////
+/// Counters with a row per bucket, and the buckets they count.
+const TIME_BUCKETS: &[(&str, &str)] = &[
+    ("top_hourly", "hour"),
+    ("top_daily", "day"),
+    ("top_weekly", "week"),
+    ("top_yearly", "year"),
+];
+
+impl BruteSystem {
+    /// Moves every time bucket to the start of its bucket in
+    /// `stats_timezone` when they're aligned to another timezone, rows that
+    /// end up in the same bucket are merged. Buckets can't be split, so an
+    /// attempt can end up in the bucket before its own. Returns the timezone
+    /// they were aligned to, `None` when they already were.
+    pub async fn align_time_buckets(&self) -> anyhow::Result<Option<String>> {
+        let mut transaction = self.db_pool.begin().await?;
+        let aligned = sqlx::query_scalar::<_, String>("SELECT timezone FROM time_bucket_alignment FOR UPDATE;")
+            .fetch_one(&mut *transaction)
+            .await?;
+        if *aligned == *self.stats_timezone {
+            return Ok(None);
+        }
+
+        set_stats_timezone(&mut transaction, &self.stats_timezone).await?;
+        // in the order `start_report` updates them.
+        sqlx::query("LOCK TABLE top_hourly, top_daily, top_weekly, top_yearly IN EXCLUSIVE MODE;")
+            .execute(&mut *transaction)
+            .await?;
+        for (table, unit) in TIME_BUCKETS {
+            let query = format!(
+                r#"
+                WITH old AS (DELETE FROM {table} RETURNING timestamp, amount)
+                INSERT INTO {table} (timestamp, amount)
+                SELECT (EXTRACT(EPOCH FROM date_trunc('{unit}', to_timestamp(timestamp / 1000.0))) * 1000)::BIGINT, SUM(amount)::INTEGER
+                FROM old GROUP BY 1;
+                "#
+            );
+            sqlx::query(&query).execute(&mut *transaction).await?;
+        }
+        sqlx::query("UPDATE time_bucket_alignment SET timezone = $1;")
+            .bind(&*self.stats_timezone)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(Some(aligned))
+    }
+}
+
+////////////////
+// RE-ENRICH //
+//////////////
+/// IPs a re-enrichment job looks up at once, its progress is saved after
+/// every batch.
+const REENRICH_BATCH: i64 = 16;
+
+/// Advisory lock held by the running re-enrichment job, also keeps a job
+/// started from the command line and one started by the server apart.
+const REENRICH_LOCK: i64 = 0x6272_7574_6572;
+
+/// Every counter, locked in the order `start_report` and then
+/// `finish_report` update them so attempts reported meanwhile wait and are
+/// counted once.
+const LOCK_COUNTERS: &str = "LOCK TABLE top_username, top_password, top_ip, top_protocol, top_usr_pass_combo, \
+    top_hourly, top_daily, top_weekly, top_yearly, \
+    top_city, top_region, top_country, top_timezone, top_org, top_postal, top_loc IN EXCLUSIVE MODE;";
+
+/// Enriched attempts and the location counters they're counted in, which
+/// are locked in the order `finish_report` updates them.
+const LOCK_LOCATION_COUNTERS: &str =
+    "LOCK TABLE processed_individual, top_city, top_region, top_country, top_timezone, top_org, top_postal, top_loc IN EXCLUSIVE MODE;";
+
+/// Counters of every stored attempt, the column they count by.
+const ATTEMPT_COUNTERS: &[(&str, &str)] = &[
+    ("top_username", "username"),
+    ("top_password", "password"),
+    ("top_ip", "ip"),
+    ("top_protocol", "protocol"),
+];
+
+/// Counters of the enriched attempts, the columns they count by and which
+/// attempts they skip.
+const LOCATION_COUNTERS: &[(&str, &str, &str)] = &[
+    ("top_city", "city, country", "TRUE"),
+    ("top_region", "region, country", "TRUE"),
+    ("top_country", "country", "TRUE"),
+    ("top_timezone", "timezone", "TRUE"),
+    ("top_org", "org", "org IS NOT NULL"),
+    ("top_postal", "postal", "postal IS NOT NULL"),
+    ("top_loc", "loc", "TRUE"),
+];
+
+/// Adds `sign` times the enriched attempts of `ip` to the location counters
+/// of its current details, rows that drop to zero are removed.
+async fn adjust_location_counters(conn: &mut PgConnection, ip: IpAddr, sign: i64) -> Result<(), sqlx::Error> {
+    for (table, columns, filter) in LOCATION_COUNTERS {
+        let adjust = format!(
+            r#"
+            INSERT INTO {table} ({columns}, amount)
+            SELECT {columns}, $2 * COUNT(*) FROM processed_individual JOIN ip_enrichment USING (ip)
+            WHERE ip = $1 AND {filter} GROUP BY {columns}
+            ON CONFLICT ({columns}) DO UPDATE SET amount = {table}.amount + EXCLUDED.amount;
+            "#
+        );
+        sqlx::query(&adjust)
+            .bind(ip.to_string())
+            .bind(sign)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query(&format!("DELETE FROM {table} WHERE amount <= 0;"))
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Only one re-enrichment job runs at a time, the lock is released when
+/// this is dropped.
+pub struct ReenrichLock {
+    _transaction: sqlx::Transaction<'static, Postgres>,
+}
+
+impl BruteSystem {
+    /// Fails if a job is already running, here or in another process.
+    pub async fn lock_reenrich(&self) -> Result<ReenrichLock, BruteResponeError> {
+        let internal = |_| BruteResponeError::InternalError("something definitely broke on our side".to_string());
+        let mut transaction = self.db_pool.begin().await.map_err(internal)?;
+        let locked = sqlx::query_scalar::<_, bool>("SELECT pg_try_advisory_xact_lock($1);")
+            .bind(REENRICH_LOCK)
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(internal)?;
+        if !locked {
+            return Err(BruteResponeError::BadRequest("a re-enrichment job is already running.".to_string()));
+        }
+        Ok(ReenrichLock {
+            _transaction: transaction,
+        })
+    }
+
+    pub async fn create_reenrich(&self, from: i64, to: i64) -> Result<ReenrichJob, BruteResponeError> {
+        let query = r#"
+            INSERT INTO reenrich_job (id, range_from, range_to, status, total_ips, created_at, updated_at)
+            SELECT $1, $2, $3, 'enriching', COUNT(DISTINCT ip), $4, $4
+            FROM processed_individual
+            WHERE timestamp >= $2 AND timestamp < $3
+            RETURNING *;
+        "#;
+        sqlx::query_as::<_, ReenrichJob>(query)
+            .bind(Uuid::new_v4().as_simple().to_string())
+            .bind(from)
+            .bind(to)
+            .bind(now_millis())
+            .fetch_one(&self.db_pool)
+            .await
+            .map_err(|_| BruteResponeError::InternalError("something definitely broke on our side".to_string()))
+    }
+
+    pub async fn resume_reenrich(&self, id: &str) -> Result<ReenrichJob, BruteResponeError> {
+        // a job that failed while rebuilding skips past its IPs quickly,
+        // the cursor is already at the end.
+        let query = r#"
+            UPDATE reenrich_job SET status = 'enriching', error = NULL, finished_at = NULL, updated_at = $2
+            WHERE id = $1 AND status <> 'done'
+            RETURNING *;
+        "#;
+        sqlx::query_as::<_, ReenrichJob>(query)
+            .bind(id)
+            .bind(now_millis())
+            .fetch_optional(&self.db_pool)
+            .await
+            .map_err(|_| BruteResponeError::InternalError("something definitely broke on our side".to_string()))?
+            .ok_or_else(|| BruteResponeError::BadRequest(format!("job {} does not exist or is done.", id)))
+    }
+
+    /// Runs a job to the end, `progress` is called whenever it moves on.
+    pub async fn run_reenrich(
+        &self,
+        lock: ReenrichLock,
+        job: ReenrichJob,
+        progress: impl Fn(&ReenrichJob),
+    ) -> ReenrichJob {
+        let id = job.id().clone();
+        let result = self.reenrich(job, &progress).await;
+        drop(lock);
+        match result {
+            Ok(job) => job,
+            Err(e) => {
+                error!("Re-enrichment job {} failed: {}", id, e);
+                let query = r#"
+                    UPDATE reenrich_job SET status = 'failed', error = $2, updated_at = $3, finished_at = $3
+                    WHERE id = $1
+                    RETURNING *;
+                "#;
+                let failed = sqlx::query_as::<_, ReenrichJob>(query)
+                    .bind(&id)
+                    .bind(e.to_string())
+                    .bind(now_millis())
+                    .fetch_one(&self.db_pool)
+                    .await
+                    .unwrap_or_default();
+                progress(&failed);
+                failed
+            }
+        }
+    }
+
+    async fn reenrich(&self, mut job: ReenrichJob, progress: &impl Fn(&ReenrichJob)) -> anyhow::Result<ReenrichJob> {
+        let select_query = r#"
+            SELECT DISTINCT ip FROM processed_individual
+            WHERE timestamp >= $1 AND timestamp < $2 AND ip > $3
+            ORDER BY ip
+            LIMIT $4;
+        "#;
+        let progress_query = r#"
+            UPDATE reenrich_job SET
+                last_ip = $2,
+                enriched_ips = enriched_ips + $3,
+                failed_ips = failed_ips + $4,
+                updated_at = $5
+            WHERE id = $1
+            RETURNING *;
+        "#;
+        loop {
+            let ips = sqlx::query_scalar::<_, String>(select_query)
+                .bind(job.range_from())
+                .bind(job.range_to())
+                .bind(job.last_ip())
+                .bind(REENRICH_BATCH)
+                .fetch_all(&self.db_pool)
+                .await?;
+            let Some(last_ip) = ips.last().cloned() else {
+                break;
+            };
+
+            let mut lookups = JoinSet::new();
+            for ip in ips {
+                let enrichment = self.enrichment.clone();
+                lookups.spawn(async move {
+                    let result = match ip.parse::<IpAddr>() {
+                        Ok(parsed) => enrichment.ask(parsed).await.map(|enriched| (parsed, enriched)),
+                        Err(e) => Err(e.into()),
+                    };
+                    (ip, result)
+                });
+            }
+            let mut found = Vec::new();
+            let mut failed = 0;
+            while let Some(lookup) = lookups.join_next().await {
+                match lookup? {
+                    (_, Ok(details)) => found.push(details),
+                    (ip, Err(e)) => {
+                        warn!("Failed to re-enrich {}, keeping its details: {}", ip, e);
+                        failed += 1;
+                    }
+                }
+            }
+            let enriched = found.len() as i32;
+            self.store_reenriched(found).await?;
+
+            job = sqlx::query_as::<_, ReenrichJob>(progress_query)
+                .bind(job.id())
+                .bind(&last_ip)
+                .bind(enriched)
+                .bind(failed)
+                .bind(now_millis())
+                .fetch_one(&self.db_pool)
+                .await?;
+            progress(&job);
+        }
+
+        // pruned attempts can't be recounted, theirs were moved as the
+        // job went.
+        if self.prunes_attempts {
+            warn!("Re-enrichment job {} only moved the location counters, attempts are pruned.", job.id());
+        } else {
+            job = self.set_reenrich_status(job.id(), "rebuilding").await?;
+            progress(&job);
+            self.rebuild_counters().await?;
+        }
+
+        let query = r#"
+            UPDATE reenrich_job SET status = 'done', counters_rebuilt = $2, updated_at = $3, finished_at = $3
+            WHERE id = $1
+            RETURNING *;
+        "#;
+        job = sqlx::query_as::<_, ReenrichJob>(query)
+            .bind(job.id())
+            .bind(!self.prunes_attempts)
+            .bind(now_millis())
+            .fetch_one(&self.db_pool)
+            .await?;
+        progress(&job);
+        Ok(job)
+    }
+
+    /// Stores the new details. While attempts are pruned, the enriched
+    /// attempts of every IP are moved from the counters of its old details
+    /// to those of its new ones as well. Attempts counted under details an
+    /// expired lookup has replaced since are taken as counted under the
+    /// current ones.
+    async fn store_reenriched(&self, found: Vec<(IpAddr, Enriched)>) -> anyhow::Result<()> {
+        let mut transaction = self.db_pool.begin().await?;
+        if self.prunes_attempts {
+            sqlx::query(LOCK_LOCATION_COUNTERS).execute(&mut *transaction).await?;
+        }
+        for (ip, enriched) in found {
+            if self.prunes_attempts {
+                adjust_location_counters(&mut transaction, ip, -1).await?;
+            }
+            self.enrichment.store_in(&mut transaction, ip, enriched).await?;
+            if self.prunes_attempts {
+                adjust_location_counters(&mut transaction, ip, 1).await?;
+            }
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Recounts every counter from the stored attempts, the time buckets
+    /// in `stats_timezone`.
+    async fn rebuild_counters(&self) -> anyhow::Result<()> {
+        let mut transaction = self.db_pool.begin().await?;
+        // taken before the buckets, like `align_time_buckets` does.
+        sqlx::query("UPDATE time_bucket_alignment SET timezone = $1;")
+            .bind(&*self.stats_timezone)
+            .execute(&mut *transaction)
+            .await?;
+        set_stats_timezone(&mut transaction, &self.stats_timezone).await?;
+        sqlx::query(LOCK_COUNTERS).execute(&mut *transaction).await?;
+
+        let mut rebuild = Vec::new();
+        for (table, column) in ATTEMPT_COUNTERS {
+            rebuild.push(format!("DELETE FROM {table};"));
+            rebuild.push(format!(
+                "INSERT INTO {table} ({column}, amount) SELECT {column}, COUNT(*) FROM individual GROUP BY {column};"
+            ));
+        }
+        // combinations that are still counted keep their id, new ones get
+        // a random one like `TopUsrPassCombo::report` gives them.
+        rebuild.push(
+            "DELETE FROM top_usr_pass_combo combo WHERE NOT EXISTS (SELECT 1 FROM individual WHERE username = combo.username AND password = combo.password);"
+                .to_string(),
+        );
+        rebuild.push(
+            r#"
+            INSERT INTO top_usr_pass_combo (id, username, password, amount)
+            SELECT replace(gen_random_uuid()::TEXT, '-', ''), username, password, COUNT(*) FROM individual GROUP BY username, password
+            ON CONFLICT (username, password) DO UPDATE SET amount = EXCLUDED.amount;
+            "#
+            .to_string(),
+        );
+        for (table, unit) in TIME_BUCKETS {
+            rebuild.push(format!("DELETE FROM {table};"));
+            rebuild.push(format!(
+                r#"
+                INSERT INTO {table} (timestamp, amount)
+                SELECT (EXTRACT(EPOCH FROM date_trunc('{unit}', to_timestamp(timestamp / 1000.0))) * 1000)::BIGINT, COUNT(*)
+                FROM individual GROUP BY 1;
+                "#
+            ));
+        }
+        for (table, columns, filter) in LOCATION_COUNTERS {
+            rebuild.push(format!("DELETE FROM {table};"));
+            rebuild.push(format!(
+                "INSERT INTO {table} ({columns}, amount) SELECT {columns}, COUNT(*) FROM processed_individual JOIN ip_enrichment USING (ip) WHERE {filter} GROUP BY {columns};"
+            ));
+        }
+        for query in rebuild {
+            sqlx::query(&query).execute(&mut *transaction).await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn set_reenrich_status(&self, id: &str, status: &str) -> anyhow::Result<ReenrichJob> {
+        let query = r#"
+            UPDATE reenrich_job SET status = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING *;
+        "#;
+        let job = sqlx::query_as::<_, ReenrichJob>(query)
+            .bind(id)
+            .bind(status)
+            .bind(now_millis())
+            .fetch_one(&self.db_pool)
+            .await?;
+        Ok(job)
+    }
+
+    /// Runs `job` in the background.
+    fn spawn_reenrich(&self, lock: ReenrichLock, job: ReenrichJob) {
+        let brute_system = self.clone();
+        actix_rt::spawn(async move {
+            brute_system.run_reenrich(lock, job, log_reenrich_progress).await;
+        });
+    }
+}
+
+fn log_reenrich_progress(job: &ReenrichJob) {
+    info!(
+        "Re-enrichment job {}: {}, {}/{} IP(s) looked up, {} failed.",
+        job.id(),
+        job.status(),
+        job.enriched_ips() + job.failed_ips(),
+        job.total_ips(),
+        job.failed_ips()
+    );
+}
+
+impl Handler<StartReenrich> for BruteSystem {
+    type Result = ResponseFuture<Result<ReenrichJob, BruteResponeError>>;
+
+    fn handle(&mut self, msg: StartReenrich, _: &mut Self::Context) -> Self::Result {
+        let brute_system = self.clone();
+
+        let fut = async move {
+            let lock = brute_system.lock_reenrich().await?;
+            let job = brute_system.create_reenrich(msg.from, msg.to).await?;
+            info!("Started re-enrichment job {} for {} IP(s).", job.id(), job.total_ips());
+            brute_system.spawn_reenrich(lock, job.clone());
+            Ok(job)
+        };
+        Box::pin(fut)
+    }
+}
+
+impl Handler<ListReenrichJobs> for BruteSystem {
+    type Result = ResponseFuture<Result<Vec<ReenrichJob>, BruteResponeError>>;
+
+    fn handle(&mut self, _: ListReenrichJobs, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+
+        let fut = async move {
+            let query = "SELECT * FROM reenrich_job ORDER BY created_at DESC;";
+            sqlx::query_as::<_, ReenrichJob>(query)
+                .fetch_all(&db_pool)
+                .await
+                .map_err(|_| BruteResponeError::InternalError("something definitely broke on our side".to_string()))
+        };
+        Box::pin(fut)
+    }
+}
+
+impl Handler<GetReenrichJob> for BruteSystem {
+    type Result = ResponseFuture<Result<ReenrichJob, BruteResponeError>>;
+
+    fn handle(&mut self, msg: GetReenrichJob, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+
+        let fut = async move {
+            let query = "SELECT * FROM reenrich_job WHERE id = $1;";
+            sqlx::query_as::<_, ReenrichJob>(query)
+                .bind(&msg.id)
+                .fetch_optional(&db_pool)
+                .await
+                .map_err(|_| BruteResponeError::InternalError("something definitely broke on our side".to_string()))?
+                .ok_or_else(|| BruteResponeError::BadRequest(format!("job {} does not exist.", msg.id)))
+        };
+        Box::pin(fut)
+    }
+}
+
+impl Handler<ResumeReenrich> for BruteSystem {
+    type Result = ResponseFuture<Result<ReenrichJob, BruteResponeError>>;
+
+    fn handle(&mut self, msg: ResumeReenrich, _: &mut Self::Context) -> Self::Result {
+        let brute_system = self.clone();
+
+        let fut = async move {
+            let lock = brute_system.lock_reenrich().await?;
+            let job = brute_system.resume_reenrich(&msg.id).await?;
+            info!("Resumed re-enrichment job {}.", job.id());
+            brute_system.spawn_reenrich(lock, job.clone());
+            Ok(job)
+        };
+        Box::pin(fut)
+    }
+}
+
+//////////////////////
+// SENSOR MESSAGES //
+////////////////////
+impl Handler<CreateSensor> for BruteSystem {
+    type Result = ResponseFuture<Result<SensorToken, BruteResponeError>>;
+
+    fn handle(&mut self, msg: CreateSensor, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+        let sensors = self.sensors.clone();
+
+        let fut = async move {
+            let token = generate_token();
+            let query = r#"
+                INSERT INTO sensor (id, name, token_hash, created_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (id) DO NOTHING
+                RETURNING *;
+            "#;
+            let sensor = sqlx::query_as::<_, Sensor>(query)
+                .bind(&msg.id)
+                .bind(&msg.name)
+                .bind(hash_token(&token))
+                .bind(now_millis())
+                .fetch_optional(&db_pool)
+                .await
+                .map_err(|_| BruteResponeError::InternalError("something definitely broke on our side".to_string()))?
+                .ok_or_else(|| BruteResponeError::BadRequest(format!("sensor {} already exists.", msg.id)))?;
+            sensors.insert(sensor.clone());
+            info!("Registered sensor {} ({}).", sensor.id(), sensor.name());
+            Ok(SensorToken { sensor, token, signing_key: None })
+        };
+        Box::pin(fut)
+    }
+}
+
+impl Handler<ListSensors> for BruteSystem {
+    type Result = ResponseFuture<Result<Vec<Sensor>, BruteResponeError>>;
+
+    fn handle(&mut self, _: ListSensors, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+
+        let fut = async move {
+            let query = "SELECT * FROM sensor ORDER BY created_at;";
+            sqlx::query_as::<_, Sensor>(query)
+                .fetch_all(&db_pool)
+                .await
+                .map_err(|_| BruteResponeError::InternalError("something definitely broke on our side".to_string()))
+        };
+        Box::pin(fut)
+    }
+}
+
+impl Handler<RotateSensor> for BruteSystem {
+    type Result = ResponseFuture<Result<SensorToken, BruteResponeError>>;
+
+    fn handle(&mut self, msg: RotateSensor, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+        let sensors = self.sensors.clone();
+
+        let fut = async move {
+            let token = generate_token();
+            let query = r#"
+                UPDATE sensor
+                SET token_hash = $2, rotated_at = $3
+                WHERE id = $1 AND revoked_at IS NULL
+                RETURNING *;
+            "#;
+            let sensor = sqlx::query_as::<_, Sensor>(query)
+                .bind(&msg.id)
+                .bind(hash_token(&token))
+                .bind(now_millis())
+                .fetch_optional(&db_pool)
+                .await
+                .map_err(|_| BruteResponeError::InternalError("something definitely broke on our side".to_string()))?
+                .ok_or_else(|| BruteResponeError::BadRequest(format!("sensor {} does not exist or is revoked.", msg.id)))?;
+            sensors.insert(sensor.clone());
+            info!("Rotated the token of sensor {}.", sensor.id());
+            Ok(SensorToken { sensor, token, signing_key: None })
+        };
+        Box::pin(fut)
+    }
+}
+
+impl Handler<RevokeSensor> for BruteSystem {
+    type Result = ResponseFuture<Result<Sensor, BruteResponeError>>;
+
+    fn handle(&mut self, msg: RevokeSensor, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+        let sensors = self.sensors.clone();
+
+        let fut = async move {
+            let query = r#"
+                UPDATE sensor
+                SET revoked_at = COALESCE(revoked_at, $2)
+                WHERE id = $1
+                RETURNING *;
+            "#;
+            let sensor = sqlx::query_as::<_, Sensor>(query)
+                .bind(&msg.id)
+                .bind(now_millis())
+                .fetch_optional(&db_pool)
+                .await
+                .map_err(|_| BruteResponeError::InternalError("something definitely broke on our side".to_string()))?
+                .ok_or_else(|| BruteResponeError::BadRequest(format!("sensor {} does not exist.", msg.id)))?;
+            sensors.insert(sensor.clone());
+            info!("Revoked sensor {}.", sensor.id());
+            Ok(sensor)
+        };
+        Box::pin(fut)
+    }
+}
+
 /*
 impl Handler<Individual> for BruteSystem {
     type Result = ();
@@ -144,28 +1307,67 @@ impl Handler<Individual> for BruteSystem {
 }
 */
 
-//////////////////////////////////
-// PROCESSEDINDIVIDUAL MESSAGE //
-////////////////////////////////
-impl Handler<RequestWithLimit<ProcessedIndividual>> for BruteSystem {
-    type Result = ResponseFuture<Result<Vec<ProcessedIndividual>, BruteResponeError>>;
+/////////////////////////////
+// SEARCH ATTACKS MESSAGE //
+///////////////////////////
+impl Handler<SearchAttacks> for BruteSystem {
+    type Result = ResponseFuture<Result<AttackPage, BruteResponeError>>;
 
-    fn handle(
-        &mut self,
-        msg: RequestWithLimit<ProcessedIndividual>,
-        _: &mut Self::Context,
-    ) -> Self::Result {
+    fn handle(&mut self, msg: SearchAttacks, _: &mut Self::Context) -> Self::Result {
         let db_pool = self.db_pool.clone();
-        let limit = msg.limit;
 
         let fut = async move {
-            let query = "SELECT * FROM processed_individual ORDER BY timestamp DESC LIMIT $1";
-            let rows = sqlx::query_as::<_, ProcessedIndividual>(query)
-                .bind(limit as i64)
+            // ordered by (timestamp, id) so pages don't skip or repeat
+            // attempts that share a timestamp. attempts that aren't
+            // enriched yet are listed too, they only miss the details.
+            let query = r#"
+                SELECT * FROM individual LEFT JOIN ip_enrichment USING (ip)
+                WHERE ($2::TEXT IS NULL OR ip = $2)
+                    AND ($3::CIDR IS NULL OR ip::INET <<= $3::CIDR)
+                    AND ($4::TEXT IS NULL OR username = $4)
+                    AND ($5::TEXT IS NULL OR password = $5)
+                    AND ($6::TEXT IS NULL OR protocol = $6)
+                    AND ($7::TEXT IS NULL OR country = $7)
+                    AND ($8::TEXT IS NULL OR asn = $8)
+                    AND ($9::TEXT IS NULL OR sensor_id = $9)
+                    AND timestamp >= $10 AND timestamp < $11
+                    AND (timestamp, id) < ($12, $13)
+                ORDER BY timestamp DESC, id DESC
+                LIMIT $1;
+            "#;
+            let after = msg.after.unwrap_or(AttackCursor {
+                timestamp: i64::MAX,
+                id: String::default(),
+            });
+            // one more than asked for tells whether there's another page.
+            let rows = sqlx::query_as::<_, Attack>(query)
+                .bind(msg.limit as i64 + 1)
+                .bind(msg.ip.map(|ip| ip.to_string()))
+                .bind(msg.cidr.map(|cidr| format!("{}/{}", cidr.network(), cidr.prefix())))
+                .bind(msg.username)
+                .bind(msg.password)
+                .bind(msg.protocol)
+                .bind(msg.country)
+                .bind(msg.asn)
+                .bind(msg.sensor)
+                .bind(msg.from.unwrap_or(0))
+                .bind(msg.to.unwrap_or(i64::MAX))
+                .bind(after.timestamp)
+                .bind(after.id)
                 .fetch_all(&db_pool)
                 .await;
             match rows {
-                Ok(rows) => Ok(rows),
+                Ok(mut attacks) => {
+                    let mut next_cursor = None;
+                    if attacks.len() > msg.limit {
+                        attacks.truncate(msg.limit);
+                        next_cursor = attacks.last().map(|last| AttackCursor {
+                            timestamp: *last.timestamp(),
+                            id: last.id().clone(),
+                        });
+                    }
+                    Ok(AttackPage { attacks, next_cursor })
+                }
                 Err(_) => Err(BruteResponeError::InternalError(
                     "something definitely broke on our side".to_string(),
                 )),
@@ -188,13 +1390,16 @@ impl Handler<RequestWithLimit<TopUsername>> for BruteSystem {
     ) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_username ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopUsername>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT username, COUNT(*)::int AS amount FROM individual
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4
+                GROUP BY username ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopUsername>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(
@@ -219,13 +1424,16 @@ impl Handler<RequestWithLimit<TopPassword>> for BruteSystem {
     ) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_password WHERE password !~ '^X{2,}$' ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopPassword>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT password, COUNT(*)::int AS amount FROM individual
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4 AND password !~ '^X{2,}$'
+                GROUP BY password ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopPassword>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(
@@ -246,13 +1454,16 @@ impl Handler<RequestWithLimit<TopIp>> for BruteSystem {
     fn handle(&mut self, msg: RequestWithLimit<TopIp>, _: &mut Self::Context) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_ip ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopIp>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT ip, COUNT(*)::int AS amount FROM individual
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4
+                GROUP BY ip ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopIp>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(
@@ -277,13 +1488,18 @@ impl Handler<RequestWithLimit<TopUsrPassCombo>> for BruteSystem {
     ) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_usr_pass_combo WHERE password !~ '^X{2,}$' ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopUsrPassCombo>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            // every counted combination has a row, windows reuse its id.
+            let filtered_query = r#"
+                SELECT combo.id, username, password, COUNT(*)::int AS amount
+                FROM individual JOIN top_usr_pass_combo combo USING (username, password)
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4 AND password !~ '^X{2,}$'
+                GROUP BY combo.id, username, password ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopUsrPassCombo>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(
@@ -308,13 +1524,16 @@ impl Handler<RequestWithLimit<TopProtocol>> for BruteSystem {
     ) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_protocol ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopProtocol>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT protocol, COUNT(*)::int AS amount FROM individual
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4
+                GROUP BY protocol ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopProtocol>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(
@@ -369,13 +1588,16 @@ impl Handler<RequestWithLimit<TopCountry>> for BruteSystem {
     fn handle(&mut self, msg: RequestWithLimit<TopCountry>, _: &mut Self::Context) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_country ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopCountry>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT country, COUNT(*)::int AS amount FROM processed_individual JOIN ip_enrichment USING (ip)
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4 AND country IS NOT NULL
+                GROUP BY country ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopCountry>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(
@@ -396,13 +1618,16 @@ impl Handler<RequestWithLimit<TopCity>> for BruteSystem {
     fn handle(&mut self, msg: RequestWithLimit<TopCity>, _: &mut Self::Context) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_city ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopCity>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT city, country, COUNT(*)::int AS amount FROM processed_individual JOIN ip_enrichment USING (ip)
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4 AND city IS NOT NULL AND country IS NOT NULL
+                GROUP BY city, country ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopCity>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(format!(
@@ -424,13 +1649,16 @@ impl Handler<RequestWithLimit<TopRegion>> for BruteSystem {
     fn handle(&mut self, msg: RequestWithLimit<TopRegion>, _: &mut Self::Context) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_region ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopRegion>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT region, country, COUNT(*)::int AS amount FROM processed_individual JOIN ip_enrichment USING (ip)
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4 AND region IS NOT NULL AND country IS NOT NULL
+                GROUP BY region, country ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopRegion>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(format!(
@@ -456,13 +1684,16 @@ impl Handler<RequestWithLimit<TopTimezone>> for BruteSystem {
     ) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_timezone ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopTimezone>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT timezone, COUNT(*)::int AS amount FROM processed_individual JOIN ip_enrichment USING (ip)
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4 AND timezone IS NOT NULL
+                GROUP BY timezone ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopTimezone>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(format!(
@@ -484,13 +1715,16 @@ impl Handler<RequestWithLimit<TopOrg>> for BruteSystem {
     fn handle(&mut self, msg: RequestWithLimit<TopOrg>, _: &mut Self::Context) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_org ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopOrg>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT org, COUNT(*)::int AS amount FROM processed_individual JOIN ip_enrichment USING (ip)
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4 AND org IS NOT NULL
+                GROUP BY org ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopOrg>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(format!(
@@ -512,14 +1746,17 @@ impl Handler<RequestWithLimit<TopPostal>> for BruteSystem {
     fn handle(&mut self, msg: RequestWithLimit<TopPostal>, _: &mut Self::Context) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query =
                 "SELECT * FROM top_postal WHERE postal !~ '^\\s*$' ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopPostal>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT postal, COUNT(*)::int AS amount FROM processed_individual JOIN ip_enrichment USING (ip)
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4 AND postal !~ '^\s*$'
+                GROUP BY postal ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopPostal>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(format!(
@@ -545,13 +1782,16 @@ impl Handler<RequestWithLimit<TopLocation>> for BruteSystem {
     ) -> Self::Result {
         let db_pool = self.db_pool.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
             let query = "SELECT * FROM top_loc ORDER BY amount DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopLocation>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let filtered_query = r#"
+                SELECT loc, COUNT(*)::int AS amount FROM processed_individual JOIN ip_enrichment USING (ip)
+                WHERE ($2::TEXT IS NULL OR sensor_id = $2) AND timestamp >= $3 AND timestamp < $4 AND loc IS NOT NULL
+                GROUP BY loc ORDER BY amount DESC LIMIT $1;
+            "#;
+            let rows = fetch_with_limit::<TopLocation>(&db_pool, query, filtered_query, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(
@@ -571,14 +1811,78 @@ impl Handler<RequestWithLimit<TopHourly>> for BruteSystem {
 
     fn handle(&mut self, msg: RequestWithLimit<TopHourly>, _: &mut Self::Context) -> Self::Result {
         let db_pool = self.db_pool.clone();
+        let timezone = self.stats_timezone.clone();
         let limit = msg.limit;
+        let filter = msg.filter();
 
         let fut = async move {
-            let query = "SELECT * FROM top_hourly ORDER BY timestamp DESC LIMIT $1;";
-            let rows = sqlx::query_as::<_, TopHourly>(query)
-                .bind(limit as i64)
-                .fetch_all(&db_pool)
-                .await;
+            let rows = fetch_buckets::<TopHourly>(&db_pool, "top_hourly", "hour", &timezone, limit, filter).await;
+            match rows {
+                Ok(rows) => Ok(rows),
+                Err(_) => Err(BruteResponeError::InternalError(
+                    "something definitely broke on our side".to_string(),
+                )),
+            }
+        };
+        Box::pin(fut)
+    }
+}
+
+impl Handler<RequestWithLimit<TopDaily>> for BruteSystem {
+    type Result = ResponseFuture<Result<Vec<TopDaily>, BruteResponeError>>;
+
+    fn handle(&mut self, msg: RequestWithLimit<TopDaily>, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+        let timezone = self.stats_timezone.clone();
+        let limit = msg.limit;
+        let filter = msg.filter();
+
+        let fut = async move {
+            let rows = fetch_buckets::<TopDaily>(&db_pool, "top_daily", "day", &timezone, limit, filter).await;
+            match rows {
+                Ok(rows) => Ok(rows),
+                Err(_) => Err(BruteResponeError::InternalError(
+                    "something definitely broke on our side".to_string(),
+                )),
+            }
+        };
+        Box::pin(fut)
+    }
+}
+
+impl Handler<RequestWithLimit<TopWeekly>> for BruteSystem {
+    type Result = ResponseFuture<Result<Vec<TopWeekly>, BruteResponeError>>;
+
+    fn handle(&mut self, msg: RequestWithLimit<TopWeekly>, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+        let timezone = self.stats_timezone.clone();
+        let limit = msg.limit;
+        let filter = msg.filter();
+
+        let fut = async move {
+            let rows = fetch_buckets::<TopWeekly>(&db_pool, "top_weekly", "week", &timezone, limit, filter).await;
+            match rows {
+                Ok(rows) => Ok(rows),
+                Err(_) => Err(BruteResponeError::InternalError(
+                    "something definitely broke on our side".to_string(),
+                )),
+            }
+        };
+        Box::pin(fut)
+    }
+}
+
+impl Handler<RequestWithLimit<TopYearly>> for BruteSystem {
+    type Result = ResponseFuture<Result<Vec<TopYearly>, BruteResponeError>>;
+
+    fn handle(&mut self, msg: RequestWithLimit<TopYearly>, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+        let timezone = self.stats_timezone.clone();
+        let limit = msg.limit;
+        let filter = msg.filter();
+
+        let fut = async move {
+            let rows = fetch_buckets::<TopYearly>(&db_pool, "top_yearly", "year", &timezone, limit, filter).await;
             match rows {
                 Ok(rows) => Ok(rows),
                 Err(_) => Err(BruteResponeError::InternalError(
@@ -590,30 +1894,198 @@ impl Handler<RequestWithLimit<TopHourly>> for BruteSystem {
     }
 }
 
+impl Handler<Timeseries> for BruteSystem {
+    type Result = ResponseFuture<Result<Vec<TimeseriesPoint>, BruteResponeError>>;
+
+    fn handle(&mut self, msg: Timeseries, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+        let timezone = self.stats_timezone.clone();
+
+        let fut = async move {
+            let points = async {
+                // buckets and the steps between them follow the stats
+                // timezone, DST included.
+                let mut transaction = db_pool.begin().await?;
+                set_stats_timezone(&mut transaction, &timezone).await?;
+                fetch_timeseries(&mut transaction, msg.bucket, msg.from, msg.to, msg.sensor, None).await
+            };
+            match points.await {
+                Ok(points) => Ok(points),
+                Err(_) => Err(BruteResponeError::InternalError(
+                    "something definitely broke on our side".to_string(),
+                )),
+            }
+        };
+        Box::pin(fut)
+    }
+}
+
+//////////////////////
+// PROFILE MESSAGE //
+////////////////////
+impl Handler<GetProfile> for BruteSystem {
+    type Result = ResponseFuture<Result<Profile, BruteResponeError>>;
+
+    fn handle(&mut self, msg: GetProfile, _: &mut Self::Context) -> Self::Result {
+        let db_pool = self.db_pool.clone();
+        let timezone = self.stats_timezone.clone();
+
+        let fut = async move {
+            let network = format!("{}/{}", msg.network.network(), msg.network.prefix());
+            let single_ip = msg.network.prefix() == if msg.network.is_ipv4() { 32 } else { 128 };
+            let profile = async {
+                let mut transaction = db_pool.begin().await?;
+                set_stats_timezone(&mut transaction, &timezone).await?;
+
+                let total_attempts_all_time = sqlx::query_scalar::<_, i64>(
+                    "SELECT COALESCE(SUM(amount), 0)::BIGINT FROM top_ip WHERE ip::INET <<= $1::CIDR;",
+                )
+                .bind(&network)
+                .fetch_one(&mut *transaction)
+                .await?;
+                let (total_attempts, first_seen, last_seen) = sqlx::query_as::<_, (i64, Option<i64>, Option<i64>)>(
+                    "SELECT COUNT(*), MIN(timestamp), MAX(timestamp) FROM individual WHERE ip::INET <<= $1::CIDR;",
+                )
+                .bind(&network)
+                .fetch_one(&mut *transaction)
+                .await?;
+                if total_attempts_all_time == 0 && total_attempts == 0 {
+                    return Ok(None);
+                }
+
+                let protocols = sqlx::query_as::<_, TopProtocol>(
+                    r#"
+                    SELECT protocol, COUNT(*)::int AS amount FROM individual
+                    WHERE ip::INET <<= $1::CIDR
+                    GROUP BY protocol ORDER BY amount DESC LIMIT $2;
+                    "#,
+                )
+                .bind(&network)
+                .bind(msg.limit as i64)
+                .fetch_all(&mut *transaction)
+                .await?;
+                let usernames = sqlx::query_as::<_, TopUsername>(
+                    r#"
+                    SELECT username, COUNT(*)::int AS amount FROM individual
+                    WHERE ip::INET <<= $1::CIDR
+                    GROUP BY username ORDER BY amount DESC LIMIT $2;
+                    "#,
+                )
+                .bind(&network)
+                .bind(msg.limit as i64)
+                .fetch_all(&mut *transaction)
+                .await?;
+                let passwords = sqlx::query_as::<_, TopPassword>(
+                    r#"
+                    SELECT password, COUNT(*)::int AS amount FROM individual
+                    WHERE ip::INET <<= $1::CIDR AND password !~ '^X{2,}$'
+                    GROUP BY password ORDER BY amount DESC LIMIT $2;
+                    "#,
+                )
+                .bind(&network)
+                .bind(msg.limit as i64)
+                .fetch_all(&mut *transaction)
+                .await?;
+                let sensors = sqlx::query_as::<_, TopSensor>(
+                    r#"
+                    SELECT sensor_id, COUNT(*)::int AS amount FROM individual
+                    WHERE ip::INET <<= $1::CIDR AND sensor_id IS NOT NULL
+                    GROUP BY sensor_id ORDER BY amount DESC LIMIT $2;
+                    "#,
+                )
+                .bind(&network)
+                .bind(msg.limit as i64)
+                .fetch_all(&mut *transaction)
+                .await?;
+
+                let activity = match (first_seen, last_seen) {
+                    (Some(first_seen), Some(last_seen)) => {
+                        // long histories only get their most recent buckets.
+                        let to = last_seen + 1;
+                        let from = first_seen.max(to - (MAX_TIMESERIES_BUCKETS - 1) * msg.bucket.min_millis());
+                        fetch_timeseries(&mut transaction, msg.bucket, from, to, None, Some(network.clone())).await?
+                    }
+                    _ => Vec::new(),
+                };
+
+                let (enrichment, ips) = if single_ip {
+                    let enrichment = sqlx::query_as::<_, Enrichment>("SELECT * FROM ip_enrichment WHERE ip = $1;")
+                        .bind(msg.network.ip().to_string())
+                        .fetch_optional(&mut *transaction)
+                        .await?;
+                    (enrichment, None)
+                } else {
+                    let ips = sqlx::query_as::<_, TopIp>(
+                        r#"
+                        SELECT ip, COUNT(*)::int AS amount FROM individual
+                        WHERE ip::INET <<= $1::CIDR
+                        GROUP BY ip ORDER BY amount DESC LIMIT $2;
+                        "#,
+                    )
+                    .bind(&network)
+                    .bind(msg.limit as i64)
+                    .fetch_all(&mut *transaction)
+                    .await?;
+                    (None, Some(ips))
+                };
+
+                Ok::<_, sqlx::Error>(Some(Profile {
+                    network: network.clone(),
+                    total_attempts,
+                    total_attempts_all_time,
+                    first_seen,
+                    last_seen,
+                    protocols,
+                    usernames,
+                    passwords,
+                    sensors,
+                    activity,
+                    enrichment,
+                    ips,
+                }))
+            };
+            match profile.await {
+                Ok(Some(profile)) => Ok(profile),
+                Ok(None) => Err(BruteResponeError::NotFound(format!("{} hasn't been seen.", network))),
+                Err(_) => Err(BruteResponeError::InternalError(
+                    "something definitely broke on our side".to_string(),
+                )),
+            }
+        };
+        Box::pin(fut)
+    }
+}
+
 ///////////////
 // REPORTER //
 /////////////
 
 pub mod reporter {
-    use super::{Brute, BruteSystem};
+    use super::{
+        set_stats_timezone, Brute, BruteSystem, ENRICHMENT_BACKOFF, ENRICHMENT_LEASE, ENRICHMENT_MAX_ATTEMPTS,
+        ENRICHMENT_MAX_BACKOFF,
+    };
     use crate::model::{
         Individual, ProcessedIndividual, TopCity, TopCountry, TopDaily, TopHourly, TopIp,
         TopLocation, TopOrg, TopPassword, TopPostal, TopProtocol, TopRegion, TopTimezone,
         TopUsername, TopUsrPassCombo, TopWeekly, TopYearly,
     };
-    use ipinfo::{AbuseDetails, AsnDetails, CompanyDetails, DomainsDetails, PrivacyDetails};
     use log::info;
-    use std::time::{SystemTime, UNIX_EPOCH};
+    use sqlx::PgConnection;
+    use std::{
+        net::IpAddr,
+        time::{SystemTime, UNIX_EPOCH},
+    };
     use tokio::time::Instant;
     use uuid::Uuid;
 
     pub trait Reporter {}
 
-    // todo take Pool<Postgres> instead of the entire struct
-    // so only the pool is getting cloned and not the entire struct.
+    /// Stores `model` through `conn`, which is the reporter's transaction so
+    /// every step of a report is committed or rolled back together.
     #[allow(async_fn_in_trait)]
     pub trait Reportable<T: Reporter, R> {
-        async fn report<'a>(reporter: &T, model: &'a R) -> anyhow::Result<Self>
+        async fn report(conn: &mut PgConnection, model: &R) -> anyhow::Result<Self>
         where
             Self: Sized;
     }
@@ -629,48 +2101,123 @@ pub mod reporter {
         }
 
         // could be refractored heavily find a way to not clone the entire struct.
+        /// Stores the raw attempt and the counters that don't need its
+        /// location, the rest is left to `finish_report`. Nothing is stored
+        /// if any step fails.
         pub async fn start_report(
             &self,
             payload: Individual,
+        ) -> anyhow::Result<Individual> {
+            let mut stored = self.start_reports(vec![payload]).await?;
+            Ok(stored.remove(0))
+        }
+
+        /// `start_report` for several attempts in one transaction, nothing
+        /// is stored if any of them fails.
+        pub async fn start_reports(
+            &self,
+            payloads: Vec<Individual>,
+        ) -> anyhow::Result<Vec<Individual>> {
+            let start = Instant::now();
+            // dropping the transaction on an error rolls it back.
+            let mut transaction = self.brute.db_pool.begin().await?;
+            // the time buckets are truncated in this time zone.
+            set_stats_timezone(&mut transaction, &self.brute.stats_timezone).await?;
+            let mut stored = Vec::with_capacity(payloads.len());
+            for payload in payloads {
+                // Report individual
+                let individual = Individual::report(&mut transaction, &payload).await?;
+
+                // Report top statistics
+                TopUsername::report(&mut transaction, &individual).await?;
+                TopPassword::report(&mut transaction, &individual).await?;
+                TopIp::report(&mut transaction, &individual).await?;
+                TopProtocol::report(&mut transaction, &individual).await?;
+
+                // Report combination and time-based statistics
+                TopUsrPassCombo::report(&mut transaction, &individual).await?;
+                TopHourly::report(&mut transaction, individual.timestamp()).await?;
+                TopDaily::report(&mut transaction, individual.timestamp()).await?;
+                TopWeekly::report(&mut transaction, individual.timestamp()).await?;
+                TopYearly::report(&mut transaction, individual.timestamp()).await?;
+                stored.push(individual);
+            }
+
+            transaction.commit().await?;
+            let elasped_time = start.elapsed();
+            info!(
+                "Successfully stored {} individual report(s) in {:.2?}.",
+                stored.len(),
+                elasped_time
+            );
+            Ok(stored)
+        }
+
+        /// Enriches a stored attempt and updates the location counters. The
+        /// attempt stays pending if any step fails.
+        pub async fn finish_report(
+            &self,
+            individual: &Individual,
         ) -> anyhow::Result<ProcessedIndividual> {
             let start = Instant::now();
-            let transaction = self.brute.db_pool.begin().await.unwrap();
-            // Report individual
-            let individual = Individual::report(self, &payload).await?;
+            // the attempt references the IP's row, so it has to exist first.
+            // looked up before the transaction so it isn't held open while
+            // the provider answers.
+            let ip: IpAddr = individual.ip().parse()?;
+            self.brute.enrichment.lookup(ip).await?;
 
+            let mut transaction = self.brute.db_pool.begin().await?;
             // Report processed individual
-            let processed_individual = ProcessedIndividual::report(self, &individual).await?;
-
-            // Report top statistics
-            TopUsername::report(self, &individual).await?;
-            TopPassword::report(self, &individual).await?;
-            TopIp::report(self, &individual).await?;
-            TopProtocol::report(self, &individual).await?;
+            let processed_individual =
+                ProcessedIndividual::report(&mut transaction, individual).await?;
 
             // Report location details
-            TopCity::report(self, &processed_individual).await?;
-            TopRegion::report(self, &processed_individual).await?;
-            TopCountry::report(self, &processed_individual).await?;
-            TopTimezone::report(self, &processed_individual).await?;
-            TopOrg::report(self, &processed_individual).await?;
-            TopPostal::report(self, &processed_individual).await?;
-            TopLocation::report(self, &processed_individual).await?;
-
-            // Report combination and time-based statistics
-            TopUsrPassCombo::report(self, &individual).await?;
-            TopHourly::report(self, &0).await?;
-            TopDaily::report(self, &0).await?;
-            TopWeekly::report(self, &0).await?;
-            TopYearly::report(self, &0).await?;
-
+            TopCity::report(&mut transaction, &processed_individual).await?;
+            TopRegion::report(&mut transaction, &processed_individual).await?;
+            TopCountry::report(&mut transaction, &processed_individual).await?;
+            TopTimezone::report(&mut transaction, &processed_individual).await?;
+            TopOrg::report(&mut transaction, &processed_individual).await?;
+            TopPostal::report(&mut transaction, &processed_individual).await?;
+            TopLocation::report(&mut transaction, &processed_individual).await?;
+
+            sqlx::query(
+                "UPDATE individual SET enrichment_status = 'enriched', enrichment_retry_at = NULL WHERE id = $1 AND enrichment_status = 'pending';",
+            )
+            .bind(individual.id())
+            .execute(&mut *transaction)
+            .await?;
+
+            transaction.commit().await?;
             let elasped_time = start.elapsed();
             info!(
-                "Successfully processed individual report in {:.2?}.",
+                "Successfully enriched individual report in {:.2?}.",
                 elasped_time
             );
-            transaction.commit().await.unwrap();
             Ok(processed_individual)
         }
+
+        /// Schedules another try with exponential backoff. Returns whether
+        /// the attempt ran out of tries and is marked as failed.
+        pub async fn enrichment_failed(&self, individual: &Individual) -> anyhow::Result<bool> {
+            let query = r#"
+                UPDATE individual SET
+                    enrichment_attempts = enrichment_attempts + 1,
+                    enrichment_status = CASE WHEN enrichment_attempts + 1 >= $4 THEN 'failed' ELSE 'pending' END,
+                    enrichment_retry_at = $1 + LEAST($2 << LEAST(enrichment_attempts, 30), $3)
+                WHERE id = $5 AND enrichment_status = 'pending'
+                RETURNING enrichment_status;
+            "#;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+            let status = sqlx::query_scalar::<_, String>(query)
+                .bind(now)
+                .bind(ENRICHMENT_BACKOFF.as_millis() as i64)
+                .bind(ENRICHMENT_MAX_BACKOFF.as_millis() as i64)
+                .bind(ENRICHMENT_MAX_ATTEMPTS)
+                .bind(individual.id())
+                .fetch_optional(&self.brute.db_pool)
+                .await?;
+            Ok(status.as_deref() == Some("failed"))
+        }
     }
 
     impl Reporter for BruteReporter<BruteSystem> {}
@@ -681,14 +2228,15 @@ pub mod reporter {
 
     // individual
     impl Reportable<BruteReporter<BruteSystem>, Individual> for Individual {
-        async fn report<'a>(
-            reporter: &BruteReporter<BruteSystem>,
-            model: &'a Individual,
+        async fn report(
+            conn: &mut PgConnection,
+            model: &Individual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
+            // the lease runs out if the attempt is never enriched, it's
+            // queued again then.
             let query = r#"
-                INSERT INTO individual (id, username, password, ip, protocol, timestamp)
-                VALUES ($1, $2, $3, $4, $5, $6)
+                INSERT INTO individual (id, username, password, ip, protocol, timestamp, sensor_id, enrichment_retry_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
                 RETURNING *
             "#;
 
@@ -707,7 +2255,9 @@ pub mod reporter {
                 .bind(model.ip())
                 .bind(model.protocol())
                 .bind(new_timestamp)
-                .fetch_one(pool)
+                .bind(&model.sensor_id)
+                .bind(new_timestamp + ENRICHMENT_LEASE.as_millis() as i64)
+                .fetch_one(&mut *conn)
                 .await?;
 
             Ok(inserted)
@@ -716,202 +2266,40 @@ pub mod reporter {
 
     // processed individual
     impl Reportable<BruteReporter<BruteSystem>, Individual> for ProcessedIndividual {
-        async fn report<'a>(
-            reporter: &BruteReporter<BruteSystem>,
-            model: &'a Individual,
+        async fn report(
+            conn: &mut PgConnection,
+            model: &Individual,
         ) -> anyhow::Result<ProcessedIndividual> {
-            let pool = &reporter.brute.db_pool;
-            let ipinfo = &reporter.brute.ipinfo_client;
-            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
-
-            let select_query = "
-            SELECT * FROM processed_individual
-            WHERE ip = $1
-            ORDER BY timestamp DESC
-            LIMIT 1;
-            ";
-
-            let insert_query = "
-            INSERT INTO processed_individual (
-                id, username, password, ip, protocol, hostname, city, region, country, loc, org, postal,
-                asn, asn_name, asn_domain, asn_route, asn_type,
-                company_name, company_domain, company_type,
-                vpn, proxy, tor, relay, hosting, service,
-                abuse_address, abuse_country, abuse_email, abuse_name, abuse_network, abuse_phone,
-                domain_ip, domain_total, domains, timestamp, timezone
-            ) VALUES (
-                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12,
-                $13, $14, $15, $16, $17,
-                $18, $19, $20,
-                $21, $22, $23, $24, $25, $26,
-                $27, $28, $29, $30, $31, $32,
-                $33, $34, $35, $36, $37
-            ) RETURNING *;
+            let query = "
+            WITH inserted AS (
+                INSERT INTO processed_individual (id, username, password, ip, protocol, timestamp, sensor_id)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                RETURNING *
+            )
+            SELECT * FROM inserted JOIN ip_enrichment USING (ip);
             ";
 
-            // Default values for IP details
-            let asn_default = AsnDetails {
-                asn: String::default(),
-                name: String::default(),
-                domain: String::default(),
-                route: String::default(),
-                asn_type: String::default(),
-            };
-
-            let company_default = CompanyDetails {
-                name: String::default(),
-                domain: String::default(),
-                company_type: String::default(),
-            };
-
-            let abuse_default = AbuseDetails {
-                address: String::default(),
-                country: String::default(),
-                email: String::default(),
-                name: String::default(),
-                network: String::default(),
-                phone: String::default(),
-            };
-
-            let privacy_default = PrivacyDetails {
-                vpn: false,
-                proxy: false,
-                tor: false,
-                relay: false,
-                hosting: false,
-                service: String::default(),
-            };
-
-            let domain_default = DomainsDetails {
-                ip: Some(String::default()),
-                total: 0,
-                domains: Vec::default(),
-            };
-
-            let ip_exists = sqlx::query_as::<_, ProcessedIndividual>(select_query)
+            let processed = sqlx::query_as::<_, ProcessedIndividual>(query)
+                .bind(model.id())
+                .bind(model.username())
+                .bind(model.password())
                 .bind(model.ip())
-                .fetch_optional(pool)
+                .bind(model.protocol())
+                .bind(model.timestamp)
+                .bind(&model.sensor_id)
+                .fetch_one(&mut *conn)
                 .await?;
 
-            let mut ipinfo_lock = ipinfo.lock().await;
-            let ip_details = match ip_exists {
-                Some(mut result) if now - result.timestamp <= 300_000 => {
-                    if result.postal().is_none() {
-                        // fix unwrap error.
-                        result.postal = Some(String::default())
-                    }
-                    info!("Reusing cached results for IP: {}", model.ip());
-                    sqlx::query_as::<_, ProcessedIndividual>(insert_query)
-                        .bind(model.id())
-                        .bind(model.username())
-                        .bind(model.password())
-                        .bind(model.ip())
-                        .bind(model.protocol())
-                        .bind(result.hostname())
-                        .bind(result.city())
-                        .bind(result.region())
-                        .bind(result.country())
-                        .bind(result.loc())
-                        .bind(result.org())
-                        .bind(result.postal())
-                        .bind(result.asn())
-                        .bind(result.asn_name())
-                        .bind(result.asn_domain())
-                        .bind(result.asn_route())
-                        .bind(result.asn_type())
-                        .bind(result.company_name())
-                        .bind(result.company_domain())
-                        .bind(result.company_type())
-                        .bind(result.vpn())
-                        .bind(result.proxy())
-                        .bind(result.tor())
-                        .bind(result.relay())
-                        .bind(result.hosting())
-                        .bind(result.service())
-                        .bind(result.abuse_address())
-                        .bind(result.abuse_country())
-                        .bind(result.abuse_email())
-                        .bind(result.abuse_name())
-                        .bind(result.abuse_network())
-                        .bind(result.abuse_phone())
-                        .bind(result.domain_ip())
-                        .bind(result.domain_total().unwrap())
-                        .bind(result.domains())
-                        .bind(model.timestamp)
-                        .bind(result.timezone())
-                        .fetch_one(pool)
-                        .await?;
-                    result
-                }
-                _ => {
-                    info!("Fetching new details from ipinfo for IP: {}", model.ip());
-                    let mut ip_details = ipinfo_lock.lookup(model.ip()).await?;
-
-                    let asn_details = ip_details.asn.as_ref().unwrap_or(&asn_default);
-                    let company_details = ip_details.company.as_ref().unwrap_or(&company_default);
-                    let abuse_details = ip_details.abuse.as_ref().unwrap_or(&abuse_default);
-                    let domain_details = ip_details.domains.as_ref().unwrap_or(&domain_default);
-                    let privacy_details = ip_details.privacy.as_ref().unwrap_or(&privacy_default);
-                    if ip_details.postal.is_none() {
-                        // fix unwrap error.
-                        ip_details.postal = Some(String::default())
-                    }
-
-                    // Insert the new details
-                    sqlx::query_as::<_, ProcessedIndividual>(insert_query)
-                        .bind(model.id())
-                        .bind(model.username())
-                        .bind(model.password())
-                        .bind(model.ip())
-                        .bind(model.protocol())
-                        .bind(&ip_details.hostname)
-                        .bind(&ip_details.city)
-                        .bind(&ip_details.region)
-                        .bind(&ip_details.country)
-                        .bind(&ip_details.loc)
-                        .bind(&ip_details.org)
-                        .bind(&ip_details.postal)
-                        .bind(&asn_details.asn)
-                        .bind(&asn_details.name)
-                        .bind(&asn_details.domain)
-                        .bind(&asn_details.route)
-                        .bind(&asn_details.asn_type)
-                        .bind(&company_details.name)
-                        .bind(&company_details.domain)
-                        .bind(&company_details.company_type)
-                        .bind(privacy_details.vpn)
-                        .bind(privacy_details.proxy)
-                        .bind(privacy_details.tor)
-                        .bind(privacy_details.relay)
-                        .bind(privacy_details.hosting)
-                        .bind(&privacy_details.service)
-                        .bind(&abuse_details.address)
-                        .bind(&abuse_details.country)
-                        .bind(&abuse_details.email)
-                        .bind(&abuse_details.name)
-                        .bind(&abuse_details.network)
-                        .bind(&abuse_details.phone)
-                        .bind(&domain_details.ip)
-                        .bind(domain_details.total as i64)
-                        .bind(&domain_details.domains)
-                        .bind(model.timestamp)
-                        .bind(&ip_details.timezone)
-                        .fetch_one(pool)
-                        .await?
-                }
-            };
-
-            Ok(ip_details)
+            Ok(processed)
         }
     }
 
     // top username
     impl Reportable<BruteReporter<BruteSystem>, Individual> for TopUsername {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &Individual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_username ( username, amount )
@@ -922,7 +2310,7 @@ pub mod reporter {
             "#;
             let result = sqlx::query_as::<_, TopUsername>(query)
                 .bind(model.username())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -931,10 +2319,9 @@ pub mod reporter {
     // top password
     impl Reportable<BruteReporter<BruteSystem>, Individual> for TopPassword {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &Individual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_password ( password, amount )
@@ -945,7 +2332,7 @@ pub mod reporter {
             "#;
             let result = sqlx::query_as::<_, TopPassword>(query)
                 .bind(model.password())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -954,10 +2341,9 @@ pub mod reporter {
     // top ip
     impl Reportable<BruteReporter<BruteSystem>, Individual> for TopIp {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &Individual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_ip ( ip, amount )
@@ -968,7 +2354,7 @@ pub mod reporter {
             "#;
             let result = sqlx::query_as::<_, TopIp>(query)
                 .bind(model.ip())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -977,10 +2363,9 @@ pub mod reporter {
     // top protocol
     impl Reportable<BruteReporter<BruteSystem>, Individual> for TopProtocol {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &Individual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_protocol ( protocol, amount )
@@ -991,7 +2376,7 @@ pub mod reporter {
             "#;
             let result = sqlx::query_as::<_, TopProtocol>(query)
                 .bind(model.protocol())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -1000,10 +2385,9 @@ pub mod reporter {
     // top city
     impl Reportable<BruteReporter<BruteSystem>, ProcessedIndividual> for TopCity {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &ProcessedIndividual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_city (city, country, amount)
@@ -1015,7 +2399,7 @@ pub mod reporter {
             let result = sqlx::query_as::<_, TopCity>(query)
                 .bind(model.city())
                 .bind(model.country())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -1024,10 +2408,9 @@ pub mod reporter {
     // top region
     impl Reportable<BruteReporter<BruteSystem>, ProcessedIndividual> for TopRegion {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &ProcessedIndividual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_region (region, country, amount)
@@ -1039,7 +2422,7 @@ pub mod reporter {
             let result = sqlx::query_as::<_, TopRegion>(query)
                 .bind(model.region())
                 .bind(model.country())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -1048,10 +2431,9 @@ pub mod reporter {
     // top timezone
     impl Reportable<BruteReporter<BruteSystem>, ProcessedIndividual> for TopTimezone {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &ProcessedIndividual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_timezone ( timezone, amount )
@@ -1062,7 +2444,7 @@ pub mod reporter {
             "#;
             let result = sqlx::query_as::<_, TopTimezone>(query)
                 .bind(model.timezone())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -1071,10 +2453,9 @@ pub mod reporter {
     // top country
     impl Reportable<BruteReporter<BruteSystem>, ProcessedIndividual> for TopCountry {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &ProcessedIndividual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_country ( country, amount )
@@ -1085,7 +2466,7 @@ pub mod reporter {
             "#;
             let result = sqlx::query_as::<_, TopCountry>(query)
                 .bind(model.country())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -1094,10 +2475,16 @@ pub mod reporter {
     // top org
     impl Reportable<BruteReporter<BruteSystem>, ProcessedIndividual> for TopOrg {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &ProcessedIndividual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
+            if model.org().is_none() {
+                info!(
+                    "top_org not updated as no org information was found. for: {}",
+                    model.id()
+                );
+                return Ok(TopOrg::new(String::default(), 0));
+            }
             // query
             let query = r#"
                 INSERT INTO top_org ( org, amount )
@@ -1108,7 +2495,7 @@ pub mod reporter {
             "#;
             let result = sqlx::query_as::<_, TopOrg>(query)
                 .bind(model.org())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -1117,7 +2504,7 @@ pub mod reporter {
     // top postal
     impl Reportable<BruteReporter<BruteSystem>, ProcessedIndividual> for TopPostal {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &ProcessedIndividual,
         ) -> anyhow::Result<Self> {
             if model.postal().is_none() {
@@ -1127,7 +2514,6 @@ pub mod reporter {
                 );
                 return Ok(TopPostal::new(String::default(), 0));
             }
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_postal ( postal, amount )
@@ -1138,7 +2524,7 @@ pub mod reporter {
             "#;
             let result = sqlx::query_as::<_, TopPostal>(query)
                 .bind(model.postal())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -1147,10 +2533,9 @@ pub mod reporter {
     // top postal
     impl Reportable<BruteReporter<BruteSystem>, ProcessedIndividual> for TopLocation {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &ProcessedIndividual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_loc ( loc, amount )
@@ -1161,7 +2546,7 @@ pub mod reporter {
             "#;
             let result = sqlx::query_as::<_, TopLocation>(query)
                 .bind(model.loc())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
@@ -1169,10 +2554,9 @@ pub mod reporter {
 
     impl Reportable<BruteReporter<BruteSystem>, Individual> for TopUsrPassCombo {
         async fn report(
-            reporter: &BruteReporter<BruteSystem>,
+            conn: &mut PgConnection,
             model: &Individual,
         ) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
             // query
             let query = r#"
                 INSERT INTO top_usr_pass_combo (
@@ -1188,273 +2572,85 @@ pub mod reporter {
                 .bind(Uuid::new_v4().as_simple().to_string())
                 .bind(model.username())
                 .bind(model.password())
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
             Ok(result)
         }
     }
 
     impl Reportable<BruteReporter<BruteSystem>, i64> for TopHourly {
-        async fn report(reporter: &BruteReporter<BruteSystem>, _: &i64) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .map_err(|e| anyhow::anyhow!("Failed to get system time: {}", e))?
-                .as_millis() as i64;
-
-            let select_query = r#"
-                SELECT *
-                FROM top_hourly
-                ORDER BY timestamp DESC
-                LIMIT 1;
+        async fn report(conn: &mut PgConnection, timestamp: &i64) -> anyhow::Result<Self> {
+            // the bucket starts at the hour in the transaction's time zone,
+            // set by `start_report`.
+            let query = r#"
+                INSERT INTO top_hourly (timestamp, amount)
+                VALUES ((EXTRACT(EPOCH FROM date_trunc('hour', to_timestamp($1 / 1000.0))) * 1000)::BIGINT, 1)
+                ON CONFLICT (timestamp)
+                DO UPDATE SET amount = top_hourly.amount + 1
+                RETURNING *;
             "#;
-
-            let top_hourly = sqlx::query_as::<_, TopHourly>(select_query)
-                .fetch_optional(pool)
+            let result = sqlx::query_as::<_, TopHourly>(query)
+                .bind(timestamp)
+                .fetch_one(&mut *conn)
                 .await?;
-
-            match top_hourly {
-                Some(record) if now - record.timestamp > 3_600_000 => {
-                    // Insert new record if it exceeds an hour
-                    let insert_query = r#"
-                        INSERT INTO top_hourly (timestamp, amount)
-                        VALUES ($1, 1);
-                    "#;
-                    sqlx::query(insert_query).bind(now).execute(pool).await?;
-
-                    Ok(TopHourly {
-                        timestamp: now,
-                        amount: 1,
-                    })
-                }
-                Some(mut record) => {
-                    // Update existing record within the hour
-                    record.amount += 1;
-                    let update_query = r#"
-                        UPDATE top_hourly
-                        SET amount = $1
-                        WHERE timestamp = $2;
-                    "#;
-                    sqlx::query(update_query)
-                        .bind(record.amount)
-                        .bind(record.timestamp)
-                        .execute(pool)
-                        .await?;
-
-                    Ok(record)
-                }
-                None => {
-                    // Insert a new record if none exists
-                    let insert_query = r#"
-                        INSERT INTO top_hourly (timestamp, amount)
-                        VALUES ($1, 1);
-                    "#;
-                    sqlx::query(insert_query).bind(now).execute(pool).await?;
-
-                    Ok(TopHourly {
-                        timestamp: now,
-                        amount: 1,
-                    })
-                }
-            }
+            Ok(result)
         }
     }
 
     impl Reportable<BruteReporter<BruteSystem>, i64> for TopDaily {
-        async fn report(reporter: &BruteReporter<BruteSystem>, _: &i64) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .map_err(|e| anyhow::anyhow!("Failed to get system time: {}", e))?
-                .as_millis() as i64;
-
-            let select_query = r#"
-            SELECT *
-            FROM top_daily
-            ORDER BY timestamp DESC
-            LIMIT 1;
-        "#;
-
-            let top_daily = sqlx::query_as::<_, TopDaily>(select_query)
-                .fetch_optional(pool)
+        async fn report(conn: &mut PgConnection, timestamp: &i64) -> anyhow::Result<Self> {
+            // the bucket starts at the day in the transaction's time zone,
+            // set by `start_report`.
+            let query = r#"
+                INSERT INTO top_daily (timestamp, amount)
+                VALUES ((EXTRACT(EPOCH FROM date_trunc('day', to_timestamp($1 / 1000.0))) * 1000)::BIGINT, 1)
+                ON CONFLICT (timestamp)
+                DO UPDATE SET amount = top_daily.amount + 1
+                RETURNING *;
+            "#;
+            let result = sqlx::query_as::<_, TopDaily>(query)
+                .bind(timestamp)
+                .fetch_one(&mut *conn)
                 .await?;
-
-            match top_daily {
-                Some(record) if now - record.timestamp > 86_400_000 => {
-                    // Insert new record if it exceeds a day
-                    let insert_query = r#"
-                    INSERT INTO top_daily (timestamp, amount)
-                    VALUES ($1, 1);
-                "#;
-                    sqlx::query(insert_query).bind(now).execute(pool).await?;
-
-                    Ok(TopDaily {
-                        timestamp: now,
-                        amount: 1,
-                    })
-                }
-                Some(mut record) => {
-                    // Update existing record within the day
-                    record.amount += 1;
-                    let update_query = r#"
-                    UPDATE top_daily
-                    SET amount = $1
-                    WHERE timestamp = $2;
-                "#;
-                    sqlx::query(update_query)
-                        .bind(record.amount)
-                        .bind(record.timestamp)
-                        .execute(pool)
-                        .await?;
-
-                    Ok(record)
-                }
-                None => {
-                    // Insert a new record if none exists
-                    let insert_query = r#"
-                    INSERT INTO top_daily (timestamp, amount)
-                    VALUES ($1, 1);
-                "#;
-                    sqlx::query(insert_query).bind(now).execute(pool).await?;
-
-                    Ok(TopDaily {
-                        timestamp: now,
-                        amount: 1,
-                    })
-                }
-            }
+            Ok(result)
         }
     }
 
     impl Reportable<BruteReporter<BruteSystem>, i64> for TopWeekly {
-        async fn report(reporter: &BruteReporter<BruteSystem>, _: &i64) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .map_err(|e| anyhow::anyhow!("Failed to get system time: {}", e))?
-                .as_millis() as i64;
-
-            let select_query = r#"
-                SELECT *
-                FROM top_weekly
-                ORDER BY timestamp DESC
-                LIMIT 1;
+        async fn report(conn: &mut PgConnection, timestamp: &i64) -> anyhow::Result<Self> {
+            // the bucket starts at the week in the transaction's time zone,
+            // set by `start_report`.
+            let query = r#"
+                INSERT INTO top_weekly (timestamp, amount)
+                VALUES ((EXTRACT(EPOCH FROM date_trunc('week', to_timestamp($1 / 1000.0))) * 1000)::BIGINT, 1)
+                ON CONFLICT (timestamp)
+                DO UPDATE SET amount = top_weekly.amount + 1
+                RETURNING *;
             "#;
-
-            let top_weekly = sqlx::query_as::<_, TopWeekly>(select_query)
-                .fetch_optional(pool)
+            let result = sqlx::query_as::<_, TopWeekly>(query)
+                .bind(timestamp)
+                .fetch_one(&mut *conn)
                 .await?;
-
-            match top_weekly {
-                Some(record) if now - record.timestamp > 604_800_000 => {
-                    // Insert new record if it exceeds a week
-                    let insert_query = r#"
-                        INSERT INTO top_weekly (timestamp, amount)
-                        VALUES ($1, 1);
-                    "#;
-                    sqlx::query(insert_query).bind(now).execute(pool).await?;
-
-                    Ok(TopWeekly {
-                        timestamp: now,
-                        amount: 1,
-                    })
-                }
-                Some(mut record) => {
-                    // Update existing record within the week
-                    record.amount += 1;
-                    let update_query = r#"
-                        UPDATE top_weekly
-                        SET amount = $1
-                        WHERE timestamp = $2;
-                    "#;
-                    sqlx::query(update_query)
-                        .bind(record.amount)
-                        .bind(record.timestamp)
-                        .execute(pool)
-                        .await?;
-
-                    Ok(record)
-                }
-                None => {
-                    // Insert a new record if none exists
-                    let insert_query = r#"
-                        INSERT INTO top_weekly (timestamp, amount)
-                        VALUES ($1, 1);
-                    "#;
-                    sqlx::query(insert_query).bind(now).execute(pool).await?;
-
-                    Ok(TopWeekly {
-                        timestamp: now,
-                        amount: 1,
-                    })
-                }
-            }
+            Ok(result)
         }
     }
 
     impl Reportable<BruteReporter<BruteSystem>, i64> for TopYearly {
-        async fn report(reporter: &BruteReporter<BruteSystem>, _: &i64) -> anyhow::Result<Self> {
-            let pool = &reporter.brute.db_pool;
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .map_err(|e| anyhow::anyhow!("Failed to get system time: {}", e))?
-                .as_millis() as i64;
-
-            let select_query = r#"
-                SELECT *
-                FROM top_yearly
-                ORDER BY timestamp DESC
-                LIMIT 1;
+        async fn report(conn: &mut PgConnection, timestamp: &i64) -> anyhow::Result<Self> {
+            // the bucket starts at the year in the transaction's time zone,
+            // set by `start_report`.
+            let query = r#"
+                INSERT INTO top_yearly (timestamp, amount)
+                VALUES ((EXTRACT(EPOCH FROM date_trunc('year', to_timestamp($1 / 1000.0))) * 1000)::BIGINT, 1)
+                ON CONFLICT (timestamp)
+                DO UPDATE SET amount = top_yearly.amount + 1
+                RETURNING *;
             "#;
-
-            let top_yearly = sqlx::query_as::<_, TopYearly>(select_query)
-                .fetch_optional(pool)
+            let result = sqlx::query_as::<_, TopYearly>(query)
+                .bind(timestamp)
+                .fetch_one(&mut *conn)
                 .await?;
-
-            match top_yearly {
-                Some(record) if now - record.timestamp > 31_556_800_000 => {
-                    // Insert new record if it exceeds a year
-                    let insert_query = r#"
-                        INSERT INTO top_yearly (timestamp, amount)
-                        VALUES ($1, 1);
-                    "#;
-                    sqlx::query(insert_query).bind(now).execute(pool).await?;
-
-                    Ok(TopYearly {
-                        timestamp: now,
-                        amount: 1,
-                    })
-                }
-                Some(mut record) => {
-                    // Update existing record within the year
-                    record.amount += 1;
-                    let update_query = r#"
-                        UPDATE top_yearly
-                        SET amount = $1
-                        WHERE timestamp = $2;
-                    "#;
-                    sqlx::query(update_query)
-                        .bind(record.amount)
-                        .bind(record.timestamp)
-                        .execute(pool)
-                        .await?;
-
-                    Ok(record)
-                }
-                None => {
-                    // Insert a new record if none exists
-                    let insert_query = r#"
-                        INSERT INTO top_yearly (timestamp, amount)
-                        VALUES ($1, 1);
-                    "#;
-                    sqlx::query(insert_query).bind(now).execute(pool).await?;
-
-                    Ok(TopYearly {
-                        timestamp: now,
-                        amount: 1,
-                    })
-                }
-            }
+            Ok(result)
         }
     }
 }
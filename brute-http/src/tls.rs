@@ -0,0 +1,303 @@
+//////////
+// TLS //
+////////
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use log::{info, warn};
+use rustls::{
+    crypto::CryptoProvider,
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    Error, InconsistentKeys,
+};
+
+/// How often the certificate files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// `cert.pem` and `key.pem` in the certs directory.
+#[derive(Clone, Debug)]
+pub struct CertificatePaths {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl CertificatePaths {
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            cert: directory.join("cert.pem"),
+            key: directory.join("key.pem"),
+        }
+    }
+
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let cert = fs::metadata(&self.cert).and_then(|m| m.modified()).ok()?;
+        let key = fs::metadata(&self.key).and_then(|m| m.modified()).ok()?;
+        Some((cert, key))
+    }
+}
+
+/// Hands out the last certificate that loaded successfully, so it can be
+/// swapped without restarting the listener.
+pub struct CertificateResolver {
+    paths: CertificatePaths,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertificateResolver {
+    pub fn load(paths: CertificatePaths) -> Result<Self, String> {
+        let current = load_certified_key(&paths)?;
+        Ok(Self {
+            paths,
+            current: RwLock::new(Arc::new(current)),
+        })
+    }
+
+    /// Keeps the current certificate if the new one doesn't load.
+    pub fn reload(&self) -> Result<(), String> {
+        let certified = load_certified_key(&self.paths)?;
+        *self.current.write().unwrap() = Arc::new(certified);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CertificateResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertificateResolver")
+            .field("paths", &self.paths)
+            .finish()
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+/// Loads a PEM certificate chain and its RSA (PKCS#1), EC (SEC1) or PKCS#8
+/// private key.
+pub fn load_certified_key(paths: &CertificatePaths) -> Result<CertifiedKey, String> {
+    let certs = rustls_pemfile::certs(&mut open(&paths.cert)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read {}: {}", paths.cert.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("{} has no certificates.", paths.cert.display()));
+    }
+    let key = rustls_pemfile::private_key(&mut open(&paths.key)?)
+        .map_err(|e| format!("Failed to read {}: {}", paths.key.display(), e))?
+        .ok_or_else(|| format!("{} has no private key.", paths.key.display()))?;
+
+    let provider = CryptoProvider::get_default()
+        .ok_or_else(|| "No crypto provider is installed.".to_string())?;
+    let signing_key = provider
+        .key_provider
+        .load_private_key(key)
+        .map_err(|e| format!("Failed to load {}: {}", paths.key.display(), e))?;
+    let certified = CertifiedKey::new(certs, signing_key);
+    // also catches a half-replaced pair, the old certificate is kept.
+    match certified.keys_match() {
+        Ok(()) | Err(Error::InconsistentKeys(InconsistentKeys::Unknown)) => Ok(certified),
+        Err(e) => Err(format!(
+            "{} doesn't match {}: {}",
+            paths.key.display(),
+            paths.cert.display(),
+            e
+        )),
+    }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+/// Writes a self-signed certificate for localhost unless a certificate or
+/// key already exists. Returns whether one was generated.
+pub fn generate_self_signed(paths: &CertificatePaths) -> Result<bool, String> {
+    if paths.cert.exists() || paths.key.exists() {
+        return Ok(false);
+    }
+    if let Some(directory) = paths.cert.parent() {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create {}: {}", directory.display(), e))?;
+    }
+    let names = vec![
+        "localhost".to_string(),
+        "127.0.0.1".to_string(),
+        "::1".to_string(),
+    ];
+    let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(names)
+        .map_err(|e| format!("Failed to generate a certificate: {}", e))?;
+    if let Err(e) = write_private(&paths.key, key_pair.serialize_pem().as_bytes()) {
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            let _ = fs::remove_file(&paths.key);
+        }
+        return Err(format!("Failed to write {}: {}", paths.key.display(), e));
+    }
+    if let Err(e) = fs::write(&paths.cert, cert.pem()) {
+        // a key without its certificate would stop every later start.
+        let _ = fs::remove_file(&paths.cert);
+        let _ = fs::remove_file(&paths.key);
+        return Err(format!("Failed to write {}: {}", paths.cert.display(), e));
+    }
+    Ok(true)
+}
+
+/// Creates a file only its owner can read, the mode is set when it is
+/// created so the contents are never readable by anyone else.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)
+}
+
+/// Reloads the certificate when its files change and, on unix, on SIGHUP.
+pub fn spawn_reload(resolver: Arc<CertificateResolver>) {
+    let polled = resolver.clone();
+    actix_rt::spawn(async move {
+        let mut modified = polled.paths.modified();
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let latest = polled.paths.modified();
+            if latest.is_none() || latest == modified {
+                continue;
+            }
+            // only retried once the files change again.
+            modified = latest;
+            reload(&polled, "the certificate changed");
+        }
+    });
+
+    #[cfg(unix)]
+    actix_rt::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                warn!("Failed to listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            reload(&resolver, "SIGHUP");
+        }
+    });
+}
+
+fn reload(resolver: &CertificateResolver, reason: &str) {
+    match resolver.reload() {
+        Ok(()) => info!("Reloaded the TLS certificate ({}).", reason),
+        Err(e) => warn!("Failed to reload the TLS certificate, keeping the current one: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("brute-tls-{}-{}", std::process::id(), name))
+    }
+
+    /// `tests/certs/<name>.cert.pem` and `tests/certs/<name>.key.pem`.
+    fn fixture(name: &str) -> CertificatePaths {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/certs");
+        CertificatePaths {
+            cert: directory.join(format!("{}.cert.pem", name)),
+            key: directory.join(format!("{}.key.pem", name)),
+        }
+    }
+
+    fn install_provider() {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    }
+
+    #[test]
+    fn loads_every_key_format() {
+        install_provider();
+        // PKCS#1, SEC1 and PKCS#8.
+        for name in ["rsa", "ec", "pkcs8"] {
+            let loaded = load_certified_key(&fixture(name));
+            assert!(loaded.is_ok(), "{}: {:?}", name, loaded.err());
+        }
+    }
+
+    #[test]
+    fn mismatched_pairs_are_rejected() {
+        install_provider();
+        let paths = CertificatePaths {
+            cert: fixture("rsa").cert,
+            key: fixture("ec").key,
+        };
+        assert!(load_certified_key(&paths).is_err());
+    }
+
+    #[test]
+    fn reload_keeps_the_current_certificate_on_failure() {
+        install_provider();
+        let directory = temp_dir("reload");
+        fs::create_dir_all(&directory).unwrap();
+        let paths = CertificatePaths::new(&directory);
+        fs::copy(fixture("rsa").cert, &paths.cert).unwrap();
+        fs::copy(fixture("rsa").key, &paths.key).unwrap();
+        let resolver = CertificateResolver::load(paths.clone()).unwrap();
+        let current = || resolver.current.read().unwrap().cert[0].clone();
+        let before = current();
+
+        // half of a replaced pair.
+        fs::copy(fixture("ec").key, &paths.key).unwrap();
+        assert!(resolver.reload().is_err());
+        assert_eq!(current(), before);
+
+        fs::copy(fixture("ec").cert, &paths.cert).unwrap();
+        assert!(resolver.reload().is_ok());
+        assert_ne!(current(), before);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn a_failed_certificate_write_removes_the_key() {
+        let directory = temp_dir("partial");
+        let paths = CertificatePaths {
+            // too long for a file name, so only the certificate fails.
+            cert: directory.join("c".repeat(300)),
+            key: directory.join("key.pem"),
+        };
+        assert!(generate_self_signed(&paths).is_err());
+        assert!(!paths.key.exists());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn self_signed_key_is_private() {
+        let directory = temp_dir("self-signed");
+        let paths = CertificatePaths {
+            cert: directory.join("cert.pem"),
+            key: directory.join("key.pem"),
+        };
+        assert_eq!(generate_self_signed(&paths), Ok(true));
+        // existing files are left alone.
+        assert_eq!(generate_self_signed(&paths), Ok(false));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&paths.key).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,9 +1,9 @@
 use std::net::IpAddr;
 
-use ipnetwork::{Ipv4Network, Ipv6Network};
+use ipnetwork::IpNetwork;
 use regex::Regex;
 
-use crate::{error::BruteResponeError, model::Individual};
+use crate::{error::BruteResponeError, model::Individual, system::CreateSensor};
 
 pub trait Validate {
     fn validate(&mut self) -> anyhow::Result<(), BruteResponeError>;
@@ -57,21 +57,50 @@ impl Validate for Individual {
             self.protocol = "SSH".to_string();
         }
         
-        validate_and_check_ip(self.ip())?;
+        validate_ip(self.ip())?;
+        Ok(())
+    }
+}
+
+impl Validate for CreateSensor {
+    fn validate(&mut self) -> anyhow::Result<(), BruteResponeError> {
+        let id_re = Regex::new(r"^[A-Za-z0-9_.-]{1,64}$").unwrap();
+        if !id_re.is_match(&self.id) {
+            return Err(BruteResponeError::BadRequest(
+                "input validation error: sensor id must be 1 to 64 letters, digits, '_', '.' or '-'.".to_string(),
+            ));
+        }
+
+        if self.name.is_empty() {
+            // the id doubles as the name.
+            self.name = self.id.clone();
+        }
+
+        if self.name.len() > 255 {
+            return Err(BruteResponeError::BadRequest(
+                "input validation error: name is too long max is 255 characters.".to_string(),
+            ));
+        }
         Ok(())
     }
 }
 
 
-pub fn validate_and_check_ip(ip_str: &str) -> Result<(), BruteResponeError> {
+pub fn validate_and_check_ip(ip_str: &str, ignored: &IpFilter) -> Result<(), BruteResponeError> {
+    let ip = validate_ip(ip_str)?;
+    ignored.check(ip)?;
+
+    Ok(())
+}
+
+pub fn validate_ip(ip_str: &str) -> Result<IpAddr, BruteResponeError> {
     let ip: IpAddr = ip_str.parse().map_err(|_| BruteResponeError::BadRequest(
         "Input validation error: Invalid IP address format.".to_string(),
     ))?;
 
     validate_ip_format(ip_str)?;
-    is_private_ip(ip)?;
 
-    Ok(())
+    Ok(ip)
 }
 
 fn validate_ip_format(ip_address: &str) -> Result<(), BruteResponeError> {
@@ -88,38 +117,98 @@ fn validate_ip_format(ip_address: &str) -> Result<(), BruteResponeError> {
     }
 }
 
-fn is_private_ip(ip: IpAddr) -> Result<(), BruteResponeError> {
-    // Define private IP ranges
-    let ipv4_networks = [
-        Ipv4Network::new("10.0.0.0".parse().unwrap(), 8).unwrap(),
-        Ipv4Network::new("172.16.0.0".parse().unwrap(), 12).unwrap(),
-        Ipv4Network::new("192.168.0.0".parse().unwrap(), 16).unwrap(),
-        Ipv4Network::new("127.0.0.0".parse().unwrap(), 8).unwrap(), // Loopback network
-    ];
-
-    let ipv6_networks = [
-        Ipv6Network::new("fc00::".parse().unwrap(), 7).unwrap(), // Unique Local Addresses (ULA)
-        Ipv6Network::new("fe80::".parse().unwrap(), 10).unwrap(), // Link-Local Addresses
-    ];
-
-    match ip {
-        IpAddr::V4(ipv4) => {
-            if ipv4_networks.iter().any(|network| network.contains(ipv4)) {
-                Err(BruteResponeError::ValidationError(
-                    "Input validation error: IPv4 address is from a private network.".to_string(),
-                ))
-            } else {
-                Ok(())
-            }
+/// Source ranges that are dropped at ingest (loopback, private networks,
+/// our own monitoring probes...) and the networks sensors may report from.
+#[derive(Clone, Debug, Default)]
+pub struct IpFilter {
+    networks: Vec<IpNetwork>,
+    /// Every address may report while it is empty.
+    sensor_networks: Vec<IpNetwork>,
+}
+
+impl IpFilter {
+    pub fn new(networks: Vec<IpNetwork>, sensor_networks: Vec<IpNetwork>) -> Self {
+        Self { networks, sensor_networks }
+    }
+
+    /// Fails unless `peer`, the address a sensor connected from, is in one
+    /// of the sensor networks.
+    pub fn check_sensor(&self, peer: Option<IpAddr>) -> Result<(), BruteResponeError> {
+        if self.sensor_networks.is_empty() {
+            return Ok(());
+        }
+        match peer.map(|peer| peer.to_canonical()) {
+            Some(peer) if self.sensor_networks.iter().any(|network| network.contains(peer)) => Ok(()),
+            Some(peer) => Err(BruteResponeError::Forbidden(format!("{} may not report attempts.", peer))),
+            None => Err(BruteResponeError::Forbidden("unknown addresses may not report attempts.".to_string())),
         }
-        IpAddr::V6(ipv6) => {
-            if ipv6_networks.iter().any(|network| network.contains(ipv6)) {
-                Err(BruteResponeError::ValidationError(
-                    "Input validation error: IPv6 address is from a private network.".to_string(),
-                ))
-            } else {
-                Ok(())
-            }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4-mapped IPv6 addresses should match IPv4 ranges.
+        let ip = ip.to_canonical();
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    pub fn check(&self, ip: IpAddr) -> Result<(), BruteResponeError> {
+        if self.contains(ip) {
+            Err(BruteResponeError::ValidationError(
+                "Input validation error: IP address is in an ignored range.".to_string(),
+            ))
+        } else {
+            Ok(())
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(networks: &[&str]) -> IpFilter {
+        IpFilter::new(networks.iter().map(|network| network.parse().unwrap()).collect(), Vec::new())
+    }
+
+    #[test]
+    fn ignored_ranges_are_rejected() {
+        let filter = filter(&["10.0.0.0/8", "fc00::/7"]);
+        assert!(filter.check("10.9.8.7".parse().unwrap()).is_err());
+        assert!(filter.check("fd00::1".parse().unwrap()).is_err());
+        assert!(filter.check("203.0.113.1".parse().unwrap()).is_ok());
+        assert!(filter.check("2001:db8::1".parse().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_match_ipv4_ranges() {
+        let filter = filter(&["127.0.0.0/8"]);
+        assert!(filter.contains("::ffff:127.0.0.1".parse().unwrap()));
+        assert!(!filter.contains("::ffff:203.0.113.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn an_empty_filter_accepts_everything() {
+        assert!(IpFilter::default().check("127.0.0.1".parse().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn sensors_only_report_from_their_networks() {
+        let open = IpFilter::default();
+        assert!(open.check_sensor(Some("203.0.113.1".parse().unwrap())).is_ok());
+        assert!(open.check_sensor(None).is_ok());
+
+        let restricted = IpFilter::new(Vec::new(), vec!["192.0.2.0/24".parse().unwrap(), "2001:db8::/32".parse().unwrap()]);
+        assert!(restricted.check_sensor(Some("192.0.2.7".parse().unwrap())).is_ok());
+        assert!(restricted.check_sensor(Some("::ffff:192.0.2.7".parse().unwrap())).is_ok());
+        assert!(restricted.check_sensor(Some("2001:db8::1".parse().unwrap())).is_ok());
+        assert!(restricted.check_sensor(Some("203.0.113.1".parse().unwrap())).is_err());
+        assert!(restricted.check_sensor(None).is_err());
+    }
+
+    #[test]
+    fn ingest_checks_the_format_before_the_filter() {
+        let filter = filter(&["127.0.0.0/8"]);
+        assert!(validate_and_check_ip("127.0.0.1", &filter).is_err());
+        assert!(validate_and_check_ip("not-an-ip", &filter).is_err());
+        assert!(validate_and_check_ip("203.0.113.1", &filter).is_ok());
+    }
+}
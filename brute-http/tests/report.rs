@@ -0,0 +1,843 @@
+//! Needs a database, so these only run with `DATABASE_URL` set and
+//! `cargo test -- --ignored`. Failures are injected with triggers that only
+//! fire for the values used here.
+
+use std::{
+    net::IpAddr,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use actix::Actor;
+use actix_web::{http::StatusCode, test};
+use ipnetwork::IpNetwork;
+
+use brute_http::{
+    config::Limits,
+    error::BruteResponeError,
+    enrichment::{Enriched, Enricher, EnrichmentCache},
+    http::{auth::ApiKeys, configure_app, signature::SignatureVerifier, AppState},
+    model::{Enrichment, Individual, TimeBucket, TopCity, TopUsername, TopUsrPassCombo},
+    system::{BruteSystem, Drain, GetProfile, RequestWithLimit, RetryEnrichment, SearchAttacks, SensorRegistry, Timeseries},
+    validator::IpFilter,
+};
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+const FAIL: &str = "brute-test-fail";
+
+/// Held by tests that write the counters directly, re-enrich or compare
+/// counters with the stored details, so one doesn't undo the other and
+/// only one job runs at a time.
+static COUNTERS: Mutex<()> = Mutex::const_new(());
+
+struct Fixed(Enrichment);
+
+#[async_trait::async_trait]
+impl Enricher for Fixed {
+    fn name(&self) -> &'static str {
+        "fixed"
+    }
+
+    async fn enrich(&self, _: IpAddr) -> anyhow::Result<Enriched> {
+        Ok(Enriched {
+            provider: self.name(),
+            enrichment: Some(self.0.clone()),
+        })
+    }
+}
+
+/// Counts the lookups that reach it, each takes a moment so concurrent
+/// lookups overlap.
+#[derive(Clone, Default)]
+struct Counting(Arc<AtomicUsize>);
+
+impl Counting {
+    fn calls(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait::async_trait]
+impl Enricher for Counting {
+    fn name(&self) -> &'static str {
+        "counting"
+    }
+
+    async fn enrich(&self, _: IpAddr) -> anyhow::Result<Enriched> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        Ok(Enriched {
+            provider: self.name(),
+            enrichment: Some(Enrichment {
+                country: "ZZ".to_string(),
+                ..Default::default()
+            }),
+        })
+    }
+}
+
+/// Fails every lookup while `down` is set.
+#[derive(Clone)]
+struct Flaky {
+    down: Arc<AtomicBool>,
+    enrichment: Enrichment,
+}
+
+#[async_trait::async_trait]
+impl Enricher for Flaky {
+    fn name(&self) -> &'static str {
+        "flaky"
+    }
+
+    async fn enrich(&self, _: IpAddr) -> anyhow::Result<Enriched> {
+        if self.down.load(Ordering::SeqCst) {
+            anyhow::bail!("flaky is down");
+        }
+        Ok(Enriched {
+            provider: self.name(),
+            enrichment: Some(self.enrichment.clone()),
+        })
+    }
+}
+
+async fn connect() -> Pool<Postgres> {
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must point at a test database");
+    let pool = PgPoolOptions::new().connect(&url).await.unwrap();
+    sqlx::migrate!("../migrations").run(&pool).await.unwrap();
+    pool
+}
+
+/// Makes every write of `FAIL` into `table.column` raise an error.
+async fn fail_on(pool: &Pool<Postgres>, table: &str, column: &str) {
+    let function = format!(
+        "CREATE OR REPLACE FUNCTION brute_test_fail_{table}() RETURNS trigger AS $$ BEGIN RAISE EXCEPTION 'injected failure'; END $$ LANGUAGE plpgsql;"
+    );
+    let trigger = format!(
+        "CREATE OR REPLACE TRIGGER brute_test_fail BEFORE INSERT OR UPDATE ON {table} FOR EACH ROW WHEN (NEW.{column} = '{FAIL}') EXECUTE FUNCTION brute_test_fail_{table}();"
+    );
+    sqlx::query(&function).execute(pool).await.unwrap();
+    sqlx::query(&trigger).execute(pool).await.unwrap();
+}
+
+async fn stop_failing(pool: &Pool<Postgres>, table: &str) {
+    let query = format!("DROP TRIGGER IF EXISTS brute_test_fail ON {table};");
+    sqlx::query(&query).execute(pool).await.unwrap();
+}
+
+async fn system(pool: &Pool<Postgres>, enrichment: Enrichment) -> BruteSystem {
+    // a zero ttl looks the IP up again, so it gets this test's details.
+    let cache = EnrichmentCache::new(
+        pool.clone(),
+        Box::new(Fixed(enrichment)),
+        Duration::ZERO,
+        NonZeroUsize::new(1).unwrap(),
+        None,
+    );
+    BruteSystem::new_brute(pool.clone(), cache, 0, "UTC", false).await
+}
+
+/// Never looked up before, so there are no expired details to fall back to.
+fn unique_ipv6() -> String {
+    let segments: Vec<String> = Uuid::new_v4()
+        .as_bytes()
+        .chunks(2)
+        .take(6)
+        .map(|pair| format!("{:x}", u16::from_be_bytes([pair[0], pair[1]])))
+        .collect();
+    format!("2001:db8:{}", segments.join(":"))
+}
+
+async fn count(pool: &Pool<Postgres>, query: &str, value: &str) -> i64 {
+    sqlx::query_scalar::<_, i64>(query)
+        .bind(value)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+fn unique() -> String {
+    Uuid::new_v4().as_simple().to_string()
+}
+
+fn unique_ip() -> String {
+    let id = Uuid::new_v4();
+    let bytes = id.as_bytes();
+    format!("198.18.{}.{}", bytes[0], bytes[1])
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn start_report_stores_nothing_when_a_step_fails() {
+    let pool = connect().await;
+    fail_on(&pool, "top_protocol", "protocol").await;
+    let reporter = system(&pool, Enrichment::default()).await.reporter();
+    let username = unique();
+    let password = unique();
+
+    let failed = reporter
+        .start_report(Individual::new_short(
+            username.clone(),
+            password.clone(),
+            unique_ip(),
+            FAIL.to_string(),
+        ))
+        .await;
+    assert!(failed.is_err());
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM individual WHERE username = $1", &username).await, 0);
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM top_username WHERE username = $1", &username).await, 0);
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM top_password WHERE password = $1", &password).await, 0);
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM top_usr_pass_combo WHERE username = $1", &username).await, 0);
+
+    reporter
+        .start_report(Individual::new_short(
+            username.clone(),
+            password.clone(),
+            unique_ip(),
+            "SSH".to_string(),
+        ))
+        .await
+        .unwrap();
+    stop_failing(&pool, "top_protocol").await;
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM individual WHERE username = $1", &username).await, 1);
+    assert_eq!(count(&pool, "SELECT amount::BIGINT FROM top_username WHERE username = $1", &username).await, 1);
+    assert_eq!(count(&pool, "SELECT amount::BIGINT FROM top_password WHERE password = $1", &password).await, 1);
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn finish_report_stays_pending_when_a_step_fails() {
+    let pool = connect().await;
+    fail_on(&pool, "top_postal", "postal").await;
+    let city = unique();
+    let region = unique();
+    let enrichment = Enrichment {
+        city: city.clone(),
+        region: region.clone(),
+        country: "ZZ".to_string(),
+        timezone: "UTC".to_string(),
+        postal: Some(FAIL.to_string()),
+        ..Default::default()
+    };
+    let reporter = system(&pool, enrichment).await.reporter();
+
+    let individual = reporter
+        .start_report(Individual::new_short(unique(), unique(), unique_ip(), "SSH".to_string()))
+        .await
+        .unwrap();
+    let failed = reporter.finish_report(&individual).await;
+    stop_failing(&pool, "top_postal").await;
+    assert!(failed.is_err());
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM processed_individual WHERE id = $1", individual.id()).await, 0);
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM top_city WHERE city = $1", &city).await, 0);
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM top_region WHERE region = $1", &region).await, 0);
+    assert_eq!(
+        count(&pool, "SELECT COUNT(*) FROM individual WHERE id = $1 AND enrichment_status = 'pending'", individual.id()).await,
+        1
+    );
+}
+
+fn location(city: &str) -> Enrichment {
+    Enrichment {
+        city: city.to_string(),
+        country: "ZZ".to_string(),
+        timezone: "UTC".to_string(),
+        ..Default::default()
+    }
+}
+
+/// Re-enriches the attempt with details of `new_city`, returns whether the
+/// job rebuilt the counters.
+async fn reenrich(pool: &Pool<Postgres>, individual: &Individual, new_city: &str, prunes_attempts: bool) -> bool {
+    let mut brute_system = system(pool, location(new_city)).await;
+    brute_system.prunes_attempts = prunes_attempts;
+    let lock = brute_system.lock_reenrich().await.unwrap();
+    let job = brute_system
+        .create_reenrich(individual.timestamp, individual.timestamp + 1)
+        .await
+        .unwrap();
+    let job = brute_system.run_reenrich(lock, job, |_| {}).await;
+    assert_eq!(job.status(), "done");
+    job.counters_rebuilt().unwrap()
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn reenrich_rebuilds_every_counter() {
+    let pool = connect().await;
+    let _counters = COUNTERS.lock().await;
+    let (old_city, new_city) = (unique(), unique());
+    let reporter = system(&pool, location(&old_city)).await.reporter();
+    let username = unique();
+    let individual = reporter
+        .start_report(Individual::new_short(username.clone(), unique(), unique_ip(), "SSH".to_string()))
+        .await
+        .unwrap();
+    reporter.finish_report(&individual).await.unwrap();
+    sqlx::query("UPDATE top_username SET amount = 5 WHERE username = $1;")
+        .bind(&username)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM top_usr_pass_combo WHERE username = $1;")
+        .bind(&username)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    assert!(reenrich(&pool, &individual, &new_city, false).await);
+    // the same kind of id `start_report` gives it.
+    let combo_id = sqlx::query_scalar::<_, String>("SELECT id FROM top_usr_pass_combo WHERE username = $1")
+        .bind(&username)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(combo_id.len() == 32 && combo_id.chars().all(|c| c.is_ascii_hexdigit()), "{}", combo_id);
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM top_city WHERE city = $1", &old_city).await, 0);
+    assert_eq!(count(&pool, "SELECT amount::BIGINT FROM top_city WHERE city = $1", &new_city).await, 1);
+    assert_eq!(count(&pool, "SELECT amount::BIGINT FROM top_username WHERE username = $1", &username).await, 1);
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn reenrich_moves_the_location_counters_while_attempts_are_pruned() {
+    let pool = connect().await;
+    let _counters = COUNTERS.lock().await;
+    let (old_city, new_city) = (unique(), unique());
+    let reporter = system(&pool, location(&old_city)).await.reporter();
+    let username = unique();
+    let individual = reporter
+        .start_report(Individual::new_short(username.clone(), unique(), unique_ip(), "SSH".to_string()))
+        .await
+        .unwrap();
+    reporter.finish_report(&individual).await.unwrap();
+    // pruned attempts keep counting where they were.
+    for (table, column, value) in [("top_city", "city", &old_city), ("top_username", "username", &username)] {
+        sqlx::query(&format!("UPDATE {table} SET amount = 5 WHERE {column} = $1;"))
+            .bind(value)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    assert!(!reenrich(&pool, &individual, &new_city, true).await);
+    assert_eq!(count(&pool, "SELECT amount::BIGINT FROM top_city WHERE city = $1", &old_city).await, 4);
+    assert_eq!(count(&pool, "SELECT amount::BIGINT FROM top_city WHERE city = $1", &new_city).await, 1);
+    assert_eq!(count(&pool, "SELECT amount::BIGINT FROM top_username WHERE username = $1", &username).await, 5);
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn time_buckets_follow_the_stats_timezone() {
+    let pool = connect().await;
+    let _counters = COUNTERS.lock().await;
+    // buckets in 2200, past anything the other tests write.
+    let hours = i64::from(u16::from_be_bytes(Uuid::new_v4().as_bytes()[..2].try_into().unwrap()));
+    let hour = 7_258_118_400_000 + hours * 3_600_000;
+    let day = hour - hour % 86_400_000;
+    sqlx::query("UPDATE time_bucket_alignment SET timezone = 'UTC';")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO top_hourly (timestamp, amount) VALUES ($1, 3);")
+        .bind(hour)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO top_daily (timestamp, amount) VALUES ($1, 5);")
+        .bind(day)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let amount = |table: &str, timestamp: i64| {
+        let query = format!("SELECT COALESCE(SUM(amount), 0)::BIGINT FROM {table} WHERE timestamp = $1");
+        let pool = pool.clone();
+        async move {
+            sqlx::query_scalar::<_, i64>(&query)
+                .bind(timestamp)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        }
+    };
+
+    let mut brute_system = system(&pool, Enrichment::default()).await;
+    assert_eq!(brute_system.align_time_buckets().await.unwrap(), None);
+
+    // UTC+5:30, every bucket starts half an hour earlier.
+    brute_system.stats_timezone = "Asia/Kolkata".into();
+    assert_eq!(brute_system.align_time_buckets().await.unwrap().as_deref(), Some("UTC"));
+    assert_eq!(brute_system.align_time_buckets().await.unwrap(), None);
+    assert_eq!(amount("top_hourly", hour).await, 0);
+    assert_eq!(amount("top_hourly", hour - 1_800_000).await, 3);
+    assert_eq!(amount("top_daily", day - 19_800_000).await, 5);
+
+    brute_system.stats_timezone = "UTC".into();
+    assert_eq!(brute_system.align_time_buckets().await.unwrap().as_deref(), Some("Asia/Kolkata"));
+    assert_eq!(amount("top_hourly", hour - 3_600_000).await, 3);
+    assert_eq!(amount("top_daily", day - 86_400_000).await, 5);
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn timeseries_fill_the_gaps() {
+    let pool = connect().await;
+    let sensor = unique();
+    let hour = 3_600_000;
+    let start = 7_258_118_400_000;
+    for minutes in [10, 20, 125] {
+        sqlx::query(
+            "INSERT INTO individual (id, username, password, ip, protocol, timestamp, sensor_id) VALUES ($1, 'root', 'root', $2, 'SSH', $3, $4);",
+        )
+        .bind(unique())
+        .bind(unique_ip())
+        .bind(start + minutes * 60_000)
+        .bind(&sensor)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+    let actor = system(&pool, Enrichment::default()).await.start();
+    let timeseries = |from: i64, to: i64| {
+        let request = Timeseries {
+            bucket: TimeBucket::Hour,
+            from,
+            to,
+            sensor: Some(sensor.clone()),
+        };
+        let actor = actor.clone();
+        async move {
+            actor
+                .send(request)
+                .await
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|point| (point.timestamp, point.amount))
+                .collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(
+        timeseries(start, start + 3 * hour).await,
+        [(start, 2), (start + hour, 0), (start + 2 * hour, 1)]
+    );
+    // the first bucket starts on the hour but only counts from `from` on.
+    assert_eq!(
+        timeseries(start + hour / 4, start + 2 * hour).await,
+        [(start, 1), (start + hour, 0)]
+    );
+}
+
+/// Counts only the attempts at `timestamp`.
+fn window<T>(table: T, timestamp: i64) -> RequestWithLimit<T> {
+    RequestWithLimit {
+        table,
+        limit: 100,
+        max_limit: 100,
+        sensor: None,
+        from: Some(timestamp),
+        to: Some(timestamp + 1),
+    }
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn windowed_stats_count_attempts_before_they_are_enriched() {
+    let pool = connect().await;
+    let city = unique();
+    let brute_system = system(
+        &pool,
+        Enrichment {
+            city: city.clone(),
+            country: "ZZ".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+    let reporter = brute_system.reporter();
+    let username = unique();
+    let individual = reporter
+        .start_report(Individual::new_short(username.clone(), unique(), unique_ip(), "SSH".to_string()))
+        .await
+        .unwrap();
+    let actor = brute_system.start();
+    let usernames = || async { actor.send(window(TopUsername::default(), individual.timestamp)).await.unwrap().unwrap() };
+    let cities = || async { actor.send(window(TopCity::default(), individual.timestamp)).await.unwrap().unwrap() };
+
+    // pending, only the location stats wait for the details.
+    assert!(usernames().await.iter().any(|row| *row.username() == username && *row.amount() == 1));
+    assert!(!cities().await.iter().any(|row| *row.city() == city));
+
+    reporter.finish_report(&individual).await.unwrap();
+    assert!(usernames().await.iter().any(|row| *row.username() == username && *row.amount() == 1));
+    assert!(cities().await.iter().any(|row| *row.city() == city));
+
+    // combinations keep the id they are counted under.
+    let (stored,): (String,) = sqlx::query_as("SELECT id FROM top_usr_pass_combo WHERE username = $1")
+        .bind(&username)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let combos = actor.send(window(TopUsrPassCombo::default(), individual.timestamp)).await.unwrap().unwrap();
+    assert!(combos.iter().any(|row| *row.username() == username && *row.id() == stored));
+}
+
+fn search(username: &str) -> SearchAttacks {
+    SearchAttacks {
+        limit: 10,
+        after: None,
+        ip: None,
+        cidr: None,
+        username: Some(username.to_string()),
+        password: None,
+        protocol: None,
+        country: None,
+        asn: None,
+        sensor: None,
+        from: None,
+        to: None,
+    }
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn attack_search_lists_attempts_before_they_are_enriched() {
+    let pool = connect().await;
+    let brute_system = system(
+        &pool,
+        Enrichment {
+            country: "ZZ".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+    let reporter = brute_system.reporter();
+    let username = unique();
+    let mut attempts = Vec::new();
+    for _ in 0..2 {
+        let attempt = Individual::new_short(username.clone(), unique(), unique_ipv6(), "SSH".to_string());
+        attempts.push(reporter.start_report(attempt).await.unwrap());
+    }
+    let actor = brute_system.start();
+
+    let page = actor.send(search(&username)).await.unwrap().unwrap();
+    assert_eq!(page.attacks.len(), 2);
+    assert!(page.attacks.iter().all(|attack| attack.enrichment_status() == "pending" && attack.country().is_none()));
+    assert!(page.next_cursor.is_none());
+
+    reporter.finish_report(&attempts[0]).await.unwrap();
+    let mut enriched = search(&username);
+    enriched.country = Some("ZZ".to_string());
+    let page = actor.send(enriched).await.unwrap().unwrap();
+    assert_eq!(page.attacks.len(), 1);
+    assert_eq!(page.attacks[0].id(), &attempts[0].id);
+    assert_eq!(page.attacks[0].enrichment_status(), "enriched");
+
+    // newest first, one at a time.
+    let mut first = search(&username);
+    first.limit = 1;
+    let page = actor.send(first).await.unwrap().unwrap();
+    assert_eq!(page.attacks.len(), 1);
+    let mut second = search(&username);
+    second.limit = 1;
+    second.after = page.next_cursor;
+    let next = actor.send(second).await.unwrap().unwrap();
+    assert_eq!(next.attacks.len(), 1);
+    assert_ne!(next.attacks[0].id(), page.attacks[0].id());
+    assert!(next.next_cursor.is_none());
+}
+
+fn counting_cache(pool: &Pool<Postgres>, enricher: &Counting, ttl: Duration) -> EnrichmentCache {
+    EnrichmentCache::new(
+        pool.clone(),
+        Box::new(enricher.clone()),
+        ttl,
+        NonZeroUsize::new(16).unwrap(),
+        None,
+    )
+}
+
+/// Marks the details of `ip` as fetched `age` ago, if there are any.
+async fn age_details(pool: &Pool<Postgres>, ip: IpAddr, age: Duration) {
+    sqlx::query("UPDATE ip_enrichment SET fetched_at = (EXTRACT(EPOCH FROM NOW()) * 1000)::BIGINT - $2 WHERE ip = $1")
+        .bind(ip.to_string())
+        .bind(age.as_millis() as i64)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn fresh_details_skip_the_enricher() {
+    let pool = connect().await;
+    let ttl = Duration::from_secs(3600);
+    let ip: IpAddr = unique_ip().parse().unwrap();
+    // an earlier run may have stored this IP.
+    age_details(&pool, ip, ttl * 2).await;
+    let enricher = Counting::default();
+
+    let cache = counting_cache(&pool, &enricher, ttl);
+    cache.lookup(ip).await.unwrap();
+    // from memory.
+    cache.lookup(ip).await.unwrap();
+    assert_eq!(enricher.calls(), 1);
+
+    // nothing in memory, from ip_enrichment.
+    counting_cache(&pool, &enricher, ttl).lookup(ip).await.unwrap();
+    assert_eq!(enricher.calls(), 1);
+
+    age_details(&pool, ip, ttl * 2).await;
+    let cache = counting_cache(&pool, &enricher, ttl);
+    cache.lookup(ip).await.unwrap();
+    cache.lookup(ip).await.unwrap();
+    assert_eq!(enricher.calls(), 2);
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn concurrent_lookups_share_one() {
+    let pool = connect().await;
+    let ttl = Duration::from_secs(3600);
+    let ip: IpAddr = unique_ip().parse().unwrap();
+    age_details(&pool, ip, ttl * 2).await;
+    let enricher = Counting::default();
+    let cache = Arc::new(counting_cache(&pool, &enricher, ttl));
+
+    let lookups: Vec<_> = (0..8)
+        .map(|_| {
+            let cache = cache.clone();
+            tokio::spawn(async move { cache.lookup(ip).await })
+        })
+        .collect();
+    for lookup in lookups {
+        assert_eq!(lookup.await.unwrap().unwrap().country, "ZZ");
+    }
+    assert_eq!(enricher.calls(), 1);
+}
+
+/// `enrichment_attempts`, how far `enrichment_retry_at` is from now and
+/// `enrichment_status`.
+async fn retry_state(pool: &Pool<Postgres>, id: &str) -> (i32, Option<i64>, String) {
+    sqlx::query_as(
+        "SELECT enrichment_attempts, enrichment_retry_at - (EXTRACT(EPOCH FROM NOW()) * 1000)::BIGINT, enrichment_status::TEXT FROM individual WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn failed_enrichments_back_off_and_give_up() {
+    let pool = connect().await;
+    // a re-enrich would look up this test's attempts again.
+    let _counters = COUNTERS.lock().await;
+    let down = Arc::new(AtomicBool::new(true));
+    let city = unique();
+    let enricher = Flaky {
+        down: down.clone(),
+        enrichment: Enrichment {
+            city: city.clone(),
+            country: "ZZ".to_string(),
+            ..Default::default()
+        },
+    };
+    let cache = EnrichmentCache::new(
+        pool.clone(),
+        Box::new(enricher),
+        Duration::ZERO,
+        NonZeroUsize::new(1).unwrap(),
+        None,
+    );
+    let brute_system = BruteSystem::new_brute(pool.clone(), cache, 1, "UTC", false).await;
+    let reporter = brute_system.reporter();
+    let report = |ip: String| reporter.start_report(Individual::new_short(unique(), unique(), ip, "SSH".to_string()));
+
+    // every failure waits twice as long as the one before.
+    let individual = report(unique_ipv6()).await.unwrap();
+    let mut waits = Vec::new();
+    for attempt in 1..=3 {
+        assert!(reporter.finish_report(&individual).await.is_err());
+        assert!(!reporter.enrichment_failed(&individual).await.unwrap());
+        let (attempts, wait, status) = retry_state(&pool, &individual.id).await;
+        assert_eq!((attempts, status.as_str()), (attempt, "pending"));
+        waits.push(wait.unwrap());
+    }
+    assert!((55_000..=60_000).contains(&waits[0]), "{:?}", waits);
+    assert!(waits[1] > waits[0] * 3 / 2 && waits[2] > waits[1] * 3 / 2, "{:?}", waits);
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM top_city WHERE city = $1", &city).await, 0);
+
+    // a retry that succeeds counts it after all.
+    down.store(false, Ordering::SeqCst);
+    sqlx::query("UPDATE individual SET enrichment_retry_at = 0 WHERE id = $1")
+        .bind(&individual.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let actor = brute_system.start();
+    assert!(actor.send(RetryEnrichment).await.unwrap().unwrap() >= 1);
+    actor.send(Drain).await.unwrap();
+    let (attempts, _, status) = retry_state(&pool, &individual.id).await;
+    assert_eq!((attempts, status.as_str()), (3, "enriched"));
+    // other due attempts in the database are retried with this city too.
+    let enriched = count(&pool, "SELECT COUNT(*) FROM processed_individual JOIN ip_enrichment USING (ip) WHERE city = $1", &city).await;
+    assert!(enriched >= 1);
+    assert_eq!(count(&pool, "SELECT amount::BIGINT FROM top_city WHERE city = $1", &city).await, enriched);
+
+    // given up on after the last try, and never retried again.
+    down.store(true, Ordering::SeqCst);
+    let individual = report(unique_ipv6()).await.unwrap();
+    for attempt in 1..=10 {
+        assert!(reporter.finish_report(&individual).await.is_err());
+        assert_eq!(reporter.enrichment_failed(&individual).await.unwrap(), attempt == 10);
+    }
+    let (attempts, _, status) = retry_state(&pool, &individual.id).await;
+    assert_eq!((attempts, status.as_str()), (10, "failed"));
+    assert!(!reporter.enrichment_failed(&individual).await.unwrap());
+    assert_eq!(retry_state(&pool, &individual.id).await.0, 10);
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn a_batch_fails_when_the_database_does() {
+    let pool = connect().await;
+    fail_on(&pool, "top_password", "password").await;
+    let sensors = Arc::new(SensorRegistry::default());
+    let state = AppState::new(
+        system(&pool, Enrichment::default()).await.start(),
+        Arc::new(ApiKeys::new(vec!["ingest:i".parse().unwrap()])),
+        IpFilter::new(Vec::new(), Vec::new()),
+        Arc::new(SignatureVerifier::new(Vec::new(), None, sensors.clone(), 300, false)),
+        sensors,
+        Limits {
+            max_limit: 100,
+            max_batch_size: 100,
+        },
+        Arc::from([]),
+    );
+    let app = test::init_service(configure_app(state)).await;
+    let batch = |passwords: &[&str]| -> Vec<serde_json::Value> {
+        passwords
+            .iter()
+            .map(|password| {
+                serde_json::json!({
+                    "username": unique(),
+                    "password": password,
+                    "ip_address": unique_ip(),
+                    "protocol": "SSH",
+                })
+            })
+            .collect()
+    };
+    let send = |attempts: &Vec<serde_json::Value>| {
+        test::TestRequest::post()
+            .uri("/brute/attack/add/batch")
+            .insert_header(("Authorization", "Bearer i"))
+            .set_json(attempts)
+            .to_request()
+    };
+
+    let too_long = "x".repeat(256);
+    let response = test::call_service(&app, send(&batch(&[&unique(), &too_long]))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let result: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(result, serde_json::json!({ "accepted": 1, "rejected": 1 }));
+
+    // nothing is stored, so the retry stores every attempt once.
+    let before = unique();
+    let attempts = batch(&[&before, FAIL, &unique()]);
+    let response = test::call_service(&app, send(&attempts)).await;
+    stop_failing(&pool, "top_password").await;
+    assert!(response.status().is_server_error());
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM individual WHERE password = $1", &before).await, 0);
+
+    let response = test::call_service(&app, send(&attempts)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let result: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(result, serde_json::json!({ "accepted": 3, "rejected": 0 }));
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM individual WHERE password = $1", &before).await, 1);
+}
+
+fn profile(network: IpNetwork) -> GetProfile {
+    GetProfile {
+        network,
+        limit: 10,
+        bucket: TimeBucket::Day,
+    }
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn ip_profiles_count_the_stored_attempts() {
+    let pool = connect().await;
+    let brute_system = system(
+        &pool,
+        Enrichment {
+            country: "ZZ".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+    let reporter = brute_system.reporter();
+    let ip = unique_ipv6();
+    let username = unique();
+    let mut attempts = Vec::new();
+    for _ in 0..2 {
+        let attempt = Individual::new_short(username.clone(), unique(), ip.clone(), "SSH".to_string());
+        attempts.push(reporter.start_report(attempt).await.unwrap());
+    }
+    reporter.finish_report(&attempts[0]).await.unwrap();
+    // attempts past the retention period only count all time.
+    sqlx::query("UPDATE top_ip SET amount = amount + 3 WHERE ip = $1;")
+        .bind(&ip)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let actor = brute_system.start();
+
+    let found = actor.send(profile(ip.parse().unwrap())).await.unwrap().unwrap();
+    assert_eq!((found.total_attempts, found.total_attempts_all_time), (2, 5));
+    assert_eq!(found.first_seen, Some(attempts[0].timestamp));
+    assert_eq!(found.last_seen, Some(attempts[1].timestamp));
+    assert_eq!(found.usernames.len(), 1);
+    assert_eq!((found.usernames[0].username(), *found.usernames[0].amount()), (&username, 2));
+    assert_eq!(found.activity.iter().map(|point| point.amount).sum::<i64>(), 2);
+    assert_eq!(found.enrichment.unwrap().country, "ZZ");
+    assert!(found.ips.is_none());
+
+    let missing = actor.send(profile(unique_ipv6().parse().unwrap())).await.unwrap();
+    assert!(matches!(missing, Err(BruteResponeError::NotFound(_))));
+}
+
+#[actix_rt::test]
+#[ignore = "needs DATABASE_URL"]
+async fn network_profiles_list_their_busiest_ips() {
+    let pool = connect().await;
+    let brute_system = system(&pool, Enrichment::default()).await;
+    let reporter = brute_system.reporter();
+    // a /112 of its own.
+    let prefix = unique_ipv6().rsplit_once(':').unwrap().0.to_string();
+    let (busy, quiet) = (format!("{}:1", prefix), format!("{}:2", prefix));
+    for ip in [&busy, &busy, &quiet] {
+        let attempt = Individual::new_short(unique(), unique(), ip.clone(), "SSH".to_string());
+        reporter.start_report(attempt).await.unwrap();
+    }
+    let actor = brute_system.start();
+
+    let network = IpNetwork::new(busy.parse().unwrap(), 112).unwrap();
+    let found = actor.send(profile(network)).await.unwrap().unwrap();
+    assert_eq!((found.total_attempts, found.total_attempts_all_time), (3, 3));
+    assert_eq!(found.protocols.len(), 1);
+    assert_eq!(*found.protocols[0].amount(), 3);
+    assert!(found.enrichment.is_none());
+    let ips: Vec<_> = found.ips.unwrap().iter().map(|ip| (ip.ip().clone(), *ip.amount())).collect();
+    assert_eq!(ips, [(busy, 2), (quiet, 1)]);
+}
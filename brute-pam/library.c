@@ -5,74 +5,120 @@
 #include "library.h"
 #include <stdio.h>
 #include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/socket.h>
+#include <sys/time.h>
+#include <sys/un.h>
 #include <security/pam_appl.h>
 #include <security/pam_modules.h>
-#include <curl/curl.h>
 
 #define BE_LOG_FILE "/var/log/brute_log.txt"
-#define BRUTE_BEARER_TOKEN "{TOKEN}"
-#define BRUTE_POST_URL "http://127.0.0.1:3000/brute/attack/add"
+// brute-daemon with LOCAL_SOCKET=true, override with `socket=/path` in the PAM config.
+#define BRUTE_SOCKET_PATH "/run/brute-daemon/brute.sock"
+#define BRUTE_FIELD_SIZE 256
+
+// a daemon that went away must not kill the process running PAM with SIGPIPE.
+#ifndef MSG_NOSIGNAL
+#define MSG_NOSIGNAL 0
+#endif
+
+// Escapes a string for a JSON value, truncating it to fit `out`.
+static void json_escape(const char *in, char *out, size_t size) {
+    size_t j = 0;
+    for (size_t i = 0; in != NULL && in[i] != '\0'; i++) {
+        unsigned char c = (unsigned char)in[i];
+        char escaped[7];
+        if (c == '"' || c == '\\') {
+            snprintf(escaped, sizeof(escaped), "\\%c", c);
+        } else if (c < 0x20) {
+            snprintf(escaped, sizeof(escaped), "\\u%04x", c);
+        } else {
+            escaped[0] = (char)c;
+            escaped[1] = '\0';
+        }
+        size_t length = strlen(escaped);
+        if (j + length >= size) {
+            break;
+        }
+        memcpy(out + j, escaped, length);
+        j += length;
+    }
+    out[j] = '\0';
+}
+
+// Sends one line to brute-daemon. Never blocks the login for more than a second.
+static int send_line(const char *path, const char *line) {
+    int fd = socket(AF_UNIX, SOCK_STREAM, 0);
+    if (fd < 0) {
+        return -1;
+    }
+
+    struct timeval timeout = { .tv_sec = 1, .tv_usec = 0 };
+    setsockopt(fd, SOL_SOCKET, SO_SNDTIMEO, &timeout, sizeof(timeout));
+#ifdef SO_NOSIGPIPE
+    int on = 1;
+    setsockopt(fd, SOL_SOCKET, SO_NOSIGPIPE, &on, sizeof(on));
+#endif
+
+    struct sockaddr_un address;
+    memset(&address, 0, sizeof(address));
+    address.sun_family = AF_UNIX;
+    strncpy(address.sun_path, path, sizeof(address.sun_path) - 1);
+
+    int result = -1;
+    if (connect(fd, (struct sockaddr *)&address, sizeof(address)) == 0) {
+        size_t length = strlen(line);
+        if (send(fd, line, length, MSG_NOSIGNAL) == (ssize_t)length) {
+            result = 0;
+        }
+    }
+    close(fd);
+    return result;
+}
 
 PAM_EXTERN int pam_sm_authenticate(pam_handle_t *pamh, int flags, int argc, const char **argv) {
-    char *username, *password, *protocol, *ip_address;
+    const char *username = NULL, *password = NULL, *protocol = NULL, *ip_address = NULL;
+    const char *socket_path = BRUTE_SOCKET_PATH;
+
+    for (int i = 0; i < argc; i++) {
+        if (strncmp(argv[i], "socket=", 7) == 0) {
+            socket_path = argv[i] + 7;
+        }
+    }
 
     // Retrieve user information
-    pam_get_item(pamh, PAM_USER, (void*)&username);
-    pam_get_item(pamh, PAM_AUTHTOK, (void*)&password);
-    pam_get_item(pamh, PAM_RHOST, (void*)&ip_address);
-    pam_get_item(pamh, PAM_SERVICE, (void*)&protocol);
-
-    // log any errors.
-    FILE *log_file = fopen(BE_LOG_FILE, "a");
-    if (log_file == NULL) {
-        fprintf(stderr, "Failed to open log file %s\n", BE_LOG_FILE);
+    pam_get_item(pamh, PAM_USER, (const void **)&username);
+    pam_get_item(pamh, PAM_AUTHTOK, (const void **)&password);
+    pam_get_item(pamh, PAM_RHOST, (const void **)&ip_address);
+    pam_get_item(pamh, PAM_SERVICE, (const void **)&protocol);
+
+    // nothing to report for local logins.
+    if (username == NULL || ip_address == NULL) {
         return PAM_SUCCESS;
     }
 
-    // Payload that is going to be sent
-    char json_payload[1024];
+    char e_username[BRUTE_FIELD_SIZE], e_password[BRUTE_FIELD_SIZE];
+    char e_protocol[BRUTE_FIELD_SIZE], e_ip_address[BRUTE_FIELD_SIZE];
+    json_escape(username, e_username, sizeof(e_username));
+    json_escape(password, e_password, sizeof(e_password));
+    json_escape(protocol, e_protocol, sizeof(e_protocol));
+    json_escape(ip_address, e_ip_address, sizeof(e_ip_address));
+
+    // Payload that is going to be sent, one JSON object per line.
+    char json_payload[1280];
     snprintf(json_payload, sizeof(json_payload),
-             "{\"username\":\"%s\",\"password\":\"%s\",\"protocol\":\"%s\",\"ip_address\":\"%s\"}",
-             username, password, protocol, ip_address);
-
-    // Initialize libcurl
-    CURL *curl;
-    CURLcode result;
-
-    curl_global_init(CURL_GLOBAL_DEFAULT);
-    curl = curl_easy_init();
-
-    if (curl) {
-        // Set URL and payload for HTTP POST request
-        curl_easy_setopt(curl, CURLOPT_URL, BRUTE_POST_URL);
-        curl_easy_setopt(curl, CURLOPT_POSTFIELDS, json_payload);
-
-        // Set headers for JSON content type and authorization
-        struct curl_slist *headers = NULL;
-        headers = curl_slist_append(headers, "Content-Type: application/json");
-        char auth_header[256];
-        snprintf(auth_header, sizeof(auth_header), "Authorization: Bearer %s", BRUTE_BEARER_TOKEN);
-        headers = curl_slist_append(headers, auth_header);
-        curl_easy_setopt(curl, CURLOPT_HTTPHEADER, headers);
-
-        // Perform HTTP POST request
-        result = curl_easy_perform(curl);
-        if (result != CURLE_OK) {
-            fprintf(log_file, "curl_easy_perform() failed: %s\n", curl_easy_strerror(result));
-        }
+             "{\"username\":\"%s\",\"password\":\"%s\",\"protocol\":\"%s\",\"ip_address\":\"%s\"}\n",
+             e_username, e_password, e_protocol, e_ip_address);
 
-        // Cleanup
-        curl_slist_free_all(headers);
-        curl_easy_cleanup(curl);
-    } else {
-        fprintf(log_file, "Failed to initialize curl.\n");
+    if (send_line(socket_path, json_payload) != 0) {
+        // log any errors.
+        FILE *log_file = fopen(BE_LOG_FILE, "a");
+        if (log_file != NULL) {
+            fprintf(log_file, "Failed to send attempt to %s\n", socket_path);
+            fclose(log_file);
+        }
     }
 
-    // Cleanup libcurl
-    curl_global_cleanup();
-
-    // Close log file
-    fclose(log_file);
-
     return PAM_SUCCESS;
-}
\ No newline at end of file
+}
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS processed_individual_sensor_idx;
+ALTER TABLE processed_individual DROP COLUMN IF EXISTS sensor_id;
+ALTER TABLE individual DROP COLUMN IF EXISTS sensor_id;
+DROP TABLE IF EXISTS sensor;
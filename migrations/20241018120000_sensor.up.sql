@@ -0,0 +1,14 @@
+-- Add up migration script here
+CREATE TABLE sensor (
+    id VARCHAR(64) PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE, -- sha256 of the token, the token itself is never stored
+    created_at BIGINT NOT NULL,
+    rotated_at BIGINT,
+    revoked_at BIGINT
+);
+
+-- NULL for attempts sent with the shared token or before sensors existed.
+ALTER TABLE individual ADD COLUMN sensor_id VARCHAR(64);
+ALTER TABLE processed_individual ADD COLUMN sensor_id VARCHAR(64);
+CREATE INDEX processed_individual_sensor_idx ON processed_individual (sensor_id, timestamp);
@@ -0,0 +1,49 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS processed_individual_ip_idx;
+ALTER TABLE processed_individual
+    DROP CONSTRAINT IF EXISTS processed_individual_ip_fkey,
+    ADD COLUMN hostname VARCHAR(255),
+    ADD COLUMN city VARCHAR(255),
+    ADD COLUMN region VARCHAR(255),
+    ADD COLUMN timezone VARCHAR(255),
+    ADD COLUMN country VARCHAR(3),
+    ADD COLUMN loc VARCHAR(255),
+    ADD COLUMN org VARCHAR(255),
+    ADD COLUMN postal VARCHAR(20),
+    ADD COLUMN asn VARCHAR(50),
+    ADD COLUMN asn_name VARCHAR(255),
+    ADD COLUMN asn_domain VARCHAR(255),
+    ADD COLUMN asn_route VARCHAR(255),
+    ADD COLUMN asn_type VARCHAR(50),
+    ADD COLUMN company_name VARCHAR(255),
+    ADD COLUMN company_domain VARCHAR(255),
+    ADD COLUMN company_type VARCHAR(50),
+    ADD COLUMN vpn BOOLEAN,
+    ADD COLUMN proxy BOOLEAN,
+    ADD COLUMN tor BOOLEAN,
+    ADD COLUMN relay BOOLEAN,
+    ADD COLUMN hosting BOOLEAN,
+    ADD COLUMN service VARCHAR(255),
+    ADD COLUMN abuse_address VARCHAR(255),
+    ADD COLUMN abuse_country VARCHAR(255),
+    ADD COLUMN abuse_email VARCHAR(255),
+    ADD COLUMN abuse_name VARCHAR(255),
+    ADD COLUMN abuse_network VARCHAR(255),
+    ADD COLUMN abuse_phone VARCHAR(50),
+    ADD COLUMN domain_ip VARCHAR(255),
+    ADD COLUMN domain_total BIGINT,
+    ADD COLUMN domains TEXT[];
+
+UPDATE processed_individual p SET
+    hostname = e.hostname, city = e.city, region = e.region, timezone = e.timezone,
+    country = e.country, loc = e.loc, org = e.org, postal = e.postal,
+    asn = e.asn, asn_name = e.asn_name, asn_domain = e.asn_domain, asn_route = e.asn_route, asn_type = e.asn_type,
+    company_name = e.company_name, company_domain = e.company_domain, company_type = e.company_type,
+    vpn = e.vpn, proxy = e.proxy, tor = e.tor, relay = e.relay, hosting = e.hosting, service = e.service,
+    abuse_address = e.abuse_address, abuse_country = e.abuse_country, abuse_email = e.abuse_email,
+    abuse_name = e.abuse_name, abuse_network = e.abuse_network, abuse_phone = e.abuse_phone,
+    domain_ip = e.domain_ip, domain_total = e.domain_total, domains = e.domains
+FROM ip_enrichment e
+WHERE e.ip = p.ip;
+
+DROP TABLE IF EXISTS ip_enrichment;
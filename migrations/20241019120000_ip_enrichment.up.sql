@@ -0,0 +1,100 @@
+-- Add up migration script here
+CREATE TABLE ip_enrichment (
+    ip VARCHAR(39) PRIMARY KEY,
+    hostname VARCHAR(255),
+    -- empty rather than NULL, they key the location counters.
+    city VARCHAR(255) NOT NULL DEFAULT '',
+    region VARCHAR(255) NOT NULL DEFAULT '',
+    timezone VARCHAR(255) NOT NULL DEFAULT '',
+    country VARCHAR(3) NOT NULL DEFAULT '',
+    loc VARCHAR(255) NOT NULL DEFAULT '',
+    org VARCHAR(255),
+    postal VARCHAR(20),
+    -- ASN fields
+    asn VARCHAR(50),
+    asn_name VARCHAR(255),
+    asn_domain VARCHAR(255),
+    asn_route VARCHAR(255),
+    asn_type VARCHAR(50),
+    -- Company fields
+    company_name VARCHAR(255),
+    company_domain VARCHAR(255),
+    company_type VARCHAR(50),
+    -- Privacy fields
+    vpn BOOLEAN,
+    proxy BOOLEAN,
+    tor BOOLEAN,
+    relay BOOLEAN,
+    hosting BOOLEAN,
+    service VARCHAR(255),
+    -- Abuse fields
+    abuse_address VARCHAR(255),
+    abuse_country VARCHAR(255),
+    abuse_email VARCHAR(255),
+    abuse_name VARCHAR(255),
+    abuse_network VARCHAR(255),
+    abuse_phone VARCHAR(50),
+    -- Domain fields
+    domain_ip VARCHAR(255),
+    domain_total BIGINT,
+    domains TEXT[],
+    provider VARCHAR(32) NOT NULL,
+    fetched_at BIGINT NOT NULL
+);
+
+-- the latest details of every IP seen so far.
+INSERT INTO ip_enrichment (
+    ip, hostname, city, region, timezone, country, loc, org, postal,
+    asn, asn_name, asn_domain, asn_route, asn_type,
+    company_name, company_domain, company_type,
+    vpn, proxy, tor, relay, hosting, service,
+    abuse_address, abuse_country, abuse_email, abuse_name, abuse_network, abuse_phone,
+    domain_ip, domain_total, domains, provider, fetched_at
+)
+SELECT DISTINCT ON (ip)
+    ip, hostname, COALESCE(city, ''), COALESCE(region, ''), COALESCE(timezone, ''),
+    COALESCE(country, ''), COALESCE(loc, ''), org, postal,
+    asn, asn_name, asn_domain, asn_route, asn_type,
+    company_name, company_domain, company_type,
+    vpn, proxy, tor, relay, hosting, service,
+    abuse_address, abuse_country, abuse_email, abuse_name, abuse_network, abuse_phone,
+    domain_ip, domain_total, domains, 'ipinfo', timestamp
+FROM processed_individual
+ORDER BY ip, timestamp DESC;
+
+-- attempts now point at their IP's details instead of carrying a copy.
+ALTER TABLE processed_individual
+    DROP COLUMN hostname,
+    DROP COLUMN city,
+    DROP COLUMN region,
+    DROP COLUMN timezone,
+    DROP COLUMN country,
+    DROP COLUMN loc,
+    DROP COLUMN org,
+    DROP COLUMN postal,
+    DROP COLUMN asn,
+    DROP COLUMN asn_name,
+    DROP COLUMN asn_domain,
+    DROP COLUMN asn_route,
+    DROP COLUMN asn_type,
+    DROP COLUMN company_name,
+    DROP COLUMN company_domain,
+    DROP COLUMN company_type,
+    DROP COLUMN vpn,
+    DROP COLUMN proxy,
+    DROP COLUMN tor,
+    DROP COLUMN relay,
+    DROP COLUMN hosting,
+    DROP COLUMN service,
+    DROP COLUMN abuse_address,
+    DROP COLUMN abuse_country,
+    DROP COLUMN abuse_email,
+    DROP COLUMN abuse_name,
+    DROP COLUMN abuse_network,
+    DROP COLUMN abuse_phone,
+    DROP COLUMN domain_ip,
+    DROP COLUMN domain_total,
+    DROP COLUMN domains,
+    ADD CONSTRAINT processed_individual_ip_fkey FOREIGN KEY (ip) REFERENCES ip_enrichment (ip);
+
+CREATE INDEX processed_individual_ip_idx ON processed_individual (ip, timestamp);
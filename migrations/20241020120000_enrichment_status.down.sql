@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS individual_enrichment_retry_idx;
+ALTER TABLE individual
+    DROP COLUMN IF EXISTS enrichment_retry_at,
+    DROP COLUMN IF EXISTS enrichment_attempts,
+    DROP COLUMN IF EXISTS enrichment_status;
@@ -0,0 +1,19 @@
+-- Add up migration script here
+-- pending until the attempt has its location and network details, failed
+-- once its retries ran out.
+ALTER TABLE individual
+    ADD COLUMN enrichment_status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    ADD COLUMN enrichment_attempts INTEGER NOT NULL DEFAULT 0,
+    -- when a pending attempt is queued again, also covers attempts that
+    -- were still queued when brute-http stopped.
+    ADD COLUMN enrichment_retry_at BIGINT;
+
+UPDATE individual SET enrichment_status = 'enriched'
+WHERE id IN (SELECT id FROM processed_individual);
+
+-- attempts that were lost to a failed lookup before this are retried.
+UPDATE individual SET enrichment_retry_at = 0
+WHERE enrichment_status = 'pending';
+
+CREATE INDEX individual_enrichment_retry_idx ON individual (enrichment_retry_at)
+WHERE enrichment_status = 'pending';
@@ -0,0 +1,18 @@
+-- Add up migration script here
+CREATE TABLE reenrich_job (
+    id VARCHAR(32) PRIMARY KEY,
+    -- attempts whose timestamp is in [range_from, range_to) get their IP looked up again.
+    range_from BIGINT NOT NULL,
+    range_to BIGINT NOT NULL,
+    -- enriching, rebuilding, done or failed.
+    status VARCHAR(16) NOT NULL,
+    total_ips INTEGER NOT NULL,
+    enriched_ips INTEGER NOT NULL DEFAULT 0,
+    failed_ips INTEGER NOT NULL DEFAULT 0,
+    -- IPs are looked up in order, a resumed job continues after this one.
+    last_ip VARCHAR(39) NOT NULL DEFAULT '',
+    error TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    finished_at BIGINT
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE ip_enrichment
+    DROP COLUMN IF EXISTS rdns_confirmed,
+    DROP COLUMN IF EXISTS rdns;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- PTR name of the IP and whether that name resolves back to the IP.
+ALTER TABLE ip_enrichment
+    ADD COLUMN rdns VARCHAR(255),
+    ADD COLUMN rdns_confirmed BOOLEAN;
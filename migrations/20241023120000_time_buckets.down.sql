@@ -0,0 +1,2 @@
+-- Add down migration script here
+-- the merged buckets can't be split again, they stay aligned.
@@ -0,0 +1,23 @@
+-- Add up migration script here
+-- buckets used to start at the first attempt in them, move every row to the
+-- start of its calendar bucket in UTC and merge the rows that end up together.
+
+WITH old AS (DELETE FROM top_hourly RETURNING timestamp, amount)
+INSERT INTO top_hourly (timestamp, amount)
+SELECT (EXTRACT(EPOCH FROM date_trunc('hour', to_timestamp(timestamp / 1000.0), 'UTC')) * 1000)::BIGINT, SUM(amount)::INTEGER
+FROM old GROUP BY 1;
+
+WITH old AS (DELETE FROM top_daily RETURNING timestamp, amount)
+INSERT INTO top_daily (timestamp, amount)
+SELECT (EXTRACT(EPOCH FROM date_trunc('day', to_timestamp(timestamp / 1000.0), 'UTC')) * 1000)::BIGINT, SUM(amount)::INTEGER
+FROM old GROUP BY 1;
+
+WITH old AS (DELETE FROM top_weekly RETURNING timestamp, amount)
+INSERT INTO top_weekly (timestamp, amount)
+SELECT (EXTRACT(EPOCH FROM date_trunc('week', to_timestamp(timestamp / 1000.0), 'UTC')) * 1000)::BIGINT, SUM(amount)::INTEGER
+FROM old GROUP BY 1;
+
+WITH old AS (DELETE FROM top_yearly RETURNING timestamp, amount)
+INSERT INTO top_yearly (timestamp, amount)
+SELECT (EXTRACT(EPOCH FROM date_trunc('year', to_timestamp(timestamp / 1000.0), 'UTC')) * 1000)::BIGINT, SUM(amount)::INTEGER
+FROM old GROUP BY 1;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS individual_sensor_idx;
+DROP INDEX IF EXISTS individual_timestamp_idx;
+DROP INDEX IF EXISTS processed_individual_timestamp_idx;
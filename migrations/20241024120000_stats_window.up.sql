@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- windowed stats are counted from the attempts in the range.
+CREATE INDEX processed_individual_timestamp_idx ON processed_individual (timestamp);
+CREATE INDEX individual_timestamp_idx ON individual (timestamp);
+CREATE INDEX individual_sensor_idx ON individual (sensor_id, timestamp);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS processed_individual_timestamp_id_idx;
+CREATE INDEX processed_individual_timestamp_idx ON processed_individual (timestamp);
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- /stats/attack pages through attempts by (timestamp, id).
+DROP INDEX IF EXISTS processed_individual_timestamp_idx;
+CREATE INDEX processed_individual_timestamp_id_idx ON processed_individual (timestamp, id);
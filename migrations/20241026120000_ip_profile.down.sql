@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS top_ip_inet_idx;
+DROP INDEX IF EXISTS individual_ip_inet_idx;
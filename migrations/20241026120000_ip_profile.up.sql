@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- the ip and network profiles match attempts by network.
+CREATE INDEX individual_ip_inet_idx ON individual USING gist ((ip::INET) inet_ops);
+CREATE INDEX top_ip_inet_idx ON top_ip USING gist ((ip::INET) inet_ops);
@@ -0,0 +1,10 @@
+-- Add up migration script here
+-- the timezone the time buckets are aligned to, the time_buckets migration
+-- aligned them to UTC. brute-http realigns them at startup when
+-- stats_timezone is another one.
+CREATE TABLE time_bucket_alignment (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    timezone TEXT NOT NULL
+);
+
+INSERT INTO time_bucket_alignment (timezone) VALUES ('UTC');
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS individual_timestamp_id_idx;
+CREATE INDEX individual_timestamp_idx ON individual (timestamp);
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- /stats/attack pages through every stored attempt by (timestamp, id).
+DROP INDEX IF EXISTS individual_timestamp_idx;
+CREATE INDEX individual_timestamp_id_idx ON individual (timestamp, id);
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE reenrich_job DROP COLUMN IF EXISTS counters_rebuilt;
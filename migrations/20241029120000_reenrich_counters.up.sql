@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- whether a finished job rebuilt every counter, jobs that ran while
+-- attempts were pruned only moved the location counters.
+ALTER TABLE reenrich_job ADD COLUMN counters_rebuilt BOOLEAN;
+UPDATE reenrich_job SET counters_rebuilt = TRUE WHERE status = 'done';